@@ -1,13 +1,16 @@
-use crate::config::{BarConfig, WaymonWidgetConfig};
-use crate::stats::AllStats;
+use crate::config::{BarConfig, Side, WaymonWidgetConfig, WidgetConfig};
+use crate::history::{RestoredBar, SavedHistory, SavedWidget};
+use crate::stats::{AllStats, UpdateSchedule};
+use crate::widgets::timeseries::TimeAxis;
 use crate::widgets::Widget;
 use gtk::gdk;
+use gtk::glib;
 use gtk::prelude::*;
 use gtk::{Orientation, Window};
 use gtk4_layer_shell::{Edge, Layer, LayerShell};
 use std::cell::RefCell;
 use std::rc::Rc;
-use tracing::{debug, error};
+use tracing::debug;
 
 /// A Bar is a single waymon window, containing a set of chart widgets.
 ///
@@ -17,36 +20,148 @@ pub struct Bar {
     pub window: Window,
     pub monitor: gdk::Monitor,
     box_widget: gtk::Box,
+    // The name of the bar configuration being shown
+    name: String,
+    side: Side,
+    width: u32,
     // It's sort of annoying that we have to store each widget in an Rc<RefCell>, given that the
     // entire Waymon structure itself is also in a Rc<RefCell> and only one operation ever happens
     // at a time.  It would be nicer if we could have only the single top-level Rc<RefCell>, and
     // each callback only had to try borrowing from that.  Unfortunately, there doesn't seem to be
     // a good way to express this currently with Rust.  We pay the cost of doing some extra
     // unnecessary runtime borrow checks as a result.
-    widgets: Vec<Rc<RefCell<dyn Widget>>>,
+    widgets: Vec<BarWidget>,
+}
+
+/// A widget in a Bar, along with the configuration that was used to create it.
+///
+/// We keep the configuration around so that we can tell which widgets need to be re-created when
+/// the bar configuration changes.
+struct BarWidget {
+    config: WidgetConfig,
+    // Tells us when the widget's stats have new data
+    schedule: Rc<UpdateSchedule>,
+    // This is shared with the scroll handler, which changes the selected time window
+    time_axis: Rc<RefCell<TimeAxis>>,
+    widget: Rc<RefCell<dyn Widget>>,
+}
+
+impl BarWidget {
+    fn new(bar_config: &BarConfig, config: &WidgetConfig, all_stats: &mut AllStats) -> Self {
+        let schedule = UpdateSchedule::new(bar_config.widget_interval(config));
+        let time_axis = bar_config.time_axis(config);
+        let widget = all_stats.with_schedule(&schedule, |all_stats| {
+            config.kind.create_widget(all_stats, &time_axis)
+        });
+        let bw = Self {
+            config: config.clone(),
+            schedule,
+            time_axis: Rc::new(RefCell::new(time_axis)),
+            widget,
+        };
+        if bw.time_axis.borrow().windows.len() > 1 {
+            bw.add_scroll_handler();
+        }
+        bw
+    }
+
+    /// Let the user switch between the widget's time windows by scrolling over it.
+    ///
+    /// Scrolling down switches to the next window in the list, and scrolling up to the previous
+    /// one.
+    fn add_scroll_handler(&self) {
+        let scroll = gtk::EventControllerScroll::new(
+            gtk::EventControllerScrollFlags::VERTICAL | gtk::EventControllerScrollFlags::DISCRETE,
+        );
+        let weak_widget = Rc::downgrade(&self.widget);
+        let time_axis = self.time_axis.clone();
+        scroll.connect_scroll(move |_, _dx, dy| {
+            let Some(widget) = weak_widget.upgrade() else {
+                return glib::Propagation::Proceed;
+            };
+            let mut time_axis = time_axis.borrow_mut();
+            if dy == 0.0 || !time_axis.select_next(dy > 0.0) {
+                return glib::Propagation::Proceed;
+            }
+            debug!(
+                "switching widget time window to {:?}",
+                time_axis.windows[time_axis.selected]
+            );
+            let mut widget = widget.borrow_mut();
+            widget.set_time_axis(&time_axis);
+            queue_draw_all(widget.gtk_widget().upcast_ref());
+            glib::Propagation::Stop
+        });
+        self.widget.borrow().gtk_widget().add_controller(scroll);
+    }
+
+    /// Update the widget's time axis, if the bar's width or gridline settings have changed.
+    fn update_time_axis(&mut self, bar_config: &BarConfig) {
+        let mut new_axis = bar_config.time_axis(&self.config);
+        let mut time_axis = self.time_axis.borrow_mut();
+        // Keep whichever window the user has selected
+        if new_axis.windows == time_axis.windows {
+            new_axis.selected = time_axis.selected;
+        }
+        if new_axis != *time_axis {
+            self.widget.borrow_mut().set_time_axis(&new_axis);
+            *time_axis = new_axis;
+        }
+    }
+
+    /// Identifies the widget when saving and restoring its history.
+    fn history_id(&self) -> String {
+        self.config.history_id()
+    }
+
+    /// Returns true if this widget was created from the specified configuration.
+    fn matches(&self, bar_config: &BarConfig, config: &WidgetConfig) -> bool {
+        self.config == *config && self.schedule.interval() == bar_config.widget_interval(config)
+    }
 }
 
 impl Bar {
-    pub fn new(monitor: gdk::Monitor, config: &BarConfig, all_stats: &mut AllStats) -> Self {
+    pub fn new(
+        monitor: gdk::Monitor,
+        config: &BarConfig,
+        all_stats: &mut AllStats,
+        history: Option<&SavedHistory>,
+    ) -> Self {
         let (window, box_widget) = Self::create_window(&monitor, config);
         let mut bar = Self {
             window,
             monitor,
             box_widget,
+            name: config.name.clone(),
+            side: config.side,
+            width: config.width,
             widgets: Vec::new(),
         };
 
         // Add the widgets
-        bar.add_widgets(config, config.width, all_stats);
+        let mut restored = history
+            .map(|h| h.restore_bar(&config.name))
+            .unwrap_or_default();
+        bar.add_widgets(config, all_stats, &mut restored);
         // Display the window
         bar.window.present();
 
         bar
     }
 
-    pub fn ensure_config(&mut self, _config: &BarConfig) {
-        // TODO: reconfigure the bar if needed
-        error!("TODO: update bar config");
+    pub fn ensure_config(&mut self, config: &BarConfig, all_stats: &mut AllStats) {
+        if config.side != self.side || config.width != self.width {
+            debug!(
+                "updating bar side/width from {:?}/{} to {:?}/{}",
+                self.side, self.width, config.side, config.width
+            );
+            Self::configure_side(&self.window, &self.box_widget, config);
+            self.side = config.side;
+            self.width = config.width;
+        }
+
+        self.name = config.name.clone();
+        self.update_widgets(config, all_stats);
     }
 
     fn create_window(monitor: &gdk::Monitor, config: &BarConfig) -> (Window, gtk::Box) {
@@ -62,33 +177,52 @@ impl Bar {
         // Push other windows out of the way
         window.auto_exclusive_zone_enable();
 
+        let box_widget = gtk::Box::new(Orientation::Vertical, /*spacing*/ 0);
+        Self::configure_side(&window, &box_widget, config);
+
+        box_widget.add_css_class("background");
+        window.set_child(Some(&box_widget));
+
+        (window, box_widget)
+    }
+
+    /// Anchor the window to the configured side of the screen, and set its size.
+    ///
+    /// This is used both when initially creating the window, and when updating the configuration
+    /// of an existing window.
+    fn configure_side(window: &Window, box_widget: &gtk::Box, config: &BarConfig) {
+        // Clear any existing anchors before applying the new ones
+        for edge in [Edge::Left, Edge::Right, Edge::Top, Edge::Bottom] {
+            window.set_anchor(edge, false);
+        }
+
         let box_orientation = match config.side {
-            crate::config::Side::Left => {
+            Side::Left => {
                 window.set_anchor(Edge::Left, true);
                 window.set_anchor(Edge::Top, true);
                 window.set_anchor(Edge::Bottom, true);
                 Orientation::Vertical
             }
-            crate::config::Side::Right => {
+            Side::Right => {
                 window.set_anchor(Edge::Right, true);
                 window.set_anchor(Edge::Top, true);
                 window.set_anchor(Edge::Bottom, true);
                 Orientation::Vertical
             }
-            crate::config::Side::Top => {
+            Side::Top => {
                 window.set_anchor(Edge::Top, true);
                 window.set_anchor(Edge::Left, true);
                 window.set_anchor(Edge::Right, true);
                 Orientation::Horizontal
             }
-            crate::config::Side::Bottom => {
+            Side::Bottom => {
                 window.set_anchor(Edge::Bottom, true);
                 window.set_anchor(Edge::Left, true);
                 window.set_anchor(Edge::Right, true);
                 Orientation::Horizontal
             }
         };
-        let box_widget = gtk::Box::new(box_orientation, /*spacing*/ 0);
+        box_widget.set_orientation(box_orientation);
 
         if box_orientation == Orientation::Vertical {
             window.set_default_size(config.width as i32, -1);
@@ -99,32 +233,115 @@ impl Bar {
             // here would be fine, and then the widgets should cause the bar to expand?
             window.set_default_size(-1, config.width as i32);
         }
+    }
 
-        box_widget.add_css_class("background");
-        window.set_child(Some(&box_widget));
-
-        (window, box_widget)
+    fn add_widgets(
+        &mut self,
+        config: &BarConfig,
+        all_stats: &mut AllStats,
+        restored: &mut RestoredBar,
+    ) {
+        let container = &self.box_widget;
+        for widget_config in &config.widgets {
+            let bw = BarWidget::new(config, widget_config, all_stats);
+            if let Some(history) = restored.take(&bw.history_id()) {
+                bw.widget.borrow_mut().restore_history(history);
+            }
+            container.append(bw.widget.borrow().gtk_widget());
+            self.widgets.push(bw);
+        }
     }
 
-    fn add_widgets(&mut self, config: &BarConfig, width: u32, all_stats: &mut AllStats) {
-        // Our charts generally display one pixel per data point.
-        // Store history for exactly as many data points as we have pixels wide.
-        let history_length: usize = width as usize;
+    /// Update the list of widgets to match the specified configuration.
+    ///
+    /// Existing widgets whose configuration has not changed are preserved (even if they have
+    /// moved to a different position in the bar), so that they keep their chart history.
+    fn update_widgets(&mut self, config: &BarConfig, all_stats: &mut AllStats) {
+        for bw in &mut self.widgets {
+            bw.update_time_axis(config);
+        }
+
+        let unchanged = self.widgets.len() == config.widgets.len()
+            && self
+                .widgets
+                .iter()
+                .zip(&config.widgets)
+                .all(|(bw, wc)| bw.matches(config, wc));
+        if unchanged {
+            return;
+        }
+        debug!("updating bar widgets");
+
+        let mut old_widgets: Vec<Option<BarWidget>> = self.widgets.drain(..).map(Some).collect();
+        for bw in old_widgets.iter().flatten() {
+            self.box_widget.remove(bw.widget.borrow().gtk_widget());
+        }
 
-        let container = &self.box_widget;
         for widget_config in &config.widgets {
-            let widget = widget_config.create_widget(all_stats, history_length);
-            container.append(widget.borrow().gtk_widget());
-            self.widgets.push(widget);
+            // Reuse an existing widget with the same configuration, if there is one
+            let existing = old_widgets
+                .iter_mut()
+                .find(|bw| {
+                    bw.as_ref()
+                        .is_some_and(|bw| bw.matches(config, widget_config))
+                })
+                .and_then(|bw| bw.take());
+            let bw = match existing {
+                Some(bw) => bw,
+                None => BarWidget::new(config, widget_config, all_stats),
+            };
+            self.box_widget.append(bw.widget.borrow().gtk_widget());
+            self.widgets.push(bw);
         }
     }
 
     pub fn update(&self) {
-        for w_rc in &self.widgets {
-            let mut w = w_rc.borrow_mut();
-            w.update();
+        for bw in &self.widgets {
+            let mut w = bw.widget.borrow_mut();
+            // Only update widgets when their stats have new data, so that each widget adds chart
+            // points at its own interval.
+            if bw.schedule.take_updated() {
+                w.update();
+            }
+
+            // Dim widgets whose stats are stale.  The "stale" CSS class can be used to style
+            // them differently.
+            let stale = w.is_stale();
+            let gtk_widget = w.gtk_widget();
+            if stale != gtk_widget.has_css_class("stale") {
+                if stale {
+                    gtk_widget.add_css_class("stale");
+                    gtk_widget.set_opacity(0.5);
+                } else {
+                    gtk_widget.remove_css_class("stale");
+                    gtk_widget.set_opacity(1.0);
+                }
+            }
         }
     }
+
+    /// Get the chart history of each widget, to be saved across restarts.
+    pub fn save_history(&self) -> (String, Vec<SavedWidget>) {
+        let widgets = self
+            .widgets
+            .iter()
+            .map(|bw| SavedWidget {
+                id: bw.history_id(),
+                charts: bw.widget.borrow().save_history(),
+            })
+            .collect();
+        (self.name.clone(), widgets)
+    }
+}
+
+/// Queue a redraw of a widget and all of its descendants.
+fn queue_draw_all(widget: &gtk::Widget) {
+    widget.queue_draw();
+    let mut child = widget.first_child();
+    while let Some(c) = child {
+        queue_draw_all(&c);
+        child = c.next_sibling();
+    }
 }
 
 impl Drop for Bar {
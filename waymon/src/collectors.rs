@@ -1,5 +1,12 @@
+pub mod cgroup;
 pub mod diskstats;
+pub mod fs_usage;
 pub mod meminfo;
 pub mod net;
+pub mod power_supply;
+pub mod processes;
 pub mod procstat;
 pub mod pressure;
+pub mod route;
+pub mod temperature;
+pub mod vmstat;
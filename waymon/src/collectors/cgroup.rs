@@ -0,0 +1,253 @@
+use crate::collectors::pressure::CpuPressure;
+use crate::read::{read_to_string_with_limit, system_path};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use thiserror::Error;
+use tracing::error;
+
+pub const CGROUP_PATH: &str = "/sys/fs/cgroup";
+
+/// Normalize a configured cgroup path to be relative to the cgroup filesystem root.
+///
+/// This accepts paths like "user.slice", "/user.slice", or "/sys/fs/cgroup/user.slice".
+pub fn relative_cgroup_path(path: &str) -> String {
+    let path = path.strip_prefix(CGROUP_PATH).unwrap_or(path);
+    path.trim_matches('/').to_string()
+}
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("invalid integer")]
+    ParseIntError(#[from] std::num::ParseIntError),
+    #[error("missing value")]
+    MissingValue,
+}
+
+/// The resource usage of a single cgroup.
+///
+/// Controllers that are not enabled for the cgroup do not create their files, so their values
+/// are left as 0.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Cgroup {
+    // The cgroup's directory name, or its full path relative to the cgroup root for the cgroup
+    // being watched
+    pub name: String,
+    // The total CPU time used, from cpu.stat
+    pub cpu_usage_usec: u64,
+    // The total memory used, from memory.current
+    pub memory_current: u64,
+    // Anonymous and page cache memory, from memory.stat
+    pub memory_anon: u64,
+    pub memory_file: u64,
+    // The bytes read and written on all devices, from io.stat
+    pub io_read_bytes: u64,
+    pub io_write_bytes: u64,
+    // The CPU pressure, from cpu.pressure.  This is None if PSI is disabled.
+    pub cpu_pressure: Option<CpuPressure>,
+}
+
+impl Cgroup {
+    fn read_from(dir: &Path, name: String) -> Result<Cgroup, std::io::Error> {
+        // cpu.stat is always present, even if the cpu controller is not enabled, so it is used
+        // to check that the cgroup exists.
+        let cpu_stat = read_to_string_with_limit(&dir.join("cpu.stat"), 4096)?;
+        let mut cgroup = Cgroup {
+            name,
+            ..Default::default()
+        };
+        cgroup.cpu_usage_usec = parse_keyed_value(&cpu_stat, "usage_usec").unwrap_or(0);
+        if let Some(data) = read_optional(&dir.join("memory.current"), 4096) {
+            cgroup.memory_current = log_parse_error(dir, data.trim().parse().map_err(Into::into));
+        }
+        if let Some(data) = read_optional(&dir.join("memory.stat"), 64 * 1024) {
+            cgroup.memory_anon = parse_keyed_value(&data, "anon").unwrap_or(0);
+            cgroup.memory_file = parse_keyed_value(&data, "file").unwrap_or(0);
+        }
+        if let Some(data) = read_optional(&dir.join("io.stat"), 64 * 1024) {
+            (cgroup.io_read_bytes, cgroup.io_write_bytes) =
+                log_parse_error(dir, parse_io_stat(&data));
+        }
+        if let Some(data) = read_optional(&dir.join("cpu.pressure"), 4096) {
+            cgroup.cpu_pressure = CpuPressure::parse(&data).ok();
+        }
+        Ok(cgroup)
+    }
+}
+
+/// Read a file that may not exist, because the controller that provides it is not enabled.
+fn read_optional(path: &Path, limit: u64) -> Option<String> {
+    read_to_string_with_limit(path, limit).ok()
+}
+
+fn log_parse_error<T: Default>(dir: &Path, result: Result<T, ParseError>) -> T {
+    result.unwrap_or_else(|e| {
+        static PARSE_ERROR_LOG: std::sync::Once = std::sync::Once::new();
+        PARSE_ERROR_LOG.call_once(|| {
+            error!("{}: {:?}", dir.display(), e);
+        });
+        T::default()
+    })
+}
+
+/// Look up a value in a flat keyed file such as cpu.stat or memory.stat, with one
+/// "<key> <value>" pair per line.
+fn parse_keyed_value(data: &str, key: &str) -> Option<u64> {
+    data.lines().find_map(|line| {
+        let (k, v) = line.split_once(' ')?;
+        if k == key {
+            v.trim().parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+/// Sum the bytes read and written over all devices in io.stat.
+///
+/// Each line has the form "<major>:<minor> rbytes=N wbytes=N rios=N wios=N dbytes=N dios=N".
+fn parse_io_stat(data: &str) -> Result<(u64, u64), ParseError> {
+    let mut read_bytes = 0;
+    let mut write_bytes = 0;
+    for line in data.lines() {
+        for field in line.split_ascii_whitespace().skip(1) {
+            let (key, value) = field.split_once('=').ok_or(ParseError::MissingValue)?;
+            match key {
+                "rbytes" => read_bytes += value.parse::<u64>()?,
+                "wbytes" => write_bytes += value.parse::<u64>()?,
+                _ => {}
+            }
+        }
+    }
+    Ok((read_bytes, write_bytes))
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct CgroupStats {
+    // The cgroup being watched
+    pub cgroup: Cgroup,
+    // Its child cgroups, sorted by name
+    pub children: Vec<Cgroup>,
+}
+
+impl CgroupStats {
+    pub fn read(path: &str) -> Result<Self, std::io::Error> {
+        Self::read_from(&system_path(CGROUP_PATH), path)
+    }
+
+    /// Read the stats for a cgroup and its children.
+    ///
+    /// The path is relative to the cgroup filesystem root, with "" meaning the root cgroup.
+    pub fn read_from(root: &Path, path: &str) -> Result<Self, std::io::Error> {
+        let dir = root.join(path);
+        let cgroup = Cgroup::read_from(&dir, path.to_string())?;
+
+        let mut children = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            // Child cgroups may be removed while we are scanning, so skip any that can't be read
+            if let Ok(child) = Cgroup::read_from(&entry.path(), name) {
+                children.push(child);
+            }
+        }
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Self { cgroup, children })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Cgroup> {
+        self.children.iter().find(|c| c.name == name)
+    }
+}
+
+impl crate::stats::StatType for CgroupStats {
+    // The path of the cgroup to watch, relative to /sys/fs/cgroup
+    type Params = String;
+
+    fn name() -> &'static str {
+        CGROUP_PATH
+    }
+
+    fn instance_name(path: &String) -> String {
+        if path.is_empty() {
+            CGROUP_PATH.to_string()
+        } else {
+            format!("{}/{}", CGROUP_PATH, path)
+        }
+    }
+
+    fn new_zero() -> Self {
+        Default::default()
+    }
+
+    fn update(&mut self, path: &String) -> Result<(), crate::stats::StatsError> {
+        *self = Self::read(path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read::FixtureDir;
+    use anyhow::Result;
+
+    #[test]
+    fn test_read() -> Result<()> {
+        let fixture = FixtureDir::with_files(&[
+            (
+                "cpu.stat",
+                "usage_usec 9000000\nuser_usec 6000000\nsystem_usec 3000000\n",
+            ),
+            ("cgroup.procs", "1\n"),
+            (
+                "user.slice/cpu.stat",
+                "usage_usec 5000000\nuser_usec 4000000\n",
+            ),
+            ("user.slice/memory.current", "2147483648\n"),
+            (
+                "user.slice/memory.stat",
+                "anon 1073741824\nfile 536870912\nkernel 1000\n",
+            ),
+            (
+                "user.slice/io.stat",
+                "259:0 rbytes=1000 wbytes=2000 rios=1 wios=2 dbytes=0 dios=0\n\
+                 8:0 rbytes=300 wbytes=400 rios=3 wios=4 dbytes=0 dios=0\n",
+            ),
+            (
+                "user.slice/cpu.pressure",
+                "some avg10=1.00 avg60=0.50 avg300=0.10 total=12345\n\
+                 full avg10=0.00 avg60=0.00 avg300=0.00 total=678\n",
+            ),
+            // Controllers that are not enabled for a cgroup have no files
+            ("init.scope/cpu.stat", "usage_usec 42\n"),
+            // Directories without cpu.stat are not cgroups
+            ("not-a-cgroup/other", ""),
+        ]);
+
+        let stats = CgroupStats::read_from(fixture.path(), "")?;
+
+        assert_eq!(stats.cgroup.cpu_usage_usec, 9000000);
+        let names: Vec<&str> = stats.children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["init.scope", "user.slice"]);
+
+        let user = stats.get("user.slice").unwrap();
+        assert_eq!(user.cpu_usage_usec, 5000000);
+        assert_eq!(user.memory_current, 2147483648);
+        assert_eq!(user.memory_anon, 1073741824);
+        assert_eq!(user.memory_file, 536870912);
+        assert_eq!((user.io_read_bytes, user.io_write_bytes), (1300, 2400));
+        let pressure = user.cpu_pressure.as_ref().unwrap();
+        assert_eq!((pressure.some, pressure.full), (12345, 678));
+
+        let init = stats.get("init.scope").unwrap();
+        assert_eq!(init.cpu_usage_usec, 42);
+        assert_eq!(init.memory_current, 0);
+        assert!(init.cpu_pressure.is_none());
+        Ok(())
+    }
+}
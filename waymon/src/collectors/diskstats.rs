@@ -1,10 +1,11 @@
-use crate::read::read_to_string_with_limit;
+use crate::read::{read_to_string_with_limit, system_path};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::Path;
 use thiserror::Error;
 use tracing::error;
 
 const PATH: &str = "/proc/diskstats";
+const SYS_BLOCK_PATH: &str = "/sys/class/block";
 
 // The sector sizes reported in /proc/diskstats are always in units of
 // 512 bytes, regardless of the actual sector size used by the physical disk.
@@ -19,7 +20,7 @@ pub enum ParseError {
     MissingField,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DiskStats {
     pub num_reads: u64,        // number of reads completed successfully
     pub num_reads_merged: u64, // number of reads operations merged
@@ -145,14 +146,14 @@ impl DiskStats {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ProcDiskStats {
     pub disks: HashMap<String, DiskStats>,
 }
 
 impl ProcDiskStats {
     pub fn read() -> Result<ProcDiskStats, std::io::Error> {
-        let data = read_to_string_with_limit(Path::new(PATH), 10 * 1024 * 1024)?;
+        let data = read_to_string_with_limit(&system_path(PATH), 10 * 1024 * 1024)?;
         Ok(Self::parse(&data))
     }
 
@@ -193,7 +194,24 @@ impl ProcDiskStats {
     }
 }
 
+/// Returns true if the named block device is a partition of another disk.
+pub fn is_partition(name: &str) -> bool {
+    system_path(SYS_BLOCK_PATH)
+        .join(name)
+        .join("partition")
+        .exists()
+}
+
+/// Returns true if the named block device is a device-mapper device (e.g., LVM or dm-crypt).
+///
+/// I/O to these devices is also reported for the underlying disks.
+pub fn is_device_mapper(name: &str) -> bool {
+    name.starts_with("dm-")
+}
+
 impl crate::stats::StatType for ProcDiskStats {
+    type Params = ();
+
     fn name() -> &'static str {
         PATH
     }
@@ -204,7 +222,7 @@ impl crate::stats::StatType for ProcDiskStats {
         }
     }
 
-    fn update(&mut self) -> Result<(), crate::stats::StatsError> {
+    fn update(&mut self, _params: &()) -> Result<(), crate::stats::StatsError> {
         *self = Self::read()?;
         Ok(())
     }
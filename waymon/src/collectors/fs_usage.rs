@@ -0,0 +1,435 @@
+use crate::read::{read_to_string_with_limit, system_path};
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::error;
+
+const PATH: &str = "/proc/self/mountinfo";
+// When reading another system's procfs, waymon's own process is not part of that system (and may
+// not be visible in it at all), so the mounts of that system's init process are used instead.
+const INIT_PATH: &str = "/proc/1/mountinfo";
+// The root directory of init, that its mount points are relative to
+const INIT_ROOT_PATH: &str = "/proc/1/root";
+
+// Kernel pseudo filesystems that never have interesting usage data.
+const PSEUDO_FS_TYPES: &[&str] = &[
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "efivarfs",
+    "fusectl",
+    "hugetlbfs",
+    "mqueue",
+    "nsfs",
+    "proc",
+    "pstore",
+    "rpc_pipefs",
+    "securityfs",
+    "selinuxfs",
+    "sysfs",
+    "tracefs",
+];
+
+// Network filesystems are only shown if they are configured explicitly, since statvfs() can
+// block for a long time if the server is unreachable.
+const REMOTE_FS_TYPES: &[&str] = &[
+    "9p",
+    "ceph",
+    "cifs",
+    "fuse.sshfs",
+    "glusterfs",
+    "nfs",
+    "nfs4",
+    "smb3",
+];
+
+// Read-only image filesystems, such as snap packages and ISO images, which are always full.
+// These are skipped when discovering filesystems even though they are backed by (loop) block
+// devices.
+const IMAGE_FS_TYPES: &[&str] = &["cramfs", "erofs", "iso9660", "squashfs"];
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("missing field in mountinfo line")]
+    MissingField,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct FsUsage {
+    pub mount_point: String,
+    pub fs_type: String,
+    // The mount source, e.g. "/dev/nvme0n1p2"
+    pub source: String,
+
+    pub total_bytes: u64,
+    // Free space, including space reserved for the root user
+    pub free_bytes: u64,
+    // Free space available to unprivileged users
+    pub avail_bytes: u64,
+    pub total_inodes: u64,
+    pub free_inodes: u64,
+}
+
+impl FsUsage {
+    pub fn used_bytes(&self) -> u64 {
+        self.total_bytes.saturating_sub(self.free_bytes)
+    }
+
+    /// The free space that is reserved for the root user
+    pub fn reserved_bytes(&self) -> u64 {
+        self.free_bytes.saturating_sub(self.avail_bytes)
+    }
+
+    pub fn used_inodes(&self) -> u64 {
+        self.total_inodes.saturating_sub(self.free_inodes)
+    }
+
+    /// Returns true if this filesystem should be shown when no mount points are configured.
+    ///
+    /// This picks local filesystems that are backed by a block device.
+    pub fn is_discoverable(&self) -> bool {
+        self.source.starts_with("/dev/")
+            && !REMOTE_FS_TYPES.contains(&self.fs_type.as_str())
+            && !IMAGE_FS_TYPES.contains(&self.fs_type.as_str())
+    }
+
+    // The statvfs field types vary between platforms, so the casts are not always no-ops
+    #[allow(clippy::unnecessary_cast)]
+    fn update_from_statvfs(&mut self) -> Result<(), std::io::Error> {
+        let c_path = CString::new(stat_path(&self.mount_point).into_os_string().into_vec())
+            .map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "mount point contains NUL")
+            })?;
+        // SAFETY: statvfs() only writes to the buffer we pass in, and c_path is a valid
+        // NUL-terminated string.
+        let mut buf: libc::statvfs = unsafe { std::mem::zeroed() };
+        let rc = unsafe { libc::statvfs(c_path.as_ptr(), &mut buf) };
+        if rc != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let block_size = buf.f_frsize as u64;
+        self.total_bytes = (buf.f_blocks as u64) * block_size;
+        self.free_bytes = (buf.f_bfree as u64) * block_size;
+        self.avail_bytes = (buf.f_bavail as u64) * block_size;
+        self.total_inodes = buf.f_files as u64;
+        self.free_inodes = buf.f_ffree as u64;
+        Ok(())
+    }
+}
+
+/// Returns true if stats are being read from another system's procfs, rather than our own.
+fn is_foreign_proc() -> bool {
+    system_path("/proc") != Path::new("/proc")
+}
+
+/// Return the path of the mountinfo file to read.
+fn mountinfo_path() -> PathBuf {
+    if is_foreign_proc() {
+        system_path(INIT_PATH)
+    } else {
+        PathBuf::from(PATH)
+    }
+}
+
+/// Return the path to stat for a mount point.
+///
+/// When reading another system's procfs (e.g., a container's), the mount points are relative to
+/// that system's root directory, which is reached through its init process's /proc/1/root.
+/// Otherwise the mount point is used as-is.
+fn stat_path(mount_point: &str) -> PathBuf {
+    if is_foreign_proc() {
+        system_path(INIT_ROOT_PATH).join(mount_point.trim_start_matches('/'))
+    } else {
+        PathBuf::from(mount_point)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FsUsageStats {
+    // The mounted filesystems, in mount order
+    pub filesystems: Vec<FsUsage>,
+}
+
+impl FsUsageStats {
+    /// Read the usage of the given mount points, or of all discoverable filesystems if
+    /// mount_points is empty.
+    pub fn read(mount_points: &[String]) -> Result<FsUsageStats, std::io::Error> {
+        let data = read_to_string_with_limit(&mountinfo_path(), 10 * 1024 * 1024)?;
+        let mut stats = Self::parse(&data);
+
+        // Only the filesystems that will be shown are stat'ed, since statvfs() can block for a
+        // long time on network and FUSE filesystems.  Filesystems that we cannot stat (e.g., due
+        // to permissions) are skipped.
+        stats.select(mount_points);
+        stats
+            .filesystems
+            .retain_mut(|fs| fs.update_from_statvfs().is_ok());
+        Ok(stats)
+    }
+
+    /// Keep only the given mount points, or the discoverable filesystems if mount_points is empty.
+    fn select(&mut self, mount_points: &[String]) {
+        if mount_points.is_empty() {
+            self.filesystems.retain(FsUsage::is_discoverable);
+        } else {
+            self.filesystems
+                .retain(|fs| mount_points.contains(&fs.mount_point));
+        }
+    }
+
+    /// Parse the list of mounted filesystems from /proc/self/mountinfo.
+    ///
+    /// This only fills in the mount information; the usage fields are left as 0.
+    pub fn parse(data: &str) -> FsUsageStats {
+        let mut s = FsUsageStats::default();
+        for (index, line) in data.split('\n').enumerate() {
+            if let Err(e) = s.parse_line(line) {
+                static PARSE_ERROR_LOG: std::sync::Once = std::sync::Once::new();
+                PARSE_ERROR_LOG.call_once(|| {
+                    error!("{}:{} {:?}", PATH, index + 1, e);
+                });
+            }
+        }
+        s
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), ParseError> {
+        if line.is_empty() {
+            // This happens after the final newline in the file.
+            return Ok(());
+        }
+
+        // The format is:
+        //   <id> <parent> <major:minor> <root> <mount point> <options> [optional fields...]
+        //   - <fs type> <source> <super options>
+        let (mount_fields, fs_fields) = line.split_once(" - ").ok_or(ParseError::MissingField)?;
+        let mount_point = mount_fields
+            .split(' ')
+            .nth(4)
+            .ok_or(ParseError::MissingField)?;
+        let mut fs_fields = fs_fields.split(' ');
+        let fs_type = fs_fields.next().ok_or(ParseError::MissingField)?;
+        let source = fs_fields.next().ok_or(ParseError::MissingField)?;
+
+        if PSEUDO_FS_TYPES.contains(&fs_type) {
+            return Ok(());
+        }
+
+        // If a filesystem is mounted over an existing mount point only the newer mount is
+        // visible, so replace any earlier entry.
+        let mount_point = unescape(mount_point);
+        self.filesystems.retain(|fs| fs.mount_point != mount_point);
+        self.filesystems.push(FsUsage {
+            mount_point,
+            fs_type: fs_type.to_string(),
+            source: unescape(source),
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    pub fn get(&self, mount_point: &str) -> Option<&FsUsage> {
+        self.filesystems
+            .iter()
+            .find(|fs| fs.mount_point == mount_point)
+    }
+}
+
+/// Decode the octal escapes (e.g., "\040" for a space) used for paths in mountinfo
+fn unescape(s: &str) -> String {
+    if !s.contains('\\') {
+        return s.to_string();
+    }
+
+    let bytes = s.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'\\' && idx + 4 <= bytes.len() {
+            let digits = &bytes[idx + 1..idx + 4];
+            if digits.iter().all(|d| (b'0'..=b'7').contains(d)) {
+                let value = digits
+                    .iter()
+                    .fold(0u32, |acc, d| acc * 8 + u32::from(d - b'0'));
+                if let Ok(value) = u8::try_from(value) {
+                    result.push(value);
+                    idx += 4;
+                    continue;
+                }
+            }
+        }
+        result.push(bytes[idx]);
+        idx += 1;
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+impl crate::stats::StatType for FsUsageStats {
+    // The mount points to read, or an empty list to discover them
+    type Params = Vec<String>;
+
+    fn name() -> &'static str {
+        PATH
+    }
+
+    fn instance_name(mount_points: &Vec<String>) -> String {
+        if mount_points.is_empty() {
+            PATH.to_string()
+        } else {
+            format!("{} {}", PATH, mount_points.join(" "))
+        }
+    }
+
+    fn new_zero() -> Self {
+        Default::default()
+    }
+
+    fn update(&mut self, mount_points: &Vec<String>) -> Result<(), crate::stats::StatsError> {
+        *self = Self::read(mount_points)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read::FixtureDir;
+    use anyhow::{anyhow, Result};
+
+    const TEST_DATA: &str = r"22 1 0:21 / /proc rw,nosuid,nodev,noexec,relatime shared:12 - proc proc rw
+23 1 0:22 / /sys rw,nosuid,nodev,noexec,relatime shared:2 - sysfs sysfs rw
+26 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw,errors=remount-ro
+29 26 0:26 / /tmp rw,nosuid,nodev shared:14 - tmpfs tmpfs rw,size=16G
+31 26 259:1 / /boot/efi rw,relatime shared:30 - vfat /dev/nvme0n1p1 rw,fmask=0077
+40 26 0:35 / /mnt/server rw,relatime shared:40 - nfs4 server:/export rw,vers=4.2
+45 26 253:0 /home /home rw,relatime shared:45 - btrfs /dev/mapper/crypt rw,ssd
+46 26 8:1 / /media/My\040Disk rw,relatime shared:46 - exfat /dev/sda1 rw
+47 26 253:0 /home2 /home rw,relatime shared:47 - btrfs /dev/mapper/crypt rw,ssd
+50 26 7:3 / /snap/core22/1380 ro,nodev,relatime shared:50 - squashfs /dev/loop3 ro
+51 26 0:60 / /mnt/cloud rw,nosuid,nodev,relatime shared:51 - fuse.rclone remote: rw
+";
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let stats = FsUsageStats::parse(TEST_DATA);
+        let mount_points: Vec<&str> = stats
+            .filesystems
+            .iter()
+            .map(|fs| fs.mount_point.as_str())
+            .collect();
+        assert_eq!(
+            mount_points,
+            vec![
+                "/",
+                "/tmp",
+                "/boot/efi",
+                "/mnt/server",
+                "/media/My Disk",
+                "/home",
+                "/snap/core22/1380",
+                "/mnt/cloud"
+            ]
+        );
+
+        let root = stats.get("/").ok_or(anyhow!("/ not found"))?;
+        assert_eq!(root.fs_type, "ext4");
+        assert_eq!(root.source, "/dev/nvme0n1p2");
+        assert!(root.is_discoverable());
+
+        let tmp = stats.get("/tmp").ok_or(anyhow!("/tmp not found"))?;
+        assert!(!tmp.is_discoverable());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_select() {
+        let mount_points = |stats: &FsUsageStats| -> Vec<String> {
+            stats
+                .filesystems
+                .iter()
+                .map(|fs| fs.mount_point.clone())
+                .collect()
+        };
+
+        // Network, FUSE, and image filesystems are not discovered
+        let mut stats = FsUsageStats::parse(TEST_DATA);
+        stats.select(&[]);
+        assert_eq!(
+            mount_points(&stats),
+            vec!["/", "/boot/efi", "/media/My Disk", "/home"]
+        );
+
+        // but can be configured explicitly
+        let mut stats = FsUsageStats::parse(TEST_DATA);
+        let configured = vec![
+            "/mnt/server".to_string(),
+            "/home".to_string(),
+            "/mnt/missing".to_string(),
+        ];
+        stats.select(&configured);
+        assert_eq!(mount_points(&stats), vec!["/mnt/server", "/home"]);
+    }
+
+    #[test]
+    fn test_read_from_proc_root() -> Result<()> {
+        let fixture = FixtureDir::with_files(&[
+            (
+                "proc/1/mountinfo",
+                "26 1 259:2 / / rw,relatime - ext4 /dev/nvme0n1p2 rw\n\
+                 45 26 253:0 /home /home rw,relatime - btrfs /dev/mapper/crypt rw\n\
+                 46 26 8:1 / /media/usb rw,relatime - exfat /dev/sda1 rw\n",
+            ),
+            // The mount points are stat'ed through init's root directory in the fixture's procfs
+            ("proc/1/root/home/.keep", ""),
+            // The fixture's "self" is not waymon, so its mounts must not be used
+            (
+                "proc/self/mountinfo",
+                "30 1 0:40 / /self-only rw,relatime - ext4 /dev/sdb1 rw\n",
+            ),
+        ]);
+        let _roots = fixture.use_as_system_roots();
+
+        let stats = FsUsageStats::read(&["/home".to_string(), "/media/usb".to_string()])?;
+        // /media/usb does not exist under the fixture root, so it can't be stat'ed
+        let home = stats.get("/home").ok_or(anyhow!("/home not found"))?;
+        assert_eq!(stats.filesystems.len(), 1);
+        assert_eq!(home.fs_type, "btrfs");
+        assert!(home.total_bytes > 0);
+        assert!(FsUsageStats::read(&[])?.get("/self-only").is_none());
+
+        assert_eq!(stat_path("/home"), fixture.path().join("proc/1/root/home"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("/media/My\\040Disk"), "/media/My Disk");
+        assert_eq!(unescape("/a\\134b"), "/a\\b");
+        assert_eq!(unescape("/trailing\\04"), "/trailing\\04");
+        assert_eq!(unescape("/plain"), "/plain");
+    }
+
+    #[test]
+    fn test_usage() {
+        let fs = FsUsage {
+            total_bytes: 1000,
+            free_bytes: 300,
+            avail_bytes: 250,
+            total_inodes: 100,
+            free_inodes: 90,
+            ..Default::default()
+        };
+        assert_eq!(fs.used_bytes(), 700);
+        assert_eq!(fs.reserved_bytes(), 50);
+        assert_eq!(fs.used_inodes(), 10);
+    }
+}
@@ -1,5 +1,5 @@
-use crate::read::read_to_string_with_limit;
-use std::path::Path;
+use crate::read::{read_to_string_with_limit, system_path};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::error;
 
@@ -13,8 +13,12 @@ pub enum ParseError {
     UnexpectedData,
 }
 
-// Note: all values in the MemoryStats structure are in kilobytes, not bytes
-#[derive(Clone, Debug, Default)]
+// Note: all values in the MemoryStats structure are in kilobytes, not bytes, except for the
+// hugepages_* counts, which are numbers of huge pages.  Fields that are missing from
+// /proc/meminfo (because the kernel is too old, or was built without the relevant feature) are
+// left as 0.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct MemoryStats {
     pub mem_total: u64,
     pub mem_free: u64,
@@ -24,6 +28,10 @@ pub struct MemoryStats {
     pub swap_cached: u64,
     pub active: u64,
     pub inactive: u64,
+    pub active_anon: u64,
+    pub inactive_anon: u64,
+    pub active_file: u64,
+    pub inactive_file: u64,
     pub unevictable: u64,
     pub mlocked: u64,
     pub high_total: u64,
@@ -32,6 +40,9 @@ pub struct MemoryStats {
     pub low_free: u64,
     pub swap_total: u64,
     pub swap_free: u64,
+    // The compressed size of pages stored in zswap, and their uncompressed size
+    pub zswap: u64,
+    pub zswapped: u64,
     pub dirty: u64,
     pub writeback: u64,
     pub anon_pages: u64,
@@ -43,16 +54,39 @@ pub struct MemoryStats {
     pub sunreclaimable: u64,
     pub kernel_stack: u64,
     pub page_tables: u64,
+    pub sec_page_tables: u64,
+    pub nfs_unstable: u64,
+    pub bounce: u64,
+    pub writeback_tmp: u64,
     pub commit_limit: u64,
     pub committed_as: u64,
     pub vmalloc_total: u64,
     pub vmalloc_used: u64,
-    // There are several other fields in /proc/meminfo that we don't currently bother tracking
+    pub vmalloc_chunk: u64,
+    pub percpu: u64,
+    pub hardware_corrupted: u64,
+    pub anon_huge_pages: u64,
+    pub shmem_huge_pages: u64,
+    pub shmem_pmd_mapped: u64,
+    pub file_huge_pages: u64,
+    pub file_pmd_mapped: u64,
+    pub cma_total: u64,
+    pub cma_free: u64,
+    pub unaccepted: u64,
+    pub hugepages_total: u64,
+    pub hugepages_free: u64,
+    pub hugepages_rsvd: u64,
+    pub hugepages_surp: u64,
+    pub hugepage_size: u64,
+    pub hugetlb: u64,
+    pub direct_map_4k: u64,
+    pub direct_map_2m: u64,
+    pub direct_map_1g: u64,
 }
 
 impl MemoryStats {
     pub fn read() -> Result<Self, std::io::Error> {
-        let data = read_to_string_with_limit(Path::new(PATH), 10 * 1024 * 1024)?;
+        let data = read_to_string_with_limit(&system_path(PATH), 10 * 1024 * 1024)?;
         Ok(Self::parse(&data))
     }
 
@@ -78,6 +112,10 @@ impl MemoryStats {
             || Self::try_parse_kb(line, "SwapCached:", &mut self.swap_cached)?
             || Self::try_parse_kb(line, "Active:", &mut self.active)?
             || Self::try_parse_kb(line, "Inactive:", &mut self.inactive)?
+            || Self::try_parse_kb(line, "Active(anon):", &mut self.active_anon)?
+            || Self::try_parse_kb(line, "Inactive(anon):", &mut self.inactive_anon)?
+            || Self::try_parse_kb(line, "Active(file):", &mut self.active_file)?
+            || Self::try_parse_kb(line, "Inactive(file):", &mut self.inactive_file)?
             || Self::try_parse_kb(line, "Unevictable:", &mut self.unevictable)?
             || Self::try_parse_kb(line, "Mlocked:", &mut self.mlocked)?
             || Self::try_parse_kb(line, "HighTotal:", &mut self.high_total)?
@@ -86,6 +124,8 @@ impl MemoryStats {
             || Self::try_parse_kb(line, "LowFree:", &mut self.low_free)?
             || Self::try_parse_kb(line, "SwapTotal:", &mut self.swap_total)?
             || Self::try_parse_kb(line, "SwapFree:", &mut self.swap_free)?
+            || Self::try_parse_kb(line, "Zswap:", &mut self.zswap)?
+            || Self::try_parse_kb(line, "Zswapped:", &mut self.zswapped)?
             || Self::try_parse_kb(line, "Dirty:", &mut self.dirty)?
             || Self::try_parse_kb(line, "Writeback:", &mut self.writeback)?
             || Self::try_parse_kb(line, "AnonPages:", &mut self.anon_pages)?
@@ -97,13 +137,45 @@ impl MemoryStats {
             || Self::try_parse_kb(line, "SUnreclaim:", &mut self.sunreclaimable)?
             || Self::try_parse_kb(line, "KernelStack:", &mut self.kernel_stack)?
             || Self::try_parse_kb(line, "PageTables:", &mut self.page_tables)?
+            || Self::try_parse_kb(line, "SecPageTables:", &mut self.sec_page_tables)?
+            || Self::try_parse_kb(line, "NFS_Unstable:", &mut self.nfs_unstable)?
+            || Self::try_parse_kb(line, "Bounce:", &mut self.bounce)?
+            || Self::try_parse_kb(line, "WritebackTmp:", &mut self.writeback_tmp)?
             || Self::try_parse_kb(line, "CommitLimit:", &mut self.commit_limit)?
             || Self::try_parse_kb(line, "Committed_AS:", &mut self.committed_as)?
             || Self::try_parse_kb(line, "VmallocTotal:", &mut self.vmalloc_total)?
-            || Self::try_parse_kb(line, "VmallocUsed:", &mut self.vmalloc_used)?;
+            || Self::try_parse_kb(line, "VmallocUsed:", &mut self.vmalloc_used)?
+            || Self::try_parse_kb(line, "VmallocChunk:", &mut self.vmalloc_chunk)?
+            || Self::try_parse_kb(line, "Percpu:", &mut self.percpu)?
+            || Self::try_parse_kb(line, "HardwareCorrupted:", &mut self.hardware_corrupted)?
+            || Self::try_parse_kb(line, "AnonHugePages:", &mut self.anon_huge_pages)?
+            || Self::try_parse_kb(line, "ShmemHugePages:", &mut self.shmem_huge_pages)?
+            || Self::try_parse_kb(line, "ShmemPmdMapped:", &mut self.shmem_pmd_mapped)?
+            || Self::try_parse_kb(line, "FileHugePages:", &mut self.file_huge_pages)?
+            || Self::try_parse_kb(line, "FilePmdMapped:", &mut self.file_pmd_mapped)?
+            || Self::try_parse_kb(line, "CmaTotal:", &mut self.cma_total)?
+            || Self::try_parse_kb(line, "CmaFree:", &mut self.cma_free)?
+            || Self::try_parse_kb(line, "Unaccepted:", &mut self.unaccepted)?
+            || Self::try_parse_count(line, "HugePages_Total:", &mut self.hugepages_total)?
+            || Self::try_parse_count(line, "HugePages_Free:", &mut self.hugepages_free)?
+            || Self::try_parse_count(line, "HugePages_Rsvd:", &mut self.hugepages_rsvd)?
+            || Self::try_parse_count(line, "HugePages_Surp:", &mut self.hugepages_surp)?
+            || Self::try_parse_kb(line, "Hugepagesize:", &mut self.hugepage_size)?
+            || Self::try_parse_kb(line, "Hugetlb:", &mut self.hugetlb)?
+            || Self::try_parse_kb(line, "DirectMap4k:", &mut self.direct_map_4k)?
+            || Self::try_parse_kb(line, "DirectMap2M:", &mut self.direct_map_2m)?
+            || Self::try_parse_kb(line, "DirectMap1G:", &mut self.direct_map_1g)?;
         Ok(())
     }
 
+    /// The memory used by swapped out pages, in kilobytes.
+    ///
+    /// This includes pages in the swap cache, which have been read back into memory but are still
+    /// also stored in swap.
+    pub fn swap_used(&self) -> u64 {
+        self.swap_total.saturating_sub(self.swap_free)
+    }
+
     fn try_parse_kb(line: &str, prefix: &str, field: &mut u64) -> Result<bool, ParseError> {
         if let Some(data) = line.strip_prefix(prefix) {
             let data = data.trim_start();
@@ -117,9 +189,21 @@ impl MemoryStats {
             Ok(false)
         }
     }
+
+    // The HugePages_* lines are counts of pages, and do not have a unit suffix
+    fn try_parse_count(line: &str, prefix: &str, field: &mut u64) -> Result<bool, ParseError> {
+        if let Some(data) = line.strip_prefix(prefix) {
+            *field = data.trim().parse()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
 }
 
 impl crate::stats::StatType for MemoryStats {
+    type Params = ();
+
     fn name() -> &'static str {
         PATH
     }
@@ -128,7 +212,7 @@ impl crate::stats::StatType for MemoryStats {
         Default::default()
     }
 
-    fn update(&mut self) -> Result<(), crate::stats::StatsError> {
+    fn update(&mut self, _params: &()) -> Result<(), crate::stats::StatsError> {
         *self = Self::read()?;
         Ok(())
     }
@@ -175,6 +259,39 @@ mod tests {
         assert_eq!(m.vmalloc_total, 34359738367);
         assert_eq!(m.vmalloc_used, 179892);
 
+        assert_eq!(m.active_anon, 7716384);
+        assert_eq!(m.inactive_anon, 1736);
+        assert_eq!(m.active_file, 4846228);
+        assert_eq!(m.inactive_file, 8296696);
+        assert_eq!(m.zswap, 0);
+        assert_eq!(m.percpu, 14688);
+        assert_eq!(m.anon_huge_pages, 499712);
+        assert_eq!(m.shmem_huge_pages, 1910784);
+        assert_eq!(m.hugepages_total, 0);
+        assert_eq!(m.hugepage_size, 2048);
+        assert_eq!(m.direct_map_1g, 11534336);
+        assert_eq!(m.swap_used(), 768);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_zswap_and_hugepages() -> Result<()> {
+        let m = MemoryStats::parse(ZSWAP_HUGEPAGES_TEST_DATA);
+
+        assert_eq!(m.swap_total, 8388604);
+        assert_eq!(m.swap_cached, 20480);
+        assert_eq!(m.swap_used(), 1048576);
+        assert_eq!(m.zswap, 65536);
+        assert_eq!(m.zswapped, 262144);
+        // The HugePages_* counts are numbers of pages, without a unit
+        assert_eq!(m.hugepages_total, 512);
+        assert_eq!(m.hugepages_free, 500);
+        assert_eq!(m.hugepages_rsvd, 4);
+        assert_eq!(m.hugepages_surp, 0);
+        assert_eq!(m.hugepage_size, 2048);
+        assert_eq!(m.hugetlb, 1048576);
+
         Ok(())
     }
 
@@ -234,5 +351,23 @@ Hugetlb:               0 kB
 DirectMap4k:      444092 kB
 DirectMap2M:    13027328 kB
 DirectMap1G:    11534336 kB
+"#;
+
+    // A system using zswap, with hugetlb pages reserved
+    const ZSWAP_HUGEPAGES_TEST_DATA: &str = r#"
+MemTotal:       32607860 kB
+MemFree:         2219436 kB
+MemAvailable:    9771080 kB
+SwapCached:        20480 kB
+SwapTotal:       8388604 kB
+SwapFree:        7340028 kB
+Zswap:             65536 kB
+Zswapped:         262144 kB
+HugePages_Total:     512
+HugePages_Free:      500
+HugePages_Rsvd:        4
+HugePages_Surp:        0
+Hugepagesize:       2048 kB
+Hugetlb:         1048576 kB
 "#;
 }
@@ -1,6 +1,6 @@
-use crate::read::read_to_string_with_limit;
+use crate::read::{read_to_string_with_limit, system_path};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::Path;
 use thiserror::Error;
 use tracing::error;
 
@@ -14,7 +14,7 @@ pub enum ParseError {
     MissingField,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct InterfaceStats {
     pub rx_bytes: u64,
     pub rx_packets: u64,
@@ -68,14 +68,14 @@ impl InterfaceStats {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct NetDevStats {
     pub interfaces: HashMap<String, InterfaceStats>,
 }
 
 impl NetDevStats {
     pub fn read() -> Result<NetDevStats, std::io::Error> {
-        let data = read_to_string_with_limit(Path::new(PATH), 10 * 1024 * 1024)?;
+        let data = read_to_string_with_limit(&system_path(PATH), 10 * 1024 * 1024)?;
         Ok(Self::parse(&data))
     }
 
@@ -115,6 +115,8 @@ impl NetDevStats {
 }
 
 impl crate::stats::StatType for NetDevStats {
+    type Params = ();
+
     fn name() -> &'static str {
         PATH
     }
@@ -125,7 +127,7 @@ impl crate::stats::StatType for NetDevStats {
         }
     }
 
-    fn update(&mut self) -> Result<(), crate::stats::StatsError> {
+    fn update(&mut self, _params: &()) -> Result<(), crate::stats::StatsError> {
         *self = Self::read()?;
         Ok(())
     }
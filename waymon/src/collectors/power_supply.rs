@@ -0,0 +1,246 @@
+use crate::read::{read_sysfs_string, system_path};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct PowerSupply {
+    // The supply name, e.g. "BAT0" or "AC"
+    pub name: String,
+    // The supply type, e.g. "Battery", "Mains", or "USB"
+    pub supply_type: String,
+    // The charging status, e.g. "Charging", "Discharging", "Full", or "Not charging"
+    pub status: Option<String>,
+    // Whether an external supply is connected.  Only reported for non-battery supplies.
+    pub online: Option<bool>,
+    // The charge level, in percent
+    pub capacity: Option<f64>,
+
+    // The remaining energy and the energy when full, in watt-hours
+    pub energy_now: Option<f64>,
+    pub energy_full: Option<f64>,
+    // The current rate of charge or discharge, in watts
+    pub power_now: Option<f64>,
+    // The current voltage, in volts
+    pub voltage_now: Option<f64>,
+    // The current charge or discharge current, in amps
+    pub current_now: Option<f64>,
+    // The remaining charge and the charge when full, in amp-hours.
+    // Some batteries report these instead of the energy_* values.
+    pub charge_now: Option<f64>,
+    pub charge_full: Option<f64>,
+}
+
+impl PowerSupply {
+    pub fn is_battery(&self) -> bool {
+        self.supply_type == "Battery"
+    }
+
+    pub fn is_discharging(&self) -> bool {
+        self.status.as_deref() == Some("Discharging")
+    }
+
+    pub fn is_charging(&self) -> bool {
+        self.status.as_deref() == Some("Charging")
+    }
+
+    /// The current power draw in watts.
+    ///
+    /// This uses power_now if the supply reports it, and otherwise computes it from the voltage
+    /// and current.
+    pub fn power_watts(&self) -> Option<f64> {
+        if let Some(power) = self.power_now {
+            return Some(power);
+        }
+        Some(self.voltage_now? * self.current_now?)
+    }
+
+    /// The remaining energy in watt-hours.
+    ///
+    /// Batteries that only report charge in amp-hours are converted using the current voltage.
+    pub fn energy_wh(&self) -> Option<f64> {
+        if let Some(energy) = self.energy_now {
+            return Some(energy);
+        }
+        Some(self.charge_now? * self.voltage_now?)
+    }
+
+    /// The energy when fully charged, in watt-hours.
+    pub fn energy_full_wh(&self) -> Option<f64> {
+        if let Some(energy) = self.energy_full {
+            return Some(energy);
+        }
+        Some(self.charge_full? * self.voltage_now?)
+    }
+
+    /// The charge level in percent.
+    ///
+    /// This uses the capacity reported by the kernel if available, and otherwise computes it from
+    /// the current and full energy levels.
+    pub fn charge_percent(&self) -> Option<f64> {
+        if let Some(capacity) = self.capacity {
+            return Some(capacity);
+        }
+        let full = self.energy_full_wh()?;
+        if full <= 0.0 {
+            return None;
+        }
+        Some(100.0 * self.energy_wh()? / full)
+    }
+
+    fn read_from(dir: &Path, name: String) -> Option<Self> {
+        let supply_type = read_sysfs_string(&dir.join("type"))?;
+        Some(Self {
+            name,
+            supply_type,
+            status: read_sysfs_string(&dir.join("status")),
+            online: read_sysfs_value(&dir.join("online")).map(|v| v != 0),
+            capacity: read_sysfs_value(&dir.join("capacity")).map(|v| v as f64),
+            energy_now: read_micro_units(&dir.join("energy_now")),
+            energy_full: read_micro_units(&dir.join("energy_full")),
+            power_now: read_micro_units(&dir.join("power_now")),
+            voltage_now: read_micro_units(&dir.join("voltage_now")),
+            current_now: read_micro_units(&dir.join("current_now")),
+            charge_now: read_micro_units(&dir.join("charge_now")),
+            charge_full: read_micro_units(&dir.join("charge_full")),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PowerSupplyStats {
+    // The power supplies, sorted by name
+    pub supplies: Vec<PowerSupply>,
+}
+
+impl PowerSupplyStats {
+    pub fn read() -> Result<Self, std::io::Error> {
+        Self::read_from(&system_path(POWER_SUPPLY_PATH))
+    }
+
+    pub fn read_from(dir: &Path) -> Result<Self, std::io::Error> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            // Systems without any power supply class devices may not have this directory at all
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+
+        let mut supplies = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if let Some(supply) = PowerSupply::read_from(&entry.path(), name) {
+                supplies.push(supply);
+            }
+        }
+        supplies.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Self { supplies })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PowerSupply> {
+        self.supplies.iter().find(|s| s.name == name)
+    }
+
+    /// Return the first battery, if there is one.
+    pub fn first_battery(&self) -> Option<&PowerSupply> {
+        self.supplies.iter().find(|s| s.is_battery())
+    }
+}
+
+fn read_sysfs_value(path: &Path) -> Option<i64> {
+    read_sysfs_string(path)?.parse::<i64>().ok()
+}
+
+/// Read a value reported in micro-units (µWh, µW, µV, µA, or µAh), and convert it to base units.
+///
+/// Some drivers report a negative current or power while discharging, so this returns the
+/// absolute value.  The status attribute should be used to tell the direction of flow.
+fn read_micro_units(path: &Path) -> Option<f64> {
+    let value = read_sysfs_value(path)?;
+    Some((value.unsigned_abs() as f64) / 1_000_000.0)
+}
+
+impl crate::stats::StatType for PowerSupplyStats {
+    type Params = ();
+
+    fn name() -> &'static str {
+        POWER_SUPPLY_PATH
+    }
+
+    fn new_zero() -> Self {
+        Default::default()
+    }
+
+    fn update(&mut self, _params: &()) -> Result<(), crate::stats::StatsError> {
+        *self = Self::read()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read::FixtureDir;
+    use anyhow::Result;
+
+    #[test]
+    fn test_read() -> Result<()> {
+        let fixture = FixtureDir::with_files(&[
+            ("AC/type", "Mains\n"),
+            ("AC/online", "0\n"),
+            ("BAT0/type", "Battery\n"),
+            ("BAT0/status", "Discharging\n"),
+            ("BAT0/capacity", "81\n"),
+            ("BAT0/energy_now", "40500000\n"),
+            ("BAT0/energy_full", "50000000\n"),
+            ("BAT0/power_now", "8250000\n"),
+            ("BAT0/voltage_now", "11900000\n"),
+            // A battery that reports charge and a negative current instead of energy and power
+            ("BAT1/type", "Battery\n"),
+            ("BAT1/status", "Discharging\n"),
+            ("BAT1/charge_now", "2000000\n"),
+            ("BAT1/charge_full", "4000000\n"),
+            ("BAT1/current_now", "-500000\n"),
+            ("BAT1/voltage_now", "12000000\n"),
+            // Entries without a type should be ignored
+            ("hidpp_battery_0/status", "Unknown\n"),
+        ]);
+
+        let stats = PowerSupplyStats::read_from(fixture.path())?;
+
+        let names: Vec<&str> = stats.supplies.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["AC", "BAT0", "BAT1"]);
+
+        let ac = stats.get("AC").unwrap();
+        assert!(!ac.is_battery());
+        assert_eq!(ac.online, Some(false));
+        assert_eq!(ac.power_watts(), None);
+
+        let bat0 = stats.first_battery().unwrap();
+        assert_eq!(bat0.name, "BAT0");
+        assert!(bat0.is_discharging());
+        assert_eq!(bat0.charge_percent(), Some(81.0));
+        assert_eq!(bat0.energy_wh(), Some(40.5));
+        assert_eq!(bat0.energy_full_wh(), Some(50.0));
+        assert_eq!(bat0.power_watts(), Some(8.25));
+
+        let bat1 = stats.get("BAT1").unwrap();
+        assert_eq!(bat1.capacity, None);
+        assert_eq!(bat1.power_watts(), Some(6.0));
+        assert_eq!(bat1.energy_wh(), Some(24.0));
+        assert_eq!(bat1.charge_percent(), Some(50.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_dir() -> Result<()> {
+        let stats = PowerSupplyStats::read_from(Path::new("/nonexistent/waymon/power_supply"))?;
+        assert!(stats.supplies.is_empty());
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
-use crate::read::read_to_string_with_limit;
+use crate::collectors::cgroup::CGROUP_PATH;
+use crate::read::{read_to_string_with_limit, system_path};
 use crate::stats::{StatType, StatsError};
-use std::path::Path;
+use serde::{Deserialize, Serialize};
 
 const CPU_PATH: &str = "/proc/pressure/cpu";
 const IO_PATH: &str = "/proc/pressure/io";
@@ -9,126 +10,207 @@ const MEMORY_PATH: &str = "/proc/pressure/memory";
 pub trait PressureStats {
     fn some_us(&self) -> u64;
     fn full_us(&self) -> u64;
+    fn some_avg(&self) -> &PressureAverages;
+    fn full_avg(&self) -> &PressureAverages;
 }
 
-#[derive(Debug, Clone, Default)]
+/// The kernel's running averages of the percentage of time that tasks were stalled, over the
+/// last 10 seconds, 60 seconds, and 300 seconds.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct PressureAverages {
+    pub avg10: f64,
+    pub avg60: f64,
+    pub avg300: f64,
+}
+
+// One "some" or "full" line from a pressure file
+#[derive(Debug, Default)]
+struct PressureLine {
+    avg: PressureAverages,
+    total: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct CpuPressure {
     pub some: u64,
     pub full: u64,
+    #[serde(default)]
+    pub some_avg: PressureAverages,
+    #[serde(default)]
+    pub full_avg: PressureAverages,
 }
 
 impl CpuPressure {
-    pub fn read() -> Result<Self, StatsError> {
-        Self::parse(&read_pressure_file(CPU_PATH)?)
+    pub fn read(cgroup: &Option<String>) -> Result<Self, StatsError> {
+        Self::parse(&read_pressure_file(&pressure_path(CPU_PATH, "cpu.pressure", cgroup))?)
     }
 
     pub fn parse(data: &str) -> Result<Self, StatsError> {
         let (some, full) = parse_pressure_data(data)?;
-        Ok(Self { some, full })
+        Ok(Self {
+            some: some.total,
+            full: full.total,
+            some_avg: some.avg,
+            full_avg: full.avg,
+        })
     }
 }
 
 impl PressureStats for CpuPressure {
     fn some_us(&self) -> u64 { self.some }
     fn full_us(&self) -> u64 { self.full }
+    fn some_avg(&self) -> &PressureAverages { &self.some_avg }
+    fn full_avg(&self) -> &PressureAverages { &self.full_avg }
 }
 
 impl StatType for CpuPressure {
+    type Params = Option<String>;
+
     fn name() -> &'static str {
         CPU_PATH
     }
 
+    fn instance_name(cgroup: &Option<String>) -> String {
+        pressure_path(CPU_PATH, "cpu.pressure", cgroup)
+    }
+
     fn new_zero() -> Self {
         Default::default()
     }
 
-    fn update(&mut self) -> Result<(), crate::stats::StatsError> {
-        *self = Self::read()?;
+    fn update(&mut self, cgroup: &Option<String>) -> Result<(), crate::stats::StatsError> {
+        *self = Self::read(cgroup)?;
         Ok(())
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct IoPressure {
     pub some: u64,
     pub full: u64,
+    #[serde(default)]
+    pub some_avg: PressureAverages,
+    #[serde(default)]
+    pub full_avg: PressureAverages,
 }
 
 impl IoPressure {
-    pub fn read() -> Result<Self, StatsError> {
-        Self::parse(&read_pressure_file(IO_PATH)?)
+    pub fn read(cgroup: &Option<String>) -> Result<Self, StatsError> {
+        Self::parse(&read_pressure_file(&pressure_path(IO_PATH, "io.pressure", cgroup))?)
     }
 
     pub fn parse(data: &str) -> Result<Self, StatsError> {
         let (some, full) = parse_pressure_data(data)?;
-        Ok(Self { some, full })
+        Ok(Self {
+            some: some.total,
+            full: full.total,
+            some_avg: some.avg,
+            full_avg: full.avg,
+        })
     }
 }
 
 impl PressureStats for IoPressure {
     fn some_us(&self) -> u64 { self.some }
     fn full_us(&self) -> u64 { self.full }
+    fn some_avg(&self) -> &PressureAverages { &self.some_avg }
+    fn full_avg(&self) -> &PressureAverages { &self.full_avg }
 }
 
 impl StatType for IoPressure {
+    type Params = Option<String>;
+
     fn name() -> &'static str {
         IO_PATH
     }
 
+    fn instance_name(cgroup: &Option<String>) -> String {
+        pressure_path(IO_PATH, "io.pressure", cgroup)
+    }
+
     fn new_zero() -> Self {
         Default::default()
     }
 
-    fn update(&mut self) -> Result<(), crate::stats::StatsError> {
-        *self = Self::read()?;
+    fn update(&mut self, cgroup: &Option<String>) -> Result<(), crate::stats::StatsError> {
+        *self = Self::read(cgroup)?;
         Ok(())
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct MemoryPressure {
     pub some: u64,
     pub full: u64,
+    #[serde(default)]
+    pub some_avg: PressureAverages,
+    #[serde(default)]
+    pub full_avg: PressureAverages,
 }
 
 impl MemoryPressure {
-    pub fn read() -> Result<Self, StatsError> {
-        Self::parse(&read_pressure_file(MEMORY_PATH)?)
+    pub fn read(cgroup: &Option<String>) -> Result<Self, StatsError> {
+        Self::parse(&read_pressure_file(&pressure_path(MEMORY_PATH, "memory.pressure", cgroup))?)
     }
 
     pub fn parse(data: &str) -> Result<MemoryPressure, StatsError> {
         let (some, full) = parse_pressure_data(data)?;
-        Ok(MemoryPressure { some, full })
+        Ok(MemoryPressure {
+            some: some.total,
+            full: full.total,
+            some_avg: some.avg,
+            full_avg: full.avg,
+        })
     }
 }
 
 impl PressureStats for MemoryPressure {
     fn some_us(&self) -> u64 { self.some }
     fn full_us(&self) -> u64 { self.full }
+    fn some_avg(&self) -> &PressureAverages { &self.some_avg }
+    fn full_avg(&self) -> &PressureAverages { &self.full_avg }
 }
 
 impl StatType for MemoryPressure {
+    type Params = Option<String>;
+
     fn name() -> &'static str {
         MEMORY_PATH
     }
 
+    fn instance_name(cgroup: &Option<String>) -> String {
+        pressure_path(MEMORY_PATH, "memory.pressure", cgroup)
+    }
+
     fn new_zero() -> Self {
         Default::default()
     }
 
-    fn update(&mut self) -> Result<(), crate::stats::StatsError> {
-        *self = Self::read()?;
+    fn update(&mut self, cgroup: &Option<String>) -> Result<(), crate::stats::StatsError> {
+        *self = Self::read(cgroup)?;
         Ok(())
     }
 }
 
+/// The path of a pressure file: either the system-wide file in /proc/pressure, or the file with
+/// the given name in a cgroup directory.
+///
+/// The cgroup path is relative to /sys/fs/cgroup, and None selects the system-wide file.
+fn pressure_path(system_path: &str, file_name: &str, cgroup: &Option<String>) -> String {
+    match cgroup.as_deref() {
+        None => system_path.to_string(),
+        Some("") => format!("{}/{}", CGROUP_PATH, file_name),
+        Some(cgroup) => format!("{}/{}/{}", CGROUP_PATH, cgroup, file_name),
+    }
+}
+
 fn read_pressure_file(path_str: &str) -> Result<String, std::io::Error> {
-    read_to_string_with_limit(Path::new(path_str), 4096)
+    read_to_string_with_limit(&system_path(path_str), 4096)
 }
 
-fn parse_pressure_data(data: &str) -> Result<(u64, u64), StatsError> {
-    let mut some: u64 = 0;
-    let mut full: u64 = 0;
+fn parse_pressure_data(data: &str) -> Result<(PressureLine, PressureLine), StatsError> {
+    let mut some = PressureLine::default();
+    let mut full = PressureLine::default();
     for (_index, line) in data.split('\n').enumerate() {
         if let Some(data) = line.strip_prefix("some ") {
             some = parse_pressure_line(data)?;
@@ -140,18 +222,39 @@ fn parse_pressure_data(data: &str) -> Result<(u64, u64), StatsError> {
     Ok((some, full))
 }
 
-fn parse_pressure_line(data: &str) -> Result<u64, StatsError> {
-    const TOTAL_PREFIX: &str = "total=";
-    if let Some(start) = data.find(TOTAL_PREFIX) {
-        let data = &data[start + TOTAL_PREFIX.len()..];
-        return data
-            .parse::<u64>()
-            .map_err(|_| StatsError::ParseError("invalid integer in Linux PSI file".to_string()));
+fn parse_pressure_line(data: &str) -> Result<PressureLine, StatsError> {
+    // The format is "avg10=0.00 avg60=0.08 avg300=0.42 total=2062279"
+    let mut line = PressureLine::default();
+    let mut have_total = false;
+    for field in data.split_ascii_whitespace() {
+        let Some((key, value)) = field.split_once('=') else {
+            continue;
+        };
+        let parse_avg = |value: &str| {
+            value.parse::<f64>().map_err(|_| {
+                StatsError::ParseError("invalid average in Linux PSI file".to_string())
+            })
+        };
+        match key {
+            "avg10" => line.avg.avg10 = parse_avg(value)?,
+            "avg60" => line.avg.avg60 = parse_avg(value)?,
+            "avg300" => line.avg.avg300 = parse_avg(value)?,
+            "total" => {
+                line.total = value.parse::<u64>().map_err(|_| {
+                    StatsError::ParseError("invalid integer in Linux PSI file".to_string())
+                })?;
+                have_total = true;
+            }
+            _ => {}
+        }
+    }
+    if !have_total {
+        return Err(StatsError::ParseError(format!(
+            "unparseable Linux PSI line: {:?}",
+            data
+        )));
     }
-    Err(StatsError::ParseError(format!(
-        "unparseable Linux PSI line: {:?}",
-        data
-    )))
+    Ok(line)
 }
 
 #[cfg(test)]
@@ -165,10 +268,41 @@ mod tests {
 
         assert_eq!(s.some, 2062279);
         assert_eq!(s.full, 1895827);
+        assert_eq!(
+            s.some_avg,
+            PressureAverages {
+                avg10: 0.0,
+                avg60: 0.08,
+                avg300: 0.42
+            }
+        );
+        assert_eq!(s.full_avg, PressureAverages::default());
+
+        assert!(CpuPressure::parse("some avg10=0.00 avg60=0.00\n").is_err());
 
         Ok(())
     }
 
+    #[test]
+    fn test_pressure_path() {
+        assert_eq!(
+            pressure_path(IO_PATH, "io.pressure", &None),
+            "/proc/pressure/io"
+        );
+        assert_eq!(
+            pressure_path(IO_PATH, "io.pressure", &Some(String::new())),
+            "/sys/fs/cgroup/io.pressure"
+        );
+        assert_eq!(
+            pressure_path(
+                CPU_PATH,
+                "cpu.pressure",
+                &Some("user.slice/user-1000.slice".to_string())
+            ),
+            "/sys/fs/cgroup/user.slice/user-1000.slice/cpu.pressure"
+        );
+    }
+
     const TEST_DATA: &str = r#"
 some avg10=0.00 avg60=0.08 avg300=0.42 total=2062279
 full avg10=0.00 avg60=0.00 avg300=0.00 total=1895827
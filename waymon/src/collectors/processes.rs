@@ -0,0 +1,291 @@
+use crate::read::{read_to_string_with_limit, system_path};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::error;
+
+const PROC_PATH: &str = "/proc";
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("invalid integer")]
+    ParseIntError(#[from] std::num::ParseIntError),
+    #[error("missing command name")]
+    NoCommand,
+    #[error("missing field")]
+    MissingField,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Process {
+    pub pid: u32,
+    // The command name.  The kernel truncates this to 15 characters.
+    pub comm: String,
+    // The CPU time used in user and kernel mode, in clock ticks
+    pub cpu_ticks: u64,
+    // When the process started, in clock ticks since boot.  Process IDs get reused, so this is
+    // used to tell whether a pid still refers to the same process.
+    pub start_time: u64,
+    // The resident set size, in bytes.  This is 0 for kernel threads.
+    pub rss_bytes: u64,
+}
+
+impl Process {
+    /// Parse the contents of /proc/[pid]/stat.
+    ///
+    /// The RSS is not filled in, since it is read from /proc/[pid]/status instead.
+    fn parse_stat(data: &str) -> Result<Process, ParseError> {
+        // The format is "<pid> (<comm>) <state> <ppid> ...".  The command name may itself contain
+        // spaces and parentheses, so it extends up to the last ')' in the line.
+        let (pid, rest) = data.split_once(" (").ok_or(ParseError::NoCommand)?;
+        let (comm, fields) = rest.rsplit_once(')').ok_or(ParseError::NoCommand)?;
+        let fields: Vec<&str> = fields.split_ascii_whitespace().collect();
+        // Field indices here are offset by 3 from the field numbers in proc(5), since the pid,
+        // command name, and state come first.
+        let field = |idx: usize| -> Result<u64, ParseError> {
+            Ok(fields.get(idx).ok_or(ParseError::MissingField)?.parse()?)
+        };
+        let utime = field(11)?;
+        let stime = field(12)?;
+        Ok(Process {
+            pid: pid.trim().parse()?,
+            comm: comm.to_string(),
+            cpu_ticks: utime + stime,
+            start_time: field(19)?,
+            rss_bytes: 0,
+        })
+    }
+
+    /// Parse the VmRSS line from /proc/[pid]/status.
+    ///
+    /// Kernel threads do not have this line, so this returns None for them.
+    fn parse_status_rss(data: &str) -> Option<u64> {
+        let line = data.lines().find_map(|line| line.strip_prefix("VmRSS:"))?;
+        let kb: u64 = line.trim().strip_suffix("kB")?.trim().parse().ok()?;
+        Some(kb * 1024)
+    }
+
+    fn read_from(dir: &Path) -> Result<Process, std::io::Error> {
+        let stat_path = dir.join("stat");
+        let data = read_to_string_with_limit(&stat_path, 4096)?;
+        let mut process = Process::parse_stat(&data).map_err(|e| {
+            static PARSE_ERROR_LOG: std::sync::Once = std::sync::Once::new();
+            PARSE_ERROR_LOG.call_once(|| {
+                error!("{}: {:?}", stat_path.display(), e);
+            });
+            std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+        })?;
+        let status = read_to_string_with_limit(&dir.join("status"), 64 * 1024)?;
+        process.rss_bytes = Process::parse_status_rss(&status).unwrap_or(0);
+        Ok(process)
+    }
+}
+
+/// The resource usage of one process between two samples.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcessUsage {
+    pub pid: u32,
+    pub comm: String,
+    // The CPU usage, as a percentage of one CPU
+    pub cpu_percent: f64,
+    pub rss_bytes: u64,
+    // How much the resident set size changed since the older sample
+    pub rss_delta: i64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ProcessStats {
+    // The running processes, sorted by pid
+    pub processes: Vec<Process>,
+    // The kernel's clock tick rate, used to convert CPU ticks to time.
+    // This is 0 if no processes have been read yet.
+    pub ticks_per_second: u64,
+}
+
+impl ProcessStats {
+    pub fn read() -> Result<Self, std::io::Error> {
+        Self::read_from(&system_path(PROC_PATH))
+    }
+
+    pub fn read_from(dir: &Path) -> Result<Self, std::io::Error> {
+        let mut processes = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let is_pid = entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.bytes().all(|b| b.is_ascii_digit()));
+            if !is_pid {
+                continue;
+            }
+            // Processes may exit while we are scanning, so just skip any that can't be read
+            if let Ok(process) = Process::read_from(&entry.path()) {
+                processes.push(process);
+            }
+        }
+        processes.sort_by_key(|p| p.pid);
+        Ok(Self {
+            processes,
+            ticks_per_second: clock_ticks_per_second(),
+        })
+    }
+
+    pub fn get(&self, pid: u32) -> Option<&Process> {
+        let idx = self.processes.binary_search_by_key(&pid, |p| p.pid).ok()?;
+        Some(&self.processes[idx])
+    }
+
+    /// Compute each process's usage since an older sample, taken `elapsed` before this one.
+    ///
+    /// Processes that started since the older sample count all of their CPU time.  If the older
+    /// sample is empty, CPU usage is reported as 0, since there is nothing to compare against.
+    pub fn usage_since(&self, old: &ProcessStats, elapsed: Duration) -> Vec<ProcessUsage> {
+        let cpu_secs = elapsed.as_secs_f64() * self.ticks_per_second as f64;
+        self.processes
+            .iter()
+            .map(|process| {
+                let old_process = old
+                    .get(process.pid)
+                    .filter(|p| p.start_time == process.start_time);
+                let (old_ticks, old_rss) = match old_process {
+                    Some(p) => (p.cpu_ticks, p.rss_bytes),
+                    None => (0, 0),
+                };
+                let cpu_percent = if old.ticks_per_second == 0 || cpu_secs <= 0.0 {
+                    0.0
+                } else {
+                    100.0 * process.cpu_ticks.saturating_sub(old_ticks) as f64 / cpu_secs
+                };
+                ProcessUsage {
+                    pid: process.pid,
+                    comm: process.comm.clone(),
+                    cpu_percent,
+                    rss_bytes: process.rss_bytes,
+                    rss_delta: process.rss_bytes as i64 - old_rss as i64,
+                }
+            })
+            .collect()
+    }
+}
+
+fn clock_ticks_per_second() -> u64 {
+    // SAFETY: sysconf() has no memory safety requirements
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    // Linux has used 100 ticks per second for userspace on all architectures for a long time
+    if ticks > 0 {
+        ticks as u64
+    } else {
+        100
+    }
+}
+
+impl crate::stats::StatType for ProcessStats {
+    type Params = ();
+
+    fn name() -> &'static str {
+        PROC_PATH
+    }
+
+    fn new_zero() -> Self {
+        Default::default()
+    }
+
+    fn update(&mut self, _params: &()) -> Result<(), crate::stats::StatsError> {
+        *self = Self::read()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read::FixtureDir;
+    use anyhow::Result;
+
+    fn stat_line(pid: u32, comm: &str, utime: u64, stime: u64, start_time: u64) -> String {
+        format!(
+            "{} ({}) S 1 {} {} 0 -1 4194560 1234 0 0 0 {} {} 0 0 20 0 1 0 {} 12345678 \
+             512 18446744073709551615 1 1 0 0 0 0 0 4096 0 0 0 0 17 3 0 0 0 0 0\n",
+            pid, comm, pid, pid, utime, stime, start_time
+        )
+    }
+
+    #[test]
+    fn test_read() -> Result<()> {
+        let fixture = FixtureDir::with_files(&[
+            ("1/stat", stat_line(1, "systemd", 150, 50, 1)),
+            (
+                "1/status",
+                "Name:\tsystemd\nVmRSS:\t   12000 kB\nThreads:\t1\n".into(),
+            ),
+            ("42/stat", stat_line(42, "Web Content (x)", 500, 100, 9000)),
+            (
+                "42/status",
+                "Name:\tWeb Content\nVmRSS:\t  204800 kB\n".into(),
+            ),
+            // Kernel threads have no RSS
+            ("2/stat", stat_line(2, "kthreadd", 0, 3, 2)),
+            ("2/status", "Name:\tkthreadd\nThreads:\t1\n".into()),
+            // Non-numeric entries and processes that exited mid-scan are skipped
+            ("self/stat", stat_line(42, "waymon", 1, 1, 1)),
+            ("77/status", "Name:\tgone\n".into()),
+        ]);
+
+        let stats = ProcessStats::read_from(fixture.path())?;
+
+        let pids: Vec<u32> = stats.processes.iter().map(|p| p.pid).collect();
+        assert_eq!(pids, vec![1, 2, 42]);
+        assert_eq!(
+            stats.get(42),
+            Some(&Process {
+                pid: 42,
+                comm: "Web Content (x)".to_string(),
+                cpu_ticks: 600,
+                start_time: 9000,
+                rss_bytes: 204800 * 1024,
+            })
+        );
+        assert_eq!(stats.get(1).unwrap().rss_bytes, 12000 * 1024);
+        assert_eq!(stats.get(2).unwrap().rss_bytes, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_usage() {
+        let process = |pid: u32, cpu_ticks: u64, start_time: u64, rss_bytes: u64| Process {
+            pid,
+            comm: format!("p{}", pid),
+            cpu_ticks,
+            start_time,
+            rss_bytes,
+        };
+        let old = ProcessStats {
+            processes: vec![process(1, 100, 1, 1000), process(5, 900, 50, 4000)],
+            ticks_per_second: 100,
+        };
+        let new = ProcessStats {
+            // pid 5 exited and was reused by a new process
+            processes: vec![
+                process(1, 300, 1, 1500),
+                process(5, 20, 800, 2000),
+                process(9, 50, 900, 500),
+            ],
+            ticks_per_second: 100,
+        };
+
+        let usage = new.usage_since(&old, Duration::from_secs(2));
+        let summary: Vec<(u32, f64, i64)> = usage
+            .iter()
+            .map(|u| (u.pid, u.cpu_percent, u.rss_delta))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(1, 100.0, 500), (5, 10.0, 2000), (9, 25.0, 500)]
+        );
+
+        // There is nothing to compare against before the first sample
+        let usage = new.usage_since(&ProcessStats::default(), Duration::from_secs(2));
+        assert!(usage.iter().all(|u| u.cpu_percent == 0.0));
+    }
+}
@@ -1,5 +1,5 @@
-use crate::read::read_to_string_with_limit;
-use std::path::Path;
+use crate::read::{read_to_string_with_limit, system_path};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::error;
 
@@ -18,13 +18,20 @@ pub enum ParseError {
     MissingCpuField,
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Ticks(u64);
 
 impl Ticks {
     pub fn value(&self) -> u64 {
         self.0
     }
+
+    /// Compute the number of ticks elapsed since an older sample of the same counter.
+    ///
+    /// This handles the counter going backwards, as described in stats::counter_delta().
+    pub fn delta_since(self, old: Ticks) -> Ticks {
+        Ticks(crate::stats::counter_delta(self.0, old.0))
+    }
 }
 
 impl std::ops::Add for Ticks {
@@ -51,8 +58,11 @@ impl std::ops::Div for Ticks {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CpuStats {
+    // The N from the cpuN line.  This is 0 for the aggregate cpu line.
+    #[serde(default)]
+    pub id: u64,
     pub user: Ticks,
     pub nice: Ticks,
     pub system: Ticks,
@@ -68,6 +78,7 @@ pub struct CpuStats {
 impl CpuStats {
     pub fn zero() -> CpuStats {
         CpuStats {
+            id: 0,
             user: Ticks(0),
             nice: Ticks(0),
             system: Ticks(0),
@@ -119,9 +130,11 @@ impl CpuStats {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ProcStat {
     pub cpu: CpuStats,
+    // The individual CPUs, in the order listed.  Offline CPUs are not listed, so a CPU's position
+    // here may not match its ID.
     pub cpus: Vec<CpuStats>,
     pub num_forks: u64,
     pub num_context_switches: u64,
@@ -131,6 +144,8 @@ pub struct ProcStat {
 }
 
 impl crate::stats::StatType for ProcStat {
+    type Params = ();
+
     fn name() -> &'static str {
         PROC_STAT_PATH
     }
@@ -147,7 +162,7 @@ impl crate::stats::StatType for ProcStat {
         }
     }
 
-    fn update(&mut self) -> Result<(), crate::stats::StatsError> {
+    fn update(&mut self, _params: &()) -> Result<(), crate::stats::StatsError> {
         *self = Self::read()?;
         Ok(())
     }
@@ -155,7 +170,7 @@ impl crate::stats::StatType for ProcStat {
 
 impl ProcStat {
     pub fn read() -> Result<ProcStat, std::io::Error> {
-        let data = read_to_string_with_limit(Path::new(PROC_STAT_PATH), 1024 * 1024)?;
+        let data = read_to_string_with_limit(&system_path(PROC_STAT_PATH), 1024 * 1024)?;
         Ok(Self::parse(&data))
     }
 
@@ -219,11 +234,11 @@ impl ProcStat {
                 // Avoid allocating a huge array if we get a crazy CPU number
                 return Err(ParseError::MaxCpuCountExceeded);
             }
-            let cpu_index = cpu_id as usize;
-            if cpu_index >= self.cpus.len() {
-                self.cpus.resize_with(cpu_index + 1, || CpuStats::zero())
-            }
-            self.cpus[cpu_index].parse(data)
+            let mut cpu = CpuStats::zero();
+            cpu.id = cpu_id;
+            cpu.parse(data)?;
+            self.cpus.push(cpu);
+            Ok(())
         }
     }
 }
@@ -276,6 +291,13 @@ mod tests {
         assert_eq!(cpu11.steal, Ticks(0));
         assert_eq!(cpu11.guest, Ticks(0));
         assert_eq!(cpu11.guest_nice, Ticks(0));
+        assert_eq!(cpu11.id, 11);
+
+        // Offline CPUs are missing from /proc/stat
+        let ps = ProcStat::parse("cpu  300 0 300 9000\ncpu0 100 0 100 3000\ncpu2 200 0 200 6000\n");
+        let ids: Vec<u64> = ps.cpus.iter().map(|cpu| cpu.id).collect();
+        assert_eq!(ids, [0, 2]);
+        assert_eq!(ps.cpus[1].user, Ticks(200));
     }
 
     const TEST_DATA: &str = r#"
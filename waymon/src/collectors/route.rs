@@ -0,0 +1,243 @@
+use crate::read::{read_to_string_with_limit, system_path};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::error;
+
+const IPV4_PATH: &str = "/proc/net/route";
+const IPV6_PATH: &str = "/proc/net/ipv6_route";
+
+// Route flags, from linux/route.h
+const RTF_UP: u32 = 0x0001;
+const RTF_REJECT: u32 = 0x0200;
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("invalid hex integer")]
+    ParseIntError(#[from] std::num::ParseIntError),
+    #[error("missing field in route line")]
+    MissingField,
+}
+
+/// The interfaces carrying the default IPv4 and IPv6 routes
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct DefaultRouteStats {
+    pub ipv4: Option<String>,
+    pub ipv6: Option<String>,
+}
+
+// A candidate default route.
+//
+// Besides a real default route (a prefix length of 0), VPN clients often add a pair of more
+// specific routes that together cover the whole address space, so that they take priority over
+// the existing default route without replacing it.  For example, OpenVPN's "def1" option adds
+// 0.0.0.0/1 and 128.0.0.0/1 routes.  These are treated as default routes too, with the most
+// specific route winning just as it does for routing decisions.
+//
+// Policy routing in other tables (e.g., the fwmark-based routing set up by wg-quick) is not
+// visible in /proc/net/route, and is not detected.
+struct DefaultRoute {
+    iface: String,
+    prefix_len: u32,
+    metric: u32,
+}
+
+impl DefaultRoute {
+    // Replace `best` with this route if it is more specific, or has a lower metric
+    fn update_best(self, best: &mut Option<DefaultRoute>) {
+        match best {
+            Some(b)
+                if b.prefix_len > self.prefix_len
+                    || (b.prefix_len == self.prefix_len && b.metric <= self.metric) => {}
+            _ => *best = Some(self),
+        }
+    }
+}
+
+impl DefaultRouteStats {
+    pub fn read() -> Result<DefaultRouteStats, std::io::Error> {
+        let ipv4_data = read_to_string_with_limit(&system_path(IPV4_PATH), 10 * 1024 * 1024)?;
+        // The IPv6 route file does not exist if IPv6 is disabled
+        let ipv6_data = match read_to_string_with_limit(&system_path(IPV6_PATH), 10 * 1024 * 1024) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Self::parse(&ipv4_data, &ipv6_data))
+    }
+
+    pub fn parse(ipv4_data: &str, ipv6_data: &str) -> DefaultRouteStats {
+        DefaultRouteStats {
+            ipv4: Self::parse_routes(ipv4_data, IPV4_PATH, Self::parse_ipv4_line),
+            ipv6: Self::parse_routes(ipv6_data, IPV6_PATH, Self::parse_ipv6_line),
+        }
+    }
+
+    /// Returns the interface carrying the default route, preferring IPv4 if both are present.
+    pub fn default_interface(&self) -> Option<&str> {
+        self.ipv4.as_deref().or(self.ipv6.as_deref())
+    }
+
+    fn parse_routes(
+        data: &str,
+        path: &str,
+        parse_line: fn(&str) -> Result<Option<DefaultRoute>, ParseError>,
+    ) -> Option<String> {
+        let mut best: Option<DefaultRoute> = None;
+        for (index, line) in data.split('\n').enumerate() {
+            match parse_line(line) {
+                Ok(Some(route)) => route.update_best(&mut best),
+                Ok(None) => {}
+                Err(e) => {
+                    static PARSE_ERROR_LOG: std::sync::Once = std::sync::Once::new();
+                    PARSE_ERROR_LOG.call_once(|| {
+                        error!("{}:{} {:?}", path, index + 1, e);
+                    });
+                }
+            }
+        }
+        best.map(|route| route.iface)
+    }
+
+    // Lines in /proc/net/route look like:
+    //   Iface Destination Gateway Flags RefCnt Use Metric Mask MTU Window IRTT
+    // with the addresses and flags in hex.
+    fn parse_ipv4_line(line: &str) -> Result<Option<DefaultRoute>, ParseError> {
+        if line.is_empty() || line.starts_with("Iface") {
+            return Ok(None);
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 8 {
+            return Err(ParseError::MissingField);
+        }
+        let flags = u32::from_str_radix(fields[3], 16)?;
+        let metric = fields[6].parse::<u32>()?;
+        // The mask is in network byte order, but the number of bits set is the same either way
+        let prefix_len = u32::from_str_radix(fields[7], 16)?.count_ones();
+        // Any /1 route covers half of the address space
+        if prefix_len > 1 || !is_usable(flags) {
+            return Ok(None);
+        }
+        Ok(Some(DefaultRoute {
+            iface: fields[0].to_string(),
+            prefix_len,
+            metric,
+        }))
+    }
+
+    // Lines in /proc/net/ipv6_route look like:
+    //   dest dest_prefix_len src src_prefix_len next_hop metric refcnt use flags iface
+    // with all numeric fields in hex.
+    fn parse_ipv6_line(line: &str) -> Result<Option<DefaultRoute>, ParseError> {
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 10 {
+            return Err(ParseError::MissingField);
+        }
+        let prefix_len = u32::from_str_radix(fields[1], 16)?;
+        let metric = u32::from_str_radix(fields[5], 16)?;
+        let flags = u32::from_str_radix(fields[8], 16)?;
+        // Besides ::/0, VPN clients may route all global unicast addresses (2000::/3) through
+        // the tunnel, e.g. with a pair of 2000::/4 and 3000::/4 routes.
+        let is_default = match prefix_len {
+            0 => true,
+            1..=4 => fields[0].starts_with('2') || fields[0].starts_with('3'),
+            _ => false,
+        };
+        if !is_default || !is_usable(flags) || fields[9] == "lo" {
+            return Ok(None);
+        }
+        Ok(Some(DefaultRoute {
+            iface: fields[9].to_string(),
+            prefix_len,
+            metric,
+        }))
+    }
+}
+
+fn is_usable(flags: u32) -> bool {
+    (flags & RTF_UP) != 0 && (flags & RTF_REJECT) == 0
+}
+
+impl crate::stats::StatType for DefaultRouteStats {
+    type Params = ();
+
+    fn name() -> &'static str {
+        IPV4_PATH
+    }
+
+    fn new_zero() -> Self {
+        Default::default()
+    }
+
+    fn update(&mut self, _params: &()) -> Result<(), crate::stats::StatsError> {
+        *self = Self::read()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IPV4_TEST_DATA: &str = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlp0s20f3\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0
+wlp0s20f3\t0001A8C0\t00000000\t0001\t0\t0\t600\t00FFFFFF\t0\t0\t0
+enx00e04c680001\t00000000\t0100000A\t0003\t0\t0\t100\t00000000\t0\t0\t0
+docker0\t000011AC\t00000000\t0001\t0\t0\t0\t0000FFFF\t0\t0\t0
+";
+
+    const IPV6_TEST_DATA: &str = "\
+fd000000000000000000000000000000 40 00000000000000000000000000000000 00 00000000000000000000000000000000 00000100 00000001 00000000 00000001 wlp0s20f3
+00000000000000000000000000000000 00 00000000000000000000000000000000 00 00000000000000000000000000000000 ffffffff 00000001 00000000 00200200       lo
+00000000000000000000000000000000 00 00000000000000000000000000000000 00 fd000000000000000000000000000001 00000400 00000001 00000000 00000003 wlp0s20f3
+";
+
+    #[test]
+    fn test_parse() {
+        let stats = DefaultRouteStats::parse(IPV4_TEST_DATA, IPV6_TEST_DATA);
+        // The ethernet route has a lower metric than the wifi route
+        assert_eq!(stats.ipv4.as_deref(), Some("enx00e04c680001"));
+        // The reject route on lo should be ignored
+        assert_eq!(stats.ipv6.as_deref(), Some("wlp0s20f3"));
+        assert_eq!(stats.default_interface(), Some("enx00e04c680001"));
+
+        let stats = DefaultRouteStats::parse("", IPV6_TEST_DATA);
+        assert_eq!(stats.ipv4, None);
+        assert_eq!(stats.default_interface(), Some("wlp0s20f3"));
+
+        let stats = DefaultRouteStats::parse("", "");
+        assert_eq!(stats.default_interface(), None);
+    }
+
+    #[test]
+    fn test_parse_vpn_routes() {
+        // OpenVPN with "redirect-gateway def1" keeps the original default route, and adds
+        // 0.0.0.0/1 and 128.0.0.0/1 routes through the tunnel.
+        let ipv4_data = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlp0s20f3\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0
+tun0\t00000000\t0100080A\t0003\t0\t0\t0\t00000080\t0\t0\t0
+tun0\t00080800\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0
+tun0\t00000080\t0100080A\t0003\t0\t0\t0\t00000080\t0\t0\t0
+wlp0s20f3\t0001A8C0\t00000000\t0001\t0\t0\t600\t00FFFFFF\t0\t0\t0
+";
+        let ipv6_data = "\
+00000000000000000000000000000000 00 00000000000000000000000000000000 00 fd000000000000000000000000000001 00000400 00000001 00000000 00000003 wlp0s20f3
+20000000000000000000000000000000 04 00000000000000000000000000000000 00 00000000000000000000000000000000 00000400 00000001 00000000 00000001 tun0
+30000000000000000000000000000000 04 00000000000000000000000000000000 00 00000000000000000000000000000000 00000400 00000001 00000000 00000001 tun0
+fe800000000000000000000000000000 40 00000000000000000000000000000000 00 00000000000000000000000000000000 00000100 00000001 00000000 00000001 wlp0s20f3
+";
+        let stats = DefaultRouteStats::parse(ipv4_data, ipv6_data);
+        assert_eq!(stats.ipv4.as_deref(), Some("tun0"));
+        assert_eq!(stats.ipv6.as_deref(), Some("tun0"));
+
+        // Without the tunnel routes the regular default route is used
+        let stats = DefaultRouteStats::parse(IPV4_TEST_DATA, "");
+        assert_eq!(stats.ipv4.as_deref(), Some("enx00e04c680001"));
+    }
+}
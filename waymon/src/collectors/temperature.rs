@@ -0,0 +1,224 @@
+use crate::read::{read_sysfs_string, system_path};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const HWMON_PATH: &str = "/sys/class/hwmon";
+const THERMAL_PATH: &str = "/sys/class/thermal";
+
+/// The chip name reported for sensors from /sys/class/thermal
+pub const THERMAL_ZONE_CHIP: &str = "thermal";
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct TemperatureSensor {
+    // The hwmon chip name (e.g., "coretemp" or "nvme"), or "thermal" for thermal zones.
+    pub chip: String,
+    // The sensor label.  For hwmon sensors without a label file this is the sensor name (e.g.,
+    // "temp1").  For thermal zones this is the zone type (e.g., "x86_pkg_temp").
+    pub label: String,
+    // The current temperature, in degrees Celsius
+    pub temp: f64,
+    // The critical temperature threshold in degrees Celsius, if the kernel reports one
+    pub crit: Option<f64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TemperatureStats {
+    pub sensors: Vec<TemperatureSensor>,
+}
+
+impl TemperatureStats {
+    pub fn read() -> Result<Self, std::io::Error> {
+        Self::read_from(&system_path(HWMON_PATH), &system_path(THERMAL_PATH))
+    }
+
+    pub fn read_from(hwmon_dir: &Path, thermal_dir: &Path) -> Result<Self, std::io::Error> {
+        let mut stats = Self::default();
+        for chip_dir in numbered_entries(hwmon_dir, "hwmon")? {
+            stats.read_hwmon_chip(&chip_dir);
+        }
+        for zone_dir in numbered_entries(thermal_dir, "thermal_zone")? {
+            stats.read_thermal_zone(&zone_dir);
+        }
+        Ok(stats)
+    }
+
+    fn read_hwmon_chip(&mut self, dir: &Path) {
+        let Some(chip) = read_sysfs_string(&dir.join("name")) else {
+            return;
+        };
+
+        // Sensors are reported in files named temp<N>_input
+        let mut sensor_names: Vec<(u32, String)> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter_map(|file_name| {
+                    let name = file_name.strip_suffix("_input")?;
+                    let index = name.strip_prefix("temp")?.parse::<u32>().ok()?;
+                    Some((index, name.to_string()))
+                })
+                .collect(),
+            Err(_) => return,
+        };
+        sensor_names.sort();
+
+        for (_index, name) in sensor_names {
+            // Reading the input can fail for sensors that are present but currently
+            // unavailable.  Just skip these.
+            let Some(temp) = read_millidegrees(&dir.join(format!("{}_input", name))) else {
+                continue;
+            };
+            let label = read_sysfs_string(&dir.join(format!("{}_label", name)))
+                .unwrap_or_else(|| name.clone());
+            let crit = read_millidegrees(&dir.join(format!("{}_crit", name)));
+            self.sensors.push(TemperatureSensor {
+                chip: chip.clone(),
+                label,
+                temp,
+                crit,
+            });
+        }
+    }
+
+    fn read_thermal_zone(&mut self, dir: &Path) {
+        let Some(label) = read_sysfs_string(&dir.join("type")) else {
+            return;
+        };
+        let Some(temp) = read_millidegrees(&dir.join("temp")) else {
+            return;
+        };
+
+        // Use the critical trip point as the critical threshold, if there is one
+        let mut crit = None;
+        for index in 0.. {
+            let type_path = dir.join(format!("trip_point_{}_type", index));
+            let Some(trip_type) = read_sysfs_string(&type_path) else {
+                break;
+            };
+            if trip_type == "critical" {
+                crit = read_millidegrees(&dir.join(format!("trip_point_{}_temp", index)));
+                break;
+            }
+        }
+
+        self.sensors.push(TemperatureSensor {
+            chip: THERMAL_ZONE_CHIP.to_string(),
+            label,
+            temp,
+            crit,
+        });
+    }
+}
+
+/// Return the entries in a directory named <prefix><N>, sorted by N.
+///
+/// Returns an empty list if the directory does not exist.
+fn numbered_entries(dir: &Path, prefix: &str) -> Result<Vec<PathBuf>, std::io::Error> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut result: Vec<(u32, PathBuf)> = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(index) = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix(prefix))
+            .and_then(|index| index.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        result.push((index, entry.path()));
+    }
+    result.sort();
+    Ok(result.into_iter().map(|(_index, path)| path).collect())
+}
+
+fn read_millidegrees(path: &Path) -> Option<f64> {
+    let value = read_sysfs_string(path)?.parse::<i64>().ok()?;
+    Some((value as f64) / 1000.0)
+}
+
+impl crate::stats::StatType for TemperatureStats {
+    type Params = ();
+
+    fn name() -> &'static str {
+        HWMON_PATH
+    }
+
+    fn new_zero() -> Self {
+        Default::default()
+    }
+
+    fn update(&mut self, _params: &()) -> Result<(), crate::stats::StatsError> {
+        *self = Self::read()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read::FixtureDir;
+    use anyhow::Result;
+
+    #[test]
+    fn test_read() -> Result<()> {
+        let fixture = FixtureDir::with_files(&[
+            ("hwmon/hwmon10/name", "nvme\n"),
+            ("hwmon/hwmon10/temp1_input", "38850\n"),
+            ("hwmon/hwmon10/temp1_label", "Composite\n"),
+            ("hwmon/hwmon10/temp1_crit", "84850\n"),
+            ("hwmon/hwmon2/name", "coretemp\n"),
+            ("hwmon/hwmon2/temp1_input", "45000\n"),
+            ("hwmon/hwmon2/temp1_label", "Package id 0\n"),
+            ("hwmon/hwmon2/temp1_crit", "100000\n"),
+            ("hwmon/hwmon2/temp10_input", "47000\n"),
+            ("hwmon/hwmon2/temp2_input", "43000\n"),
+            // A sensor whose input cannot be parsed should be skipped
+            ("hwmon/hwmon2/temp3_input", "\n"),
+            // A chip without any temperature sensors
+            ("hwmon/hwmon3/name", "BAT0\n"),
+            ("hwmon/hwmon3/in0_input", "12000\n"),
+            ("thermal/thermal_zone0/type", "acpitz\n"),
+            ("thermal/thermal_zone0/temp", "27800\n"),
+            ("thermal/thermal_zone0/trip_point_0_type", "passive\n"),
+            ("thermal/thermal_zone0/trip_point_0_temp", "90000\n"),
+            ("thermal/thermal_zone0/trip_point_1_type", "critical\n"),
+            ("thermal/thermal_zone0/trip_point_1_temp", "120000\n"),
+            ("thermal/cooling_device0/type", "Processor\n"),
+        ]);
+        let root = fixture.path();
+
+        let stats = TemperatureStats::read_from(&root.join("hwmon"), &root.join("thermal"))?;
+
+        let sensor = |chip: &str, label: &str, temp: f64, crit: Option<f64>| TemperatureSensor {
+            chip: chip.to_string(),
+            label: label.to_string(),
+            temp,
+            crit,
+        };
+        assert_eq!(
+            stats.sensors,
+            vec![
+                sensor("coretemp", "Package id 0", 45.0, Some(100.0)),
+                sensor("coretemp", "temp2", 43.0, None),
+                sensor("coretemp", "temp10", 47.0, None),
+                sensor("nvme", "Composite", 38.85, Some(84.85)),
+                sensor(THERMAL_ZONE_CHIP, "acpitz", 27.8, Some(120.0)),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_dirs() -> Result<()> {
+        let root = Path::new("/nonexistent/waymon/test");
+        let stats = TemperatureStats::read_from(&root.join("hwmon"), &root.join("thermal"))?;
+        assert!(stats.sensors.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+use crate::read::{read_to_string_with_limit, system_path};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::error;
+
+const PATH: &str = "/proc/vmstat";
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("invalid integer")]
+    ParseIntError(#[from] std::num::ParseIntError),
+}
+
+// The counters in /proc/vmstat count events since boot.  The swap and reclaim counters are in
+// pages.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VmStats {
+    pub pswpin: u64,
+    pub pswpout: u64,
+    // Page faults that required reading from disk
+    pub pgmajfault: u64,
+    // Pages reclaimed by the kswapd background threads, by allocating tasks that had to stop
+    // and reclaim memory themselves, and by khugepaged
+    pub pgsteal_kswapd: u64,
+    pub pgsteal_direct: u64,
+    pub pgsteal_khugepaged: u64,
+    // The number of times a task had to stall to compact memory for a higher-order allocation
+    pub compact_stall: u64,
+    // The number of processes killed by the OOM killer.  Only reported by Linux 4.13 and later.
+    pub oom_kill: u64,
+    // The system page size in bytes, used to convert page counts to bytes.
+    // This is 0 if the stats have not been read yet.
+    pub page_size: u64,
+}
+
+impl VmStats {
+    pub fn read() -> Result<Self, std::io::Error> {
+        let data = read_to_string_with_limit(&system_path(PATH), 1024 * 1024)?;
+        let mut stats = Self::parse(&data);
+        stats.page_size = page_size();
+        Ok(stats)
+    }
+
+    pub fn parse(data: &str) -> Self {
+        let mut s: Self = Default::default();
+        for (index, line) in data.split('\n').enumerate() {
+            if let Err(e) = s.parse_line(line) {
+                static PARSE_ERROR_LOG: std::sync::Once = std::sync::Once::new();
+                PARSE_ERROR_LOG.call_once(|| {
+                    error!("{}:{} {:?}", PATH, index + 1, e);
+                });
+            }
+        }
+        s
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), ParseError> {
+        // Each line is "<name> <value>".  There are many more counters than we track, and
+        // the set of counters varies between kernel versions.
+        let Some((name, value)) = line.split_once(' ') else {
+            return Ok(());
+        };
+        let value: u64 = value.trim().parse()?;
+        match name {
+            "pswpin" => self.pswpin = value,
+            "pswpout" => self.pswpout = value,
+            "pgmajfault" => self.pgmajfault = value,
+            "compact_stall" => self.compact_stall = value,
+            "oom_kill" => self.oom_kill = value,
+            // Kernels before 4.8 report the reclaim counters separately for each memory zone
+            // (e.g., "pgsteal_kswapd_normal"), so these are summed.
+            _ if is_counter(name, "pgsteal_kswapd") => self.pgsteal_kswapd += value,
+            _ if is_counter(name, "pgsteal_direct") => self.pgsteal_direct += value,
+            _ if is_counter(name, "pgsteal_khugepaged") => self.pgsteal_khugepaged += value,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if name is the given counter, or a per-zone version of it
+fn is_counter(name: &str, counter: &str) -> bool {
+    match name.strip_prefix(counter) {
+        Some(rest) => rest.is_empty() || rest.starts_with('_'),
+        None => false,
+    }
+}
+
+fn page_size() -> u64 {
+    // SAFETY: sysconf() has no memory safety requirements
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if size > 0 {
+        size as u64
+    } else {
+        4096
+    }
+}
+
+impl crate::stats::StatType for VmStats {
+    type Params = ();
+
+    fn name() -> &'static str {
+        PATH
+    }
+
+    fn new_zero() -> Self {
+        Default::default()
+    }
+
+    fn update(&mut self, _params: &()) -> Result<(), crate::stats::StatsError> {
+        *self = Self::read()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let s = VmStats::parse(TEST_DATA);
+        assert_eq!(s.pswpin, 31337);
+        assert_eq!(s.pswpout, 99821);
+        assert_eq!(s.pgmajfault, 27182);
+        assert_eq!(s.pgsteal_kswapd, 500);
+        assert_eq!(s.pgsteal_direct, 20);
+        assert_eq!(s.pgsteal_khugepaged, 0);
+        assert_eq!(s.compact_stall, 7);
+        assert_eq!(s.oom_kill, 2);
+
+        // Older kernels report reclaim per zone
+        let s = VmStats::parse(
+            "pgsteal_kswapd_dma32 100\npgsteal_kswapd_normal 250\npgsteal_direct_normal 3\n",
+        );
+        assert_eq!(s.pgsteal_kswapd, 350);
+        assert_eq!(s.pgsteal_direct, 3);
+        assert_eq!(s.oom_kill, 0);
+    }
+
+    const TEST_DATA: &str = r#"nr_free_pages 276775
+nr_zone_inactive_anon 434
+nr_zone_active_anon 1929096
+pgpgin 49851228
+pgpgout 117358896
+pswpin 31337
+pswpout 99821
+pgalloc_dma 1024
+pgfault 3141592653
+pgmajfault 27182
+pgsteal_kswapd 500
+pgsteal_direct 20
+pgsteal_khugepaged 0
+pgsteal_anon 400
+pgsteal_file 120
+pgscan_kswapd 900
+compact_stall 7
+compact_fail 3
+oom_kill 2
+"#;
+}
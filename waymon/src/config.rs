@@ -1,15 +1,25 @@
-use crate::widgets::cpu::CpuWidget;
+use crate::read::SystemRoots;
+use crate::widgets::cgroup::CgroupWidgetConfig;
+use crate::widgets::cpu::{default_cpu_categories, CpuSeriesConfig, CpuWidget};
+use crate::widgets::cpu_cores::CpuCoresWidgetConfig;
 use crate::widgets::disk_io::DiskIoWidgetConfig;
-use crate::widgets::mem::MemWidget;
+use crate::widgets::fs_usage::FsUsageWidgetConfig;
+use crate::widgets::mem::{default_mem_categories, MemSeriesConfig, MemWidget};
 use crate::widgets::net::NetWidget;
+use crate::widgets::power_supply::PowerSupplyWidgetConfig;
 use crate::widgets::pressure::{
     CpuPressureWidgetConfig, IoPressureWidgetConfig, MemPressureWidgetConfig,
 };
+use crate::widgets::swap::SwapWidgetConfig;
+use crate::widgets::temperature::TemperatureWidgetConfig;
+use crate::widgets::timeseries::TimeAxis;
+use crate::widgets::top::TopWidgetConfig;
+use crate::widgets::vmstat::VmstatWidgetConfig;
 use anyhow::{anyhow, Context, Result};
 use regex::Regex;
 use serde::{Deserialize, Deserializer};
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use waymon_widget_derive::WaymonWidgetConfig;
 
@@ -36,6 +46,10 @@ pub struct Config {
     pub interval: Duration,
     pub monitor_rules: Vec<MonitorRule>,
     pub bars: HashMap<String, BarConfig>,
+    // Alternate locations of /proc and /sys to read stats from
+    pub system_roots: SystemRoots,
+    // How long to wait for a collector before showing its stats as stale
+    pub collector_timeout: Duration,
 }
 
 impl Config {
@@ -43,6 +57,16 @@ impl Config {
         // TomlConfig.to_config() should ensure that there is always a primary config entry
         self.bars.get(PRIMARY_BAR_NAME).unwrap()
     }
+
+    /// The shortest update interval of any bar or widget.
+    ///
+    /// This is how often we need to wake up to check whether anything is due to be updated.
+    pub fn min_interval(&self) -> Duration {
+        self.bars
+            .values()
+            .flat_map(|bar| bar.widgets.iter().map(|w| bar.widget_interval(w)))
+            .fold(self.interval, std::cmp::min)
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -52,10 +76,19 @@ pub struct TomlConfig {
     #[serde(default = "default_interval")]
     #[serde(deserialize_with = "parse_duration")]
     pub interval: Duration,
+    #[serde(default = "default_collector_timeout")]
+    #[serde(deserialize_with = "parse_duration")]
+    pub collector_timeout: Duration,
+    #[serde(default, deserialize_with = "parse_optional_duration")]
+    pub gridlines: Option<Duration>,
     #[serde(default = "default_width")]
     pub width: u32,
     #[serde(default = "default_side")]
     pub side: Side,
+    #[serde(default)]
+    pub proc_root: Option<PathBuf>,
+    #[serde(default)]
+    pub sys_root: Option<PathBuf>,
     #[serde(default, rename = "monitor_rule")]
     pub monitor_rules: Vec<TomlMonitorRule>,
     #[serde(default, rename = "bar")]
@@ -102,7 +135,11 @@ impl TomlConfig {
         let bars: HashMap<String, BarConfig> = self
             .bars
             .drain()
-            .map(|(name, bc)| (name, bc.to_config(self.width, self.side)))
+            .map(|(name, bc)| {
+                let bar_config =
+                    bc.to_config(&name, self.width, self.side, self.interval, self.gridlines);
+                (name, bar_config)
+            })
             .collect();
         if bars.contains_key(NO_BAR_NAME) {
             return Err(anyhow!(
@@ -110,12 +147,34 @@ impl TomlConfig {
                 NO_BAR_NAME
             ));
         }
+        for (name, bar) in &bars {
+            let widget_intervals = bar.widgets.iter().filter_map(|w| w.interval);
+            if std::iter::once(bar.interval)
+                .chain(widget_intervals)
+                .any(|interval| interval.is_zero())
+            {
+                return Err(anyhow!("bar {:?}: update intervals must be non-zero", name));
+            }
+            if bar
+                .widgets
+                .iter()
+                .flat_map(|w| &w.window)
+                .any(|w| w.is_zero())
+            {
+                return Err(anyhow!("bar {:?}: chart windows must be non-zero", name));
+            }
+        }
 
         Ok(Config {
             mode: self.mode,
             interval: self.interval,
             monitor_rules: Self::convert_monitor_rules(&mut self.monitor_rules, &bars)?,
             bars: bars,
+            system_roots: SystemRoots {
+                proc_root: self.proc_root,
+                sys_root: self.sys_root,
+            },
+            collector_timeout: self.collector_timeout,
         })
     }
 
@@ -157,14 +216,16 @@ fn default_widgets() -> Vec<WidgetConfig> {
     // Provide a default set of widgets so that the bar isn't completely empty by default
     // if the config file is empty.
     vec![
-        WidgetConfig::Cpu(CpuWidgetConfig {
+        WidgetConfig::new(WidgetKind::Cpu(CpuWidgetConfig {
             label: "CPU".to_string(),
+            categories: default_cpu_categories(),
             height: default_chart_height(),
-        }),
-        WidgetConfig::Mem(MemWidgetConfig {
+        })),
+        WidgetConfig::new(WidgetKind::Mem(MemWidgetConfig {
             label: "Memory".to_string(),
+            categories: default_mem_categories(),
             height: default_chart_height(),
-        }),
+        })),
     ]
 }
 
@@ -176,6 +237,10 @@ fn default_interval() -> Duration {
     Duration::from_secs(1)
 }
 
+fn default_collector_timeout() -> Duration {
+    Duration::from_secs(2)
+}
+
 fn default_width() -> u32 {
     DEFAULT_WIDTH
 }
@@ -212,7 +277,7 @@ pub struct TomlMonitorRule {
     pub bar: Option<String>,
 }
 
-#[derive(Copy, Clone, Debug, Deserialize)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum Side {
     Right,
@@ -223,26 +288,65 @@ pub enum Side {
 
 #[derive(Debug)]
 pub struct BarConfig {
+    // The name of this bar configuration, used to save its chart history
+    pub name: String,
     pub width: u32,
     pub side: Side,
+    // The update interval for widgets that do not specify their own
+    pub interval: Duration,
+    // The time gridline spacing for widgets that do not specify their own
+    pub gridlines: Option<Duration>,
     pub widgets: Vec<WidgetConfig>,
 }
 
+impl BarConfig {
+    /// The update interval to use for a widget in this bar.
+    pub fn widget_interval(&self, widget: &WidgetConfig) -> Duration {
+        widget.interval.unwrap_or(self.interval)
+    }
+
+    /// The time axis to use for a widget's charts in this bar.
+    pub fn time_axis(&self, widget: &WidgetConfig) -> TimeAxis {
+        // By default our charts display one pixel per update interval, across the full width of
+        // the bar
+        let mut time_axis = TimeAxis::new(self.width as usize, self.widget_interval(widget));
+        if !widget.window.is_empty() {
+            time_axis.windows = widget.window.clone();
+        }
+        time_axis.gridlines = widget.gridlines.or(self.gridlines);
+        time_axis
+    }
+}
+
 #[derive(Debug, Default, Deserialize)]
 pub struct TomlBarConfig {
     #[serde(default)]
     pub width: Option<u32>,
     #[serde(default)]
     pub side: Option<Side>,
+    #[serde(default, deserialize_with = "parse_optional_duration")]
+    pub interval: Option<Duration>,
+    #[serde(default, deserialize_with = "parse_optional_duration")]
+    pub gridlines: Option<Duration>,
     #[serde(default, rename = "widget")]
     pub widgets: Vec<WidgetConfig>,
 }
 
 impl TomlBarConfig {
-    fn to_config(self, default_width: u32, default_side: Side) -> BarConfig {
+    fn to_config(
+        self,
+        name: &str,
+        default_width: u32,
+        default_side: Side,
+        default_interval: Duration,
+        default_gridlines: Option<Duration>,
+    ) -> BarConfig {
         BarConfig {
+            name: name.to_string(),
             width: self.width.unwrap_or(default_width),
             side: self.side.unwrap_or(default_side),
+            interval: self.interval.unwrap_or(default_interval),
+            gridlines: self.gridlines.or(default_gridlines),
             widgets: self.widgets,
         }
     }
@@ -252,59 +356,389 @@ pub trait WaymonWidgetConfig {
     fn create_widget(
         &self,
         all_stats: &mut crate::stats::AllStats,
-        history_length: usize,
+        time_axis: &TimeAxis,
     ) -> std::rc::Rc<std::cell::RefCell<dyn crate::widgets::Widget>>;
 }
 
-#[derive(Debug, Deserialize, WaymonWidgetConfig)]
+/// The configuration for one widget in a bar.
+///
+/// The settings common to all widget types are stored here, and the type-specific settings in
+/// `kind`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct WidgetConfig {
+    #[serde(flatten)]
+    pub kind: WidgetKind,
+    // How often to update this widget.  Defaults to the bar's interval.
+    #[serde(default, deserialize_with = "parse_optional_duration")]
+    pub interval: Option<Duration>,
+    // The spacing between vertical time gridlines in this widget's charts.  Defaults to the
+    // bar's setting.
+    #[serde(default, deserialize_with = "parse_optional_duration")]
+    pub gridlines: Option<Duration>,
+    // The time spans the widget's charts can show.  Scrolling over the chart switches between
+    // them.  Defaults to one pixel per update interval.
+    #[serde(default, deserialize_with = "parse_duration_list")]
+    pub window: Vec<Duration>,
+}
+
+impl WidgetConfig {
+    pub fn new(kind: WidgetKind) -> Self {
+        Self {
+            kind,
+            interval: None,
+            gridlines: None,
+            window: Vec::new(),
+        }
+    }
+
+    /// Identifies the widget when saving and restoring its chart history.
+    ///
+    /// This uses the widget's type and label, plus the settings that determine what each chart
+    /// series shows, so that changing the categories or devices of a widget does not show the old
+    /// data under the new series.  Other settings such as the height and interval can change
+    /// without losing the history.  Widgets in the same bar with the same ID are matched up in
+    /// order.
+    pub fn history_id(&self) -> String {
+        let series = self.kind.series_settings();
+        if series.is_empty() {
+            format!("{}:{}", self.kind.type_name(), self.kind.label())
+        } else {
+            format!(
+                "{}:{}:{}",
+                self.kind.type_name(),
+                self.kind.label(),
+                series.join(" ")
+            )
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, WaymonWidgetConfig)]
 #[serde(tag = "type")]
-pub enum WidgetConfig {
+pub enum WidgetKind {
     #[serde(rename = "cpu")]
     Cpu(CpuWidgetConfig),
+    #[serde(rename = "cpu_cores")]
+    CpuCores(CpuCoresWidgetConfig),
     #[serde(rename = "disk_io")]
     DiskIO(DiskIoWidgetConfig),
     #[serde(rename = "net")]
     Net(NetWidgetConfig),
     #[serde(rename = "mem")]
     Mem(MemWidgetConfig),
+    #[serde(rename = "swap")]
+    Swap(SwapWidgetConfig),
+    #[serde(rename = "vmstat")]
+    Vmstat(VmstatWidgetConfig),
     #[serde(rename = "cpu_pressure")]
     CpuPressure(CpuPressureWidgetConfig),
     #[serde(rename = "io_pressure")]
     IoPressure(IoPressureWidgetConfig),
     #[serde(rename = "mem_pressure")]
     MemPressure(MemPressureWidgetConfig),
+    #[serde(rename = "temperature")]
+    Temperature(TemperatureWidgetConfig),
+    #[serde(rename = "power_supply")]
+    PowerSupply(PowerSupplyWidgetConfig),
+    #[serde(rename = "fs_usage")]
+    FsUsage(FsUsageWidgetConfig),
+    #[serde(rename = "top")]
+    Top(TopWidgetConfig),
+    #[serde(rename = "cgroup")]
+    Cgroup(CgroupWidgetConfig),
+}
+
+impl WidgetKind {
+    /// The widget type, as it is named in the config file
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            WidgetKind::Cpu(_) => "cpu",
+            WidgetKind::CpuCores(_) => "cpu_cores",
+            WidgetKind::DiskIO(_) => "disk_io",
+            WidgetKind::Net(_) => "net",
+            WidgetKind::Mem(_) => "mem",
+            WidgetKind::Swap(_) => "swap",
+            WidgetKind::Vmstat(_) => "vmstat",
+            WidgetKind::CpuPressure(_) => "cpu_pressure",
+            WidgetKind::IoPressure(_) => "io_pressure",
+            WidgetKind::MemPressure(_) => "mem_pressure",
+            WidgetKind::Temperature(_) => "temperature",
+            WidgetKind::PowerSupply(_) => "power_supply",
+            WidgetKind::FsUsage(_) => "fs_usage",
+            WidgetKind::Top(_) => "top",
+            WidgetKind::Cgroup(_) => "cgroup",
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            WidgetKind::Cpu(c) => &c.label,
+            WidgetKind::CpuCores(c) => &c.label,
+            WidgetKind::DiskIO(c) => &c.label,
+            WidgetKind::Net(c) => &c.label,
+            WidgetKind::Mem(c) => &c.label,
+            WidgetKind::Swap(c) => &c.label,
+            WidgetKind::Vmstat(c) => &c.label,
+            WidgetKind::CpuPressure(c) => &c.label,
+            WidgetKind::IoPressure(c) => &c.label,
+            WidgetKind::MemPressure(c) => &c.label,
+            WidgetKind::Temperature(c) => &c.label,
+            WidgetKind::PowerSupply(c) => &c.label,
+            WidgetKind::FsUsage(c) => &c.label,
+            WidgetKind::Top(c) => &c.label,
+            WidgetKind::Cgroup(c) => &c.label,
+        }
+    }
+
+    /// The settings that determine what data each of the widget's chart series shows.
+    fn series_settings(&self) -> Vec<String> {
+        let pattern = |p: &Option<DevicePattern>| p.as_ref().map(|p| p.to_string());
+        let regex = |r: &Option<ConfigRegex>| r.as_ref().map(|r| r.0.as_str().to_string());
+        // Optional settings that are not set are left out
+        let settings: Vec<(&str, Option<String>)> = match self {
+            WidgetKind::Cpu(c) => vec![(
+                "categories",
+                Some(format!(
+                    "{:?}",
+                    c.categories.iter().map(|s| s.category).collect::<Vec<_>>()
+                )),
+            )],
+            WidgetKind::Mem(c) => vec![(
+                "categories",
+                Some(format!(
+                    "{:?}",
+                    c.categories.iter().map(|s| s.category).collect::<Vec<_>>()
+                )),
+            )],
+            WidgetKind::DiskIO(c) => vec![
+                ("disk", Some(c.disk.to_string())),
+                ("exclude", pattern(&c.exclude)),
+                ("exclude_partitions", Some(c.exclude_partitions.to_string())),
+                ("exclude_dm", Some(c.exclude_dm.to_string())),
+                ("display", Some(format!("{:?}", c.display))),
+            ],
+            WidgetKind::Net(c) => vec![
+                ("dev", Some(c.dev.to_string())),
+                ("exclude", pattern(&c.exclude)),
+            ],
+            WidgetKind::CpuPressure(CpuPressureWidgetConfig { cgroup, .. })
+            | WidgetKind::IoPressure(IoPressureWidgetConfig { cgroup, .. })
+            | WidgetKind::MemPressure(MemPressureWidgetConfig { cgroup, .. }) => {
+                vec![("cgroup", cgroup.clone())]
+            }
+            WidgetKind::Temperature(c) => {
+                vec![("chip", regex(&c.chip)), ("sensor", regex(&c.sensor))]
+            }
+            WidgetKind::PowerSupply(c) => vec![("supply", c.supply.clone())],
+            WidgetKind::FsUsage(c) => vec![(
+                "mount_points",
+                (!c.mount_points.is_empty()).then(|| c.mount_points.join(",")),
+            )],
+            WidgetKind::Cgroup(c) => {
+                vec![("cgroup", (!c.cgroup.is_empty()).then(|| c.cgroup.clone()))]
+            }
+            // These widgets always chart the same series, or key each chart by what it shows
+            WidgetKind::CpuCores(_)
+            | WidgetKind::Swap(_)
+            | WidgetKind::Vmstat(_)
+            | WidgetKind::Top(_) => Vec::new(),
+        };
+        settings
+            .into_iter()
+            .filter_map(|(name, value)| Some(format!("{}={}", name, value?)))
+            .collect()
+    }
 }
 
 pub fn default_chart_height() -> u32 {
     100
 }
 
-#[derive(Debug, Deserialize, WaymonWidgetConfig)]
+/// A regular expression that can be used in widget configs.
+///
+/// This is a thin wrapper around Regex that can be deserialized from a string, and that compares
+/// equal to other patterns with the same source string.
+#[derive(Clone, Debug)]
+pub struct ConfigRegex(pub Regex);
+
+impl ConfigRegex {
+    pub fn is_match(&self, s: &str) -> bool {
+        self.0.is_match(s)
+    }
+}
+
+impl PartialEq for ConfigRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl<'de> Deserialize<'de> for ConfigRegex {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Regex::new(&s)
+            .map(ConfigRegex)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A pattern used to select devices (e.g., network interfaces or disks) by name.
+///
+/// In the config file this is either a glob-style string such as "enx*" or "nvme?n1", or a table
+/// containing a regular expression, such as `{ regex = "^enp[0-9]+s0$" }`.  A plain device name
+/// is a valid glob that only matches itself.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "TomlDevicePattern")]
+pub struct DevicePattern {
+    source: TomlDevicePattern,
+    regex: Regex,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+enum TomlDevicePattern {
+    Glob(String),
+    Regex { regex: String },
+}
+
+impl DevicePattern {
+    pub fn is_match(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+}
+
+impl std::fmt::Display for DevicePattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.source {
+            TomlDevicePattern::Glob(glob) => write!(f, "{}", glob),
+            TomlDevicePattern::Regex { regex } => write!(f, "/{}/", regex),
+        }
+    }
+}
+
+impl PartialEq for DevicePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl TryFrom<TomlDevicePattern> for DevicePattern {
+    type Error = String;
+
+    fn try_from(source: TomlDevicePattern) -> Result<Self, String> {
+        let regex_str = match &source {
+            TomlDevicePattern::Glob(glob) => glob_to_regex(glob),
+            TomlDevicePattern::Regex { regex } => regex.clone(),
+        };
+        let regex = Regex::new(&regex_str).map_err(|e| e.to_string())?;
+        Ok(DevicePattern { source, regex })
+    }
+}
+
+/// The interfaces shown by a net widget
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(from = "DevicePattern")]
+pub enum NetDevice {
+    /// Follow whichever interface currently carries the default route
+    Auto,
+    /// Show all interfaces matching the pattern
+    Pattern(DevicePattern),
+}
+
+impl From<DevicePattern> for NetDevice {
+    fn from(pattern: DevicePattern) -> Self {
+        match &pattern.source {
+            TomlDevicePattern::Glob(glob) if glob == "auto" => NetDevice::Auto,
+            _ => NetDevice::Pattern(pattern),
+        }
+    }
+}
+
+impl std::fmt::Display for NetDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetDevice::Auto => write!(f, "auto"),
+            NetDevice::Pattern(pattern) => pattern.fmt(f),
+        }
+    }
+}
+
+/// Convert a glob pattern to an equivalent anchored regular expression.
+///
+/// This supports "*", "?", and bracketed character classes (with "!" or "^" for negation).
+fn glob_to_regex(glob: &str) -> String {
+    let mut result = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => result.push_str(".*"),
+            '?' => result.push('.'),
+            '[' => {
+                result.push('[');
+                if let Some('!') | Some('^') = chars.peek() {
+                    chars.next();
+                    result.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        result.push('\\');
+                    }
+                    result.push(c);
+                }
+                result.push(']');
+            }
+            _ => result.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    result.push('$');
+    result
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, WaymonWidgetConfig)]
 pub struct CpuWidgetConfig {
     pub label: String,
 
+    // Which categories of CPU time to show in the chart
+    #[serde(default = "default_cpu_categories")]
+    pub categories: Vec<CpuSeriesConfig>,
+
     #[serde(default = "default_chart_height")]
     pub height: u32,
 }
 
-#[derive(Debug, Deserialize, WaymonWidgetConfig)]
+#[derive(Clone, Debug, Deserialize, PartialEq, WaymonWidgetConfig)]
 pub struct NetWidgetConfig {
     pub label: String,
-    pub dev: String,
+    // The interfaces to show.  If this matches multiple interfaces their traffic is summed.
+    // "auto" follows the interface carrying the default route.
+    pub dev: NetDevice,
+    // Interfaces to ignore, even if they match dev
+    #[serde(default)]
+    pub exclude: Option<DevicePattern>,
 
     #[serde(default = "default_chart_height")]
     pub height: u32,
 }
 
-#[derive(Debug, Deserialize, WaymonWidgetConfig)]
+#[derive(Clone, Debug, Deserialize, PartialEq, WaymonWidgetConfig)]
 pub struct MemWidgetConfig {
     pub label: String,
 
+    // Which categories of memory usage to show in the chart
+    #[serde(default = "default_mem_categories")]
+    pub categories: Vec<MemSeriesConfig>,
+
     #[serde(default = "default_chart_height")]
     pub height: u32,
 }
 
-fn parse_duration<'de, D>(deser: D) -> Result<Duration, D::Error>
+pub fn parse_duration<'de, D>(deser: D) -> Result<Duration, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -319,17 +753,11 @@ where
             )
         }
 
-        fn visit_str<E>(self, _s: &str) -> Result<Self::Value, E>
+        fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
         where
             E: serde::de::Error,
         {
-            // TODO: parse various strings
-            // - 5s
-            // - 100ms
-            // - 1s500ms
-            Err(serde::de::Error::custom(
-                "todo: parsing string durations is not currently implemented",
-            ))
+            parse_duration_str(s).map_err(serde::de::Error::custom)
         }
 
         fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
@@ -371,6 +799,107 @@ where
     deser.deserialize_any(V)
 }
 
+/// Like parse_duration(), for optional settings.
+///
+/// This is only called when the setting is present, so it should be used with
+/// `#[serde(default)]`.
+pub fn parse_optional_duration<'de, D>(deser: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    parse_duration(deser).map(Some)
+}
+
+/// Like parse_duration(), for settings that accept either a single duration or a list of them.
+pub fn parse_duration_list<'de, D>(deser: D) -> Result<Vec<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct ConfigDuration(#[serde(deserialize_with = "parse_duration")] Duration);
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(ConfigDuration),
+        Many(Vec<ConfigDuration>),
+    }
+
+    Ok(match OneOrMany::deserialize(deser)? {
+        OneOrMany::One(d) => vec![d.0],
+        OneOrMany::Many(list) => list.into_iter().map(|d| d.0).collect(),
+    })
+}
+
+/// Parse a human-readable duration string.
+///
+/// The string consists of one or more numbers, each followed by a unit: "ns", "us", "ms", "s", "m",
+/// or "h".  For example: "500ms", "2s", "1s500ms", "1m", or "0.5s".
+pub fn parse_duration_str(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("empty duration string".to_string());
+    }
+
+    let is_number_char = |c: char| c.is_ascii_digit() || c == '.';
+    let mut total = Duration::ZERO;
+    let mut remaining = s;
+    while !remaining.is_empty() {
+        let num_len = remaining
+            .find(|c: char| !is_number_char(c))
+            .unwrap_or(remaining.len());
+        let (num_str, rest) = remaining.split_at(num_len);
+        let unit_len = rest.find(is_number_char).unwrap_or(rest.len());
+        let (unit, rest) = rest.split_at(unit_len);
+        remaining = rest;
+
+        if num_str.is_empty() {
+            return Err(format!(
+                "invalid duration {:?}: expected a number before {:?}",
+                s, unit
+            ));
+        }
+        let unit_nanos: u64 = match unit.trim() {
+            "ns" => 1,
+            "us" => 1_000,
+            "ms" => 1_000_000,
+            "s" => 1_000_000_000,
+            "m" => 60 * 1_000_000_000,
+            "h" => 60 * 60 * 1_000_000_000,
+            "" => {
+                return Err(format!(
+                    "invalid duration {:?}: missing unit after {:?}",
+                    s, num_str
+                ))
+            }
+            _ => {
+                return Err(format!(
+                    "invalid duration {:?}: unknown unit {:?}, expected ns, us, ms, s, m, or h",
+                    s,
+                    unit.trim()
+                ))
+            }
+        };
+
+        // Use integer math if possible, to avoid floating point rounding errors
+        let value = if let Ok(n) = num_str.parse::<u64>() {
+            n.checked_mul(unit_nanos).map(Duration::from_nanos)
+        } else if let Ok(f) = num_str.parse::<f64>() {
+            Duration::try_from_secs_f64(f * (unit_nanos as f64) / 1_000_000_000.0).ok()
+        } else {
+            return Err(format!(
+                "invalid duration {:?}: invalid number {:?}",
+                s, num_str
+            ));
+        };
+        total = value
+            .and_then(|v| total.checked_add(v))
+            .ok_or_else(|| format!("invalid duration {:?}: value is too large", s))?;
+    }
+
+    Ok(total)
+}
+
 fn read_config_contents(path: &Path) -> Result<String> {
     const MAX_CONFIG_FILE_SIZE: u64 = 50 * 1024 * 1024;
     match crate::read::read_to_string_with_limit(path, MAX_CONFIG_FILE_SIZE) {
@@ -387,3 +916,310 @@ fn read_config_contents(path: &Path) -> Result<String> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::widgets::cpu::CpuCategory;
+    use crate::widgets::mem::MemCategory;
+    use crate::widgets::timeseries::Color;
+
+    #[test]
+    fn test_parse_duration_str() {
+        assert_eq!(parse_duration_str("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration_str("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(
+            parse_duration_str("1s500ms"),
+            Ok(Duration::from_millis(1500))
+        );
+        assert_eq!(parse_duration_str("1m"), Ok(Duration::from_secs(60)));
+        assert_eq!(
+            parse_duration_str("1h 2m 3s"),
+            Ok(Duration::from_secs(3723))
+        );
+        assert_eq!(parse_duration_str("0.25s"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration_str("100us"), Ok(Duration::from_micros(100)));
+
+        assert!(parse_duration_str("").is_err());
+        assert!(parse_duration_str("5").is_err());
+        assert!(parse_duration_str("5 sec").is_err());
+        assert!(parse_duration_str("ms").is_err());
+        assert!(parse_duration_str("1.2.3s").is_err());
+        assert!(parse_duration_str("99999999999999999999h").is_err());
+    }
+
+    #[test]
+    fn test_device_pattern() {
+        let glob = |s: &str| DevicePattern::try_from(TomlDevicePattern::Glob(s.to_string()));
+        let enx = glob("enx*").unwrap();
+        assert!(enx.is_match("enx001122334455"));
+        assert!(!enx.is_match("wlan0"));
+        assert!(!enx.is_match("xenx0"));
+
+        let literal = glob("sda").unwrap();
+        assert!(literal.is_match("sda"));
+        assert!(!literal.is_match("sda1"));
+
+        let nvme = glob("nvme?n[!2]").unwrap();
+        assert!(nvme.is_match("nvme0n1"));
+        assert!(!nvme.is_match("nvme0n2"));
+        assert!(!nvme.is_match("nvme0n1p1"));
+
+        // Regex metacharacters in globs should be treated literally
+        let dotted = glob("br.lan").unwrap();
+        assert!(dotted.is_match("br.lan"));
+        assert!(!dotted.is_match("br0lan"));
+
+        let config: NetWidgetConfig = toml::from_str(
+            r#"
+            label = "Net"
+            dev = { regex = "^(eth|enp)" }
+            exclude = "enp0s31f6"
+            "#,
+        )
+        .unwrap();
+        let NetDevice::Pattern(dev) = config.dev else {
+            panic!("expected a device pattern");
+        };
+        assert!(dev.is_match("eth0"));
+        assert!(dev.is_match("enp3s0"));
+        assert!(!dev.is_match("wlan0"));
+        assert_eq!(dev.to_string(), "/^(eth|enp)/");
+        assert!(config.exclude.unwrap().is_match("enp0s31f6"));
+
+        let config: NetWidgetConfig = toml::from_str(
+            r#"
+            label = "Net"
+            dev = "auto"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.dev, NetDevice::Auto);
+
+        let err = toml::from_str::<NetWidgetConfig>(
+            r#"
+            label = "Net"
+            dev = { regex = "(" }
+            "#,
+        );
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_interval() -> Result<()> {
+        let parse = |s: &str| -> Result<Duration> {
+            Ok(toml::from_str::<TomlConfig>(s)?.to_config()?.interval)
+        };
+        assert_eq!(parse("")?, Duration::from_secs(1));
+        assert_eq!(parse("interval = 2")?, Duration::from_secs(2));
+        assert_eq!(parse("interval = 0.5")?, Duration::from_millis(500));
+        assert_eq!(parse("interval = \"250ms\"")?, Duration::from_millis(250));
+        assert!(parse("interval = \"250 bananas\"").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_widget_intervals() -> Result<()> {
+        let config = toml::from_str::<TomlConfig>(
+            r#"
+interval = "2s"
+gridlines = "1m"
+
+[bar.primary]
+interval = "5s"
+width = 80
+
+[[bar.primary.widget]]
+type = "cpu"
+label = "CPU"
+interval = "250ms"
+gridlines = "10s"
+window = ["1m", "1h"]
+
+[[bar.primary.widget]]
+type = "mem"
+label = "Memory"
+"#,
+        )?
+        .to_config()?;
+
+        let bar = config.primary_bar();
+        assert_eq!(bar.interval, Duration::from_secs(5));
+        assert_eq!(bar.widgets[0].interval, Some(Duration::from_millis(250)));
+        assert!(matches!(bar.widgets[0].kind, WidgetKind::Cpu(_)));
+        assert_eq!(bar.widget_interval(&bar.widgets[1]), Duration::from_secs(5));
+        assert_eq!(config.min_interval(), Duration::from_millis(250));
+        assert_eq!(
+            bar.time_axis(&bar.widgets[0]),
+            TimeAxis {
+                width: 80,
+                interval: Duration::from_millis(250),
+                windows: vec![Duration::from_secs(60), Duration::from_secs(3600)],
+                selected: 0,
+                gridlines: Some(Duration::from_secs(10)),
+            }
+        );
+        assert_eq!(
+            bar.time_axis(&bar.widgets[1]).windows,
+            vec![Duration::from_secs(400)]
+        );
+        assert_eq!(
+            bar.time_axis(&bar.widgets[1]).gridlines,
+            Some(Duration::from_secs(60))
+        );
+
+        let zero_interval = r#"
+[[widget]]
+type = "mem"
+label = "Memory"
+interval = 0
+"#;
+        assert!(toml::from_str::<TomlConfig>(zero_interval)?
+            .to_config()
+            .is_err());
+
+        let single_window = r#"
+[[widget]]
+type = "mem"
+label = "Memory"
+window = "10m"
+"#;
+        let config = toml::from_str::<TomlConfig>(single_window)?.to_config()?;
+        assert_eq!(
+            config.primary_bar().widgets[0].window,
+            vec![Duration::from_secs(600)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_id() -> Result<()> {
+        let parse = |data: &str| -> Result<WidgetConfig> {
+            let config = toml::from_str::<TomlConfig>(data)?.to_config()?;
+            Ok(config.primary_bar().widgets[0].clone())
+        };
+        let cpu = parse(
+            r#"
+[[widget]]
+type = "cpu"
+label = "CPU"
+"#,
+        )?;
+        let cpu_changed = parse(
+            r##"
+[[widget]]
+type = "cpu"
+label = "CPU"
+interval = "250ms"
+height = 50
+categories = ["nice", "user", { category = "system", color = "#ff0000" }]
+"##,
+        )?;
+        assert_eq!(cpu.history_id(), "cpu:CPU:categories=[Nice, User, System]");
+        // Settings that do not change what the series show keep the history
+        assert_eq!(cpu_changed.history_id(), cpu.history_id());
+
+        // Changing the categories changes what each series means, so the old history is not used
+        let cpu_reordered = parse(
+            r#"
+[[widget]]
+type = "cpu"
+label = "CPU"
+categories = ["iowait", "user", "system"]
+"#,
+        )?;
+        assert_ne!(cpu_reordered.history_id(), cpu.history_id());
+
+        let net = |dev: &str| {
+            parse(&format!(
+                "[[widget]]\ntype = \"net\"\nlabel = \"Net\"\ndev = \"{}\"\n",
+                dev
+            ))
+        };
+        assert_eq!(net("eth0")?.history_id(), "net:Net:dev=eth0");
+        assert_ne!(net("eth0")?.history_id(), net("wlan0")?.history_id());
+
+        let pressure = parse(
+            r#"
+[[widget]]
+type = "cpu_pressure"
+label = "CPU"
+"#,
+        )?;
+        assert_eq!(pressure.history_id(), "cpu_pressure:CPU");
+        Ok(())
+    }
+
+    #[test]
+    fn test_cpu_categories() -> Result<()> {
+        let config = toml::from_str::<TomlConfig>(
+            r##"
+[[widget]]
+type = "cpu"
+label = "CPU"
+categories = ["user", "system", { category = "steal", color = "#ff0000", annotate = false }]
+"##,
+        )?
+        .to_config()?;
+
+        let widgets = &config.primary_bar().widgets;
+        let WidgetKind::Cpu(cpu) = &widgets[0].kind else {
+            return Err(anyhow!("unexpected widget type: {:?}", widgets[0]));
+        };
+        assert_eq!(cpu.categories.len(), 3);
+        assert_eq!(cpu.categories[0].category, CpuCategory::User);
+        assert_eq!(cpu.categories[0].color, None);
+        assert_eq!(cpu.categories[1].category, CpuCategory::System);
+        assert_eq!(cpu.categories[2].category, CpuCategory::Steal);
+        assert_eq!(cpu.categories[2].color, Some(Color::new(1.0, 0.0, 0.0)));
+        assert_eq!(cpu.categories[2].annotate, Some(false));
+
+        let bad_category = r#"
+[[widget]]
+type = "cpu"
+label = "CPU"
+categories = ["user", "bogus"]
+"#;
+        assert!(toml::from_str::<TomlConfig>(bad_category).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mem_categories() -> Result<()> {
+        let config = toml::from_str::<TomlConfig>(
+            r##"
+[[widget]]
+type = "mem"
+label = "Memory"
+
+[[widget]]
+type = "mem"
+label = "Breakdown"
+categories = ["anon", "cache", { category = "slab", annotate = false }, "free"]
+"##,
+        )?
+        .to_config()?;
+
+        let widgets = &config.primary_bar().widgets;
+        let WidgetKind::Mem(mem) = &widgets[0].kind else {
+            return Err(anyhow!("unexpected widget type: {:?}", widgets[0]));
+        };
+        assert_eq!(mem.categories, default_mem_categories());
+
+        let WidgetKind::Mem(mem) = &widgets[1].kind else {
+            return Err(anyhow!("unexpected widget type: {:?}", widgets[1]));
+        };
+        let categories: Vec<MemCategory> = mem.categories.iter().map(|c| c.category).collect();
+        assert_eq!(
+            categories,
+            vec![
+                MemCategory::Anon,
+                MemCategory::Cache,
+                MemCategory::Slab,
+                MemCategory::Free
+            ]
+        );
+        assert_eq!(mem.categories[2].annotate, Some(false));
+        Ok(())
+    }
+}
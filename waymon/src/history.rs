@@ -0,0 +1,347 @@
+//! Saving chart history across restarts.
+//!
+//! The data points of each widget's charts are saved to a JSON state file when waymon exits, and
+//! periodically while it runs, so that a crash does not lose everything.  When waymon starts
+//! again, each widget's history is restored from the file.  Since each data point is saved with
+//! its timestamp, the time that waymon was not running shows up as a gap in the charts.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+pub const HISTORY_FILE_NAME: &str = "history.json";
+
+// How often to save the history while running
+const SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// The version of the state file format.  This should be incremented whenever a change would
+// prevent older history from being restored correctly, e.g. a change to how widgets are
+// identified.  Files saved with any other version are ignored.
+const FORMAT_VERSION: u32 = 1;
+
+/// The data points of one chart, for each of the time windows it can show.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct ChartHistory(Vec<ChartLevelHistory>);
+
+impl ChartHistory {
+    pub fn new(levels: Vec<ChartLevelHistory>) -> Self {
+        Self(levels)
+    }
+
+    pub fn levels(&self) -> &[ChartLevelHistory] {
+        &self.0
+    }
+}
+
+/// The data points of one chart, at one resolution.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ChartLevelHistory {
+    // The amount of time covered by each data point, in seconds
+    pub pixel_secs: f64,
+    // The data points, from oldest to newest
+    pub points: Vec<ChartPoint>,
+}
+
+/// One data point of a chart, possibly combining several samples.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ChartPoint {
+    // The time of the first sample, in seconds since the Unix epoch
+    pub time: f64,
+    // The average value of each of the chart's series
+    pub values: Vec<f64>,
+    // The smallest and largest total value of any one sample
+    pub min: f64,
+    pub max: f64,
+    // The number of samples combined into this point
+    pub count: u32,
+}
+
+/// The history of each chart in a widget, keyed by a name the widget picks for each chart.
+pub type WidgetHistory = BTreeMap<String, ChartHistory>;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SavedWidget {
+    // Identifies which widget in the bar this history belongs to
+    pub id: String,
+    pub charts: WidgetHistory,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SavedHistory {
+    // Files saved before the version was added are treated as version 0
+    #[serde(default)]
+    version: u32,
+    // The widgets in each bar, keyed by bar name
+    bars: BTreeMap<String, Vec<SavedWidget>>,
+}
+
+impl SavedHistory {
+    pub fn new(bars: BTreeMap<String, Vec<SavedWidget>>) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            bars,
+        }
+    }
+
+    /// Get the saved widgets for a bar.
+    pub fn restore_bar(&self, bar_name: &str) -> RestoredBar {
+        RestoredBar {
+            widgets: self
+                .bars
+                .get(bar_name)
+                .map(|widgets| {
+                    widgets
+                        .iter()
+                        .map(|w| Some((w.id.clone(), w.charts.clone())))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// The saved history for the widgets of one bar.
+#[derive(Default)]
+pub struct RestoredBar {
+    widgets: Vec<Option<(String, WidgetHistory)>>,
+}
+
+impl RestoredBar {
+    /// Take the saved history for a widget.
+    ///
+    /// If the bar has several widgets with the same ID, they are matched up in order.
+    pub fn take(&mut self, id: &str) -> Option<WidgetHistory> {
+        let (_id, history) = self
+            .widgets
+            .iter_mut()
+            .find(|w| w.as_ref().is_some_and(|(saved_id, _)| saved_id == id))?
+            .take()?;
+        Some(history)
+    }
+}
+
+/// Loads and saves the chart history state file.
+pub struct HistoryStore {
+    // Where to save the history, or None if saving history is disabled
+    path: Option<PathBuf>,
+    // The history loaded at startup.  This is only used for bars created before we first save,
+    // since after that it would be out of date.
+    loaded: Option<SavedHistory>,
+    last_save: Instant,
+}
+
+impl HistoryStore {
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let loaded = path.as_deref().and_then(|path| match load(path) {
+            Ok(history) => history,
+            Err(e) => {
+                warn!("unable to load chart history: {:#}", e);
+                None
+            }
+        });
+        Self {
+            path,
+            loaded,
+            last_save: Instant::now(),
+        }
+    }
+
+    /// The history to restore into newly created bars, if there is any.
+    pub fn loaded(&self) -> Option<&SavedHistory> {
+        self.loaded.as_ref()
+    }
+
+    /// Returns true if it has been long enough since the last save that we should save again.
+    pub fn is_save_due(&self, now: Instant) -> bool {
+        self.path.is_some() && now - self.last_save >= SAVE_INTERVAL
+    }
+
+    pub fn save(&mut self, now: Instant, bars: BTreeMap<String, Vec<SavedWidget>>) {
+        self.last_save = now;
+        self.loaded = None;
+        let Some(path) = &self.path else {
+            return;
+        };
+        let history = SavedHistory::new(bars);
+        match save(path, &history) {
+            Ok(()) => debug!("saved chart history to {}", path.display()),
+            Err(e) => error!("unable to save chart history: {:#}", e),
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<Option<SavedHistory>> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        // This is normal the first time waymon runs
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("{}", path.display())),
+    };
+    let history: SavedHistory =
+        serde_json::from_slice(&data).with_context(|| format!("{}", path.display()))?;
+    if history.version != FORMAT_VERSION {
+        info!(
+            "ignoring chart history in {} saved with format version {}",
+            path.display(),
+            history.version
+        );
+        return Ok(None);
+    }
+    Ok(Some(history))
+}
+
+fn save(path: &Path, history: &SavedHistory) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("unable to create {}", dir.display()))?;
+    }
+    // Write to a temporary file and rename it into place, so that we never leave a partially
+    // written file behind if we are killed while saving.
+    let tmp_path = path.with_extension("json.tmp");
+    let data = serde_json::to_vec(history)?;
+    std::fs::write(&tmp_path, data)
+        .with_context(|| format!("unable to write {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path).with_context(|| {
+        format!(
+            "unable to rename {} to {}",
+            tmp_path.display(),
+            path.display()
+        )
+    })?;
+    Ok(())
+}
+
+/// Collect the saved widgets from several bars.
+///
+/// When several monitors show the same bar configuration, only the first one is saved.
+pub fn collect_bars<I>(bars: I) -> BTreeMap<String, Vec<SavedWidget>>
+where
+    I: IntoIterator<Item = (String, Vec<SavedWidget>)>,
+{
+    let mut result = BTreeMap::new();
+    for (name, widgets) in bars {
+        result.entry(name).or_insert(widgets);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read::FixtureDir;
+    use crate::widgets::timeseries::{StackedTimeseriesChart, TimeAxis};
+    use std::time::SystemTime;
+
+    // The times of the data points in each level of a chart's saved history
+    fn point_times(history: &ChartHistory) -> Vec<Vec<f64>> {
+        history
+            .levels()
+            .iter()
+            .map(|level| level.points.iter().map(|p| p.time).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_save_and_restore() -> Result<()> {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let axis = TimeAxis {
+            windows: vec![Duration::from_secs(10), Duration::from_secs(20)],
+            ..TimeAxis::new(10, Duration::from_secs(1))
+        };
+        let mut chart = StackedTimeseriesChart::<f64, 2>::new(&axis);
+        chart.add_values_at(start, &[1.0, 2.0]);
+        chart.add_values_at(start + Duration::from_secs(1), &[3.0, 4.0]);
+        let mut charts = WidgetHistory::new();
+        charts.insert("chart".to_string(), chart.save_history());
+        let widgets = vec![
+            SavedWidget {
+                id: "cpu".to_string(),
+                charts: charts.clone(),
+            },
+            SavedWidget {
+                id: "cpu".to_string(),
+                charts: WidgetHistory::new(),
+            },
+        ];
+
+        let fixture = FixtureDir::new();
+        let path = fixture.path().join("waymon").join(HISTORY_FILE_NAME);
+        let bars = collect_bars([
+            ("primary".to_string(), widgets),
+            ("primary".to_string(), Vec::new()),
+        ]);
+        save(&path, &SavedHistory::new(bars))?;
+        let loaded = load(&path)?.unwrap();
+
+        // Widgets with the same ID are matched up in order
+        let mut restored = loaded.restore_bar("primary");
+        let mut history = restored.take("cpu").unwrap();
+        assert!(restored.take("mem").is_none());
+        assert_eq!(restored.take("cpu"), Some(WidgetHistory::new()));
+        assert!(restored.take("cpu").is_none());
+
+        // The 20 second window uses 2 seconds per pixel, so both samples share one point
+        assert_eq!(
+            point_times(&history["chart"]),
+            vec![
+                vec![1_700_000_000.0, 1_700_000_001.0],
+                vec![1_700_000_000.0]
+            ]
+        );
+        assert_eq!(
+            history["chart"].levels()[1].points[0],
+            ChartPoint {
+                time: 1_700_000_000.0,
+                values: vec![2.0, 3.0],
+                min: 3.0,
+                max: 7.0,
+                count: 2,
+            }
+        );
+
+        // A chart with only a long window is restored from the matching saved level
+        let mut long_chart = StackedTimeseriesChart::<f64, 2>::new(&TimeAxis {
+            windows: vec![Duration::from_secs(8)],
+            ..TimeAxis::new(4, axis.interval)
+        });
+        long_chart.restore_history(&history["chart"]);
+        assert_eq!(
+            point_times(&long_chart.save_history()),
+            vec![vec![1_700_000_000.0]]
+        );
+        assert_eq!(long_chart.max_value(), 7.0);
+
+        let mut new_chart = StackedTimeseriesChart::<f64, 2>::new(&TimeAxis::new(4, axis.interval));
+        new_chart.restore_from(&mut history, "chart");
+        assert!(history.is_empty());
+        assert_eq!(new_chart.max_value(), 7.0);
+
+        // Restored points are kept as long as they still fall within the chart's time span
+        new_chart.add_values_at(start + Duration::from_secs(3), &[0.5, 0.5]);
+        assert_eq!(point_times(&new_chart.save_history())[0].len(), 3);
+        new_chart.add_values_at(start + Duration::from_secs(5), &[0.5, 0.5]);
+        assert_eq!(
+            point_times(&new_chart.save_history()),
+            vec![vec![1_700_000_003.0, 1_700_000_005.0]]
+        );
+        assert_eq!(new_chart.max_value(), 1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_old_format_ignored() -> Result<()> {
+        // Files from before the format version was added identified widgets differently
+        let fixture = FixtureDir::with_files(&[(
+            HISTORY_FILE_NAME,
+            r#"{"bars":{"primary":[{"id":"WidgetConfig { .. }","charts":{}}]}}"#,
+        )]);
+        let path = fixture.path().join(HISTORY_FILE_NAME);
+        assert!(load(&path)?.is_none());
+
+        save(&path, &SavedHistory::new(BTreeMap::new()))?;
+        assert!(load(&path)?.is_some());
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@ use anyhow::anyhow;
 use clap::{Parser, ValueHint};
 use gtk::glib;
 use std::ffi::OsString;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use tracing_subscriber::filter::{LevelFilter, Targets};
 use tracing_subscriber::fmt::Subscriber;
@@ -11,7 +11,9 @@ use tracing_subscriber::prelude::*;
 mod bar;
 mod collectors;
 mod config;
+mod history;
 mod read;
+mod record;
 mod stats;
 mod util;
 mod waymon;
@@ -24,6 +26,21 @@ struct Opt {
     config_dir: Option<OsString>,
     #[arg(short, long)]
     logging: Option<String>,
+    /// Read process and system stats from this directory instead of /proc
+    #[arg(long, value_parser, value_hint=ValueHint::DirPath)]
+    proc_root: Option<OsString>,
+    /// Read device stats from this directory instead of /sys
+    #[arg(long, value_parser, value_hint=ValueHint::DirPath)]
+    sys_root: Option<OsString>,
+    /// Save the collected stats to this file, so they can be replayed later with --replay
+    #[arg(long, value_parser, value_hint=ValueHint::FilePath, conflicts_with = "replay")]
+    record: Option<OsString>,
+    /// Replay stats saved with --record, instead of reading them from the system
+    #[arg(long, value_parser, value_hint=ValueHint::FilePath)]
+    replay: Option<OsString>,
+    /// How fast to replay stats with --replay, as a multiple of the recorded rate
+    #[arg(long, default_value_t = 1.0)]
+    replay_speed: f64,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -43,7 +60,28 @@ fn main() -> anyhow::Result<()> {
     // uniqueness or it's command line argument parsing.
     gtk::init()?;
 
-    let waymon = match waymon::WaymonState::new(&config_dir) {
+    let root_overrides = read::SystemRoots {
+        proc_root: opts.proc_root.map(PathBuf::from),
+        sys_root: opts.sys_root.map(PathBuf::from),
+    };
+    let stats_source = if let Some(path) = &opts.record {
+        stats::StatsSource::Record(record::Recorder::create(Path::new(path))?)
+    } else if let Some(path) = &opts.replay {
+        stats::StatsSource::Replay(record::Replayer::open(Path::new(path), opts.replay_speed)?)
+    } else {
+        stats::StatsSource::Live
+    };
+    // Chart history is not saved when replaying a recording, since it is not live data
+    let history_path = match &opts.replay {
+        Some(_) => None,
+        None => dirs::state_dir().map(|x| x.join("waymon").join(history::HISTORY_FILE_NAME)),
+    };
+    let waymon = match waymon::WaymonState::new(
+        &config_dir,
+        root_overrides,
+        stats_source,
+        history_path,
+    ) {
         Ok(waymon) => waymon,
         Err(err) => {
             return Err(anyhow!("initialization error: {:#}", err));
@@ -52,7 +90,16 @@ fn main() -> anyhow::Result<()> {
 
     waymon.start();
     let main_loop = glib::MainLoop::new(None, false);
+    // Exit the main loop on SIGINT, SIGTERM, or SIGHUP, so that we get a chance to save our state
+    for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        let main_loop = main_loop.clone();
+        glib::unix_signal_add_local(signal, move || {
+            main_loop.quit();
+            glib::ControlFlow::Break
+        });
+    }
     main_loop.run();
+    waymon.shutdown();
     Ok(())
 }
 
@@ -1,5 +1,55 @@
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Alternate root directories to use in place of /proc and /sys when collecting stats.
+///
+/// This allows monitoring a container or chroot through its bind-mounted procfs and sysfs, or
+/// reading from a fixture directory tree in tests.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SystemRoots {
+    pub proc_root: Option<PathBuf>,
+    pub sys_root: Option<PathBuf>,
+}
+
+impl SystemRoots {
+    /// Return these roots, using the ones from `other` for any that are not set.
+    pub fn or(&self, other: &SystemRoots) -> SystemRoots {
+        SystemRoots {
+            proc_root: self.proc_root.clone().or_else(|| other.proc_root.clone()),
+            sys_root: self.sys_root.clone().or_else(|| other.sys_root.clone()),
+        }
+    }
+}
+
+static SYSTEM_ROOTS: RwLock<SystemRoots> = RwLock::new(SystemRoots {
+    proc_root: None,
+    sys_root: None,
+});
+
+pub fn set_system_roots(roots: SystemRoots) {
+    *SYSTEM_ROOTS.write().unwrap() = roots;
+}
+
+/// Return the path to read for a file under /proc or /sys, honoring any configured root
+/// directories.  Collectors should use this for all paths that they read.
+pub fn system_path(path: &str) -> PathBuf {
+    map_system_path(path, &SYSTEM_ROOTS.read().unwrap())
+}
+
+fn map_system_path(path: &str, roots: &SystemRoots) -> PathBuf {
+    let remap = |prefix: &str, root: &Option<PathBuf>| -> Option<PathBuf> {
+        let rest = path.strip_prefix(prefix)?;
+        if !rest.is_empty() && !rest.starts_with('/') {
+            return None;
+        }
+        let root = root.as_ref()?;
+        Some(root.join(rest.trim_start_matches('/')))
+    };
+    remap("/proc", &roots.proc_root)
+        .or_else(|| remap("/sys", &roots.sys_root))
+        .unwrap_or_else(|| PathBuf::from(path))
+}
 
 // Like read_to_string(), but with a maximum limit to avoid allocating a huge amount
 // of memory if the file happens to be very large.
@@ -22,3 +72,133 @@ pub fn read_to_string_with_limit(path: &Path, max_size: u64) -> Result<String, s
 
     Ok(buffer)
 }
+
+/// Read a small sysfs attribute file, and return its contents with surrounding whitespace removed.
+///
+/// Returns None if the file cannot be read.  Many sysfs attributes are optional, or fail to read
+/// when the underlying device is not available, so callers generally just skip missing values.
+pub fn read_sysfs_string(path: &Path) -> Option<String> {
+    read_to_string_with_limit(path, 4096)
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+/// A temporary directory tree for tests to use as a fake /proc or /sys.
+///
+/// The directory is removed when this is dropped, including when a test fails.
+#[cfg(test)]
+pub struct FixtureDir {
+    path: PathBuf,
+}
+
+#[cfg(test)]
+impl FixtureDir {
+    /// Create a new, empty fixture directory.
+    pub fn new() -> FixtureDir {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "waymon-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        FixtureDir { path }
+    }
+
+    /// Create a new fixture directory containing the given files.
+    pub fn with_files<C: AsRef<[u8]>>(files: &[(&str, C)]) -> FixtureDir {
+        let fixture = FixtureDir::new();
+        for (name, contents) in files {
+            fixture.write(name, contents);
+        }
+        fixture
+    }
+
+    /// Write a file, relative to the fixture root.  Parent directories are created as needed.
+    pub fn write<C: AsRef<[u8]>>(&self, name: &str, contents: C) {
+        let path = self.path.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read /proc and /sys from the fixture's "proc" and "sys" directories until the returned
+    /// guard is dropped.
+    pub fn use_as_system_roots(&self) -> SystemRootsGuard {
+        static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        // Only one test at a time can change the global roots
+        let lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_system_roots(SystemRoots {
+            proc_root: Some(self.path.join("proc")),
+            sys_root: Some(self.path.join("sys")),
+        });
+        SystemRootsGuard { _lock: lock }
+    }
+}
+
+#[cfg(test)]
+pub struct SystemRootsGuard {
+    _lock: std::sync::MutexGuard<'static, ()>,
+}
+
+#[cfg(test)]
+impl Drop for SystemRootsGuard {
+    fn drop(&mut self) {
+        set_system_roots(SystemRoots::default());
+    }
+}
+
+#[cfg(test)]
+impl Drop for FixtureDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_system_path() {
+        let roots = SystemRoots {
+            proc_root: Some(PathBuf::from("/container/proc")),
+            sys_root: None,
+        };
+        assert_eq!(
+            map_system_path("/proc/diskstats", &roots),
+            PathBuf::from("/container/proc/diskstats")
+        );
+        assert_eq!(
+            map_system_path("/proc/net/dev", &roots),
+            PathBuf::from("/container/proc/net/dev")
+        );
+        assert_eq!(
+            map_system_path("/sys/class/hwmon", &roots),
+            PathBuf::from("/sys/class/hwmon")
+        );
+        // Only whole path components should be matched
+        assert_eq!(
+            map_system_path("/processes", &roots),
+            PathBuf::from("/processes")
+        );
+
+        let roots = SystemRoots {
+            proc_root: None,
+            sys_root: Some(PathBuf::from("fixture/sys")),
+        };
+        assert_eq!(
+            map_system_path("/sys/class/power_supply", &roots),
+            PathBuf::from("fixture/sys/class/power_supply")
+        );
+        assert_eq!(
+            map_system_path("/proc/stat", &roots),
+            PathBuf::from("/proc/stat")
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Saving collected stats to a file, and replaying them later.
+//!
+//! Recordings are stored as JSON, with one line per update tick.  Each line holds the raw
+//! sample read by each collector that was in use, keyed by collector name, along with the time
+//! since the start of the recording.
+use crate::stats::{SharedStats, StatType, StatsDelta};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Sample {
+    // The time since the start of the recording, in seconds
+    time: f64,
+    stats: BTreeMap<String, serde_json::Value>,
+}
+
+pub struct Recorder {
+    // LineWriter flushes after each sample, so the recording is usable even if waymon is killed
+    writer: LineWriter<File>,
+    start: Option<Instant>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Recorder> {
+        let file = File::create(path)
+            .with_context(|| format!("unable to create recording file {}", path.display()))?;
+        Ok(Recorder {
+            writer: LineWriter::new(file),
+            start: None,
+        })
+    }
+
+    pub fn write_sample(&mut self, now: Instant, stats: &[SharedStats]) -> Result<()> {
+        let start = *self.start.get_or_insert(now);
+        let mut sample = Sample {
+            time: (now - start).as_secs_f64(),
+            stats: BTreeMap::new(),
+        };
+        for stat in stats {
+            let stat = stat.borrow();
+            sample.stats.insert(stat.name(), stat.save_sample()?);
+        }
+        serde_json::to_writer(&mut self.writer, &sample)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+pub struct Replayer {
+    path: PathBuf,
+    samples: Vec<Sample>,
+    // The index of the sample most recently loaded into the stats
+    index: usize,
+    // The instant that corresponds to time 0 in the recording
+    start: Instant,
+    speed: f64,
+    finished: bool,
+}
+
+impl Replayer {
+    /// Load a recording.
+    ///
+    /// The speed is a multiplier for how fast to play back the recording: 1.0 replays it at the
+    /// same rate it was recorded, 2.0 at twice that rate, etc.
+    pub fn open(path: &Path, speed: f64) -> Result<Replayer> {
+        if !speed.is_finite() || speed <= 0.0 {
+            return Err(anyhow!("invalid replay speed {}", speed));
+        }
+
+        let file = File::open(path)
+            .with_context(|| format!("unable to open recording file {}", path.display()))?;
+        let mut samples = Vec::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let sample: Sample = serde_json::from_str(&line)
+                .with_context(|| format!("{}:{}: invalid sample", path.display(), index + 1))?;
+            samples.push(sample);
+        }
+        if samples.is_empty() {
+            return Err(anyhow!("{} does not contain any samples", path.display()));
+        }
+
+        Ok(Replayer {
+            path: path.to_path_buf(),
+            samples,
+            index: 0,
+            start: Instant::now(),
+            speed,
+            finished: false,
+        })
+    }
+
+    /// How long to wait before advancing to the next sample, or None at the end of the recording.
+    ///
+    /// This follows the gaps between the recorded timestamps, so that irregular gaps (e.g., from
+    /// a suspend or an interval change) are replayed as they happened.
+    pub fn interval(&self) -> Option<Duration> {
+        let next = self.samples.get(self.index + 1)?;
+        let gap = (next.time - self.samples[self.index].time) / self.speed;
+        // Samples recorded at the same time are still replayed on separate ticks
+        let min_interval = Duration::from_millis(1);
+        Some(
+            Duration::try_from_secs_f64(gap)
+                .unwrap_or_default()
+                .max(min_interval),
+        )
+    }
+
+    fn sample_instant(&self, sample: &Sample) -> Instant {
+        self.start + Duration::try_from_secs_f64(sample.time).unwrap_or_default()
+    }
+
+    /// Create the StatsDelta for a collector, initialized from the current recorded sample.
+    pub fn initial_stats<T: StatType>(&self, params: T::Params) -> StatsDelta<T> {
+        let sample = &self.samples[self.index];
+        let name = T::instance_name(&params);
+        let value = match sample.stats.get(&name) {
+            Some(value) => serde_json::from_value(value.clone()).unwrap_or_else(|e| {
+                error!("invalid recorded {} sample: {}", name, e);
+                T::new_zero()
+            }),
+            None => {
+                warn!("{} does not contain {} stats", self.path.display(), name);
+                T::new_zero()
+            }
+        };
+        StatsDelta::from_sample(params, value, self.sample_instant(sample))
+    }
+
+    /// Load the next recorded sample into each of the stats.
+    ///
+    /// Returns false once the end of the recording has been reached.
+    pub fn replay_next(&mut self, stats: &[SharedStats]) -> bool {
+        if self.index + 1 >= self.samples.len() {
+            if !self.finished {
+                info!("finished replaying {}", self.path.display());
+                self.finished = true;
+            }
+            return false;
+        }
+
+        self.index += 1;
+        let sample = &self.samples[self.index];
+        let now = self.sample_instant(sample);
+        for stat in stats {
+            let mut stat = stat.borrow_mut();
+            // Stats that are missing from the recording keep their previous values
+            let Some(value) = sample.stats.get(&stat.name()) else {
+                continue;
+            };
+            if let Err(e) = stat.load_sample(now, value.clone()) {
+                error!("invalid recorded {} sample: {:?}", stat.name(), e);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::route::DefaultRouteStats;
+    use crate::read::FixtureDir;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn route_stats(iface: &str) -> DefaultRouteStats {
+        DefaultRouteStats {
+            ipv4: Some(iface.to_string()),
+            ipv6: None,
+        }
+    }
+
+    #[test]
+    fn test_record_and_replay() -> Result<()> {
+        let fixture = FixtureDir::new();
+        let path = fixture.path().join("recording");
+        let start = Instant::now();
+        let mut recorder = Recorder::create(&path)?;
+        for (secs, iface) in [(0, "eth0"), (2, "wlan0"), (3, "eth1")] {
+            let now = start + Duration::from_secs(secs);
+            let stats: SharedStats = Rc::new(RefCell::new(StatsDelta::from_sample(
+                (),
+                route_stats(iface),
+                now,
+            )));
+            recorder.write_sample(now, &[stats])?;
+        }
+        drop(recorder);
+
+        let mut replayer = Replayer::open(&path, 2.0)?;
+        // The recording is replayed at twice the recorded rate
+        assert_eq!(replayer.interval(), Some(Duration::from_secs(1)));
+
+        let delta = Rc::new(RefCell::new(
+            replayer.initial_stats::<DefaultRouteStats>(()),
+        ));
+        assert_eq!(*delta.borrow().get_new(), route_stats("eth0"));
+
+        let stats: Vec<SharedStats> = vec![delta.clone()];
+        assert!(replayer.replay_next(&stats));
+        {
+            let d = delta.borrow();
+            assert_eq!(
+                d.get_new_and_old(),
+                (&route_stats("wlan0"), &route_stats("eth0"))
+            );
+            // Time deltas come from the recording, regardless of the replay speed
+            assert_eq!(d.time_delta(), Duration::from_secs(2));
+        }
+        assert_eq!(replayer.interval(), Some(Duration::from_millis(500)));
+        assert!(replayer.replay_next(&stats));
+        assert_eq!(delta.borrow().time_delta(), Duration::from_secs(1));
+        assert_eq!(replayer.interval(), None);
+        assert!(!replayer.replay_next(&stats));
+        assert_eq!(*delta.borrow().get_new(), route_stats("eth1"));
+
+        Ok(())
+    }
+}
@@ -1,13 +1,17 @@
-use crate::collectors::diskstats::ProcDiskStats;
-use crate::collectors::meminfo::MemoryStats;
-use crate::collectors::net::NetDevStats;
-use crate::collectors::pressure::{CpuPressure, IoPressure, MemoryPressure};
-use crate::collectors::procstat::ProcStat;
-use std::cell::RefCell;
-use std::rc::Rc;
+use crate::record::{Recorder, Replayer};
+use gtk::glib;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::rc::{Rc, Weak};
+use std::sync::mpsc;
 use std::time::{Duration, Instant};
 use thiserror::Error;
-use tracing::error;
+use tracing::{error, info, warn};
 
 #[derive(Debug, Error)]
 pub enum StatsError {
@@ -17,25 +21,133 @@ pub enum StatsError {
     ParseError(String),
 }
 
-pub trait StatType: Clone {
+/// Compute how much a 64-bit counter has increased between two samples.
+///
+/// Counters normally only increase, but they can go backwards if they are reset (for instance,
+/// when a network interface or cgroup is removed and re-created).  Both this and
+/// counter_delta_u32() report a reset as no change, rather than guessing how much activity
+/// happened around the reset, so that ratios of counters from the same sample stay consistent.
+/// A 64-bit counter will never wrap in practice, so any decrease is treated as a reset.
+///
+/// Use counter_delta_u32() for counters that are only 32 bits wide in the kernel.
+pub fn counter_delta(new: u64, old: u64) -> u64 {
+    new.saturating_sub(old)
+}
+
+/// Compute how much a 32-bit counter has increased between two samples.
+///
+/// 32-bit counters such as the diskstats I/O times can wrap around, so a decrease is treated as
+/// a wrap if the old value was in the upper half of the range.  Otherwise it is treated as a
+/// reset, the same way as counter_delta().
+pub fn counter_delta_u32(new: u32, old: u32) -> u32 {
+    if new >= old {
+        return new - old;
+    }
+    if old >= u32::MAX / 2 {
+        return new.wrapping_sub(old);
+    }
+    0
+}
+
+/// A collector for one kind of stats.
+///
+/// Widgets request the stats they need from AllStats by type, and AllStats creates and updates a
+/// StatsDelta for each collector that is in use.  Adding a new collector only requires
+/// implementing this trait.
+pub trait StatType: Clone + Send + Serialize + DeserializeOwned + 'static {
+    /// Parameters identifying a particular instance of this collector, such as the path of a
+    /// sysfs directory to read.  Collectors that only ever have a single instance use `()`.
+    type Params: Clone + Debug + Eq + Hash + Send + 'static;
+
     fn new_zero() -> Self;
-    fn update(&mut self) -> Result<(), StatsError>;
+    fn update(&mut self, params: &Self::Params) -> Result<(), StatsError>;
     fn name() -> &'static str;
+
+    /// The name of a particular instance of this collector, used in log messages and to identify
+    /// it in recordings.
+    fn instance_name(_params: &Self::Params) -> String {
+        Self::name().to_string()
+    }
 }
 
 pub struct StatsDelta<T: StatType> {
+    params: T::Params,
     a: T,
     b: T,
     a_newer: bool,
     timestamp: Instant,
     duration: Duration,
+    // False until the first sample has been loaded, when collecting on a worker thread
+    initialized: bool,
+    // True if the worker thread has not returned a new sample within the collector timeout
+    stale: bool,
 }
 
 impl<T: StatType> StatsDelta<T> {
+    pub fn new(params: T::Params) -> Self {
+        let now = Instant::now();
+        let mut s = T::new_zero();
+        if let Err(e) = s.update(&params) {
+            error!(
+                "error initializing {} stats: {:?}",
+                T::instance_name(&params),
+                e
+            );
+            // Fall through anyway and initialize the structure with 0 values
+        }
+        Self::from_sample(params, s, now)
+    }
+
+    /// Create a StatsDelta with 0 values, to be filled in once the first sample is collected.
+    fn new_uninitialized(params: T::Params) -> Self {
+        Self {
+            initialized: false,
+            ..Self::from_sample(params, T::new_zero(), Instant::now())
+        }
+    }
+
+    /// Create a StatsDelta whose old and new values are both the given sample.
+    pub fn from_sample(params: T::Params, sample: T, now: Instant) -> Self {
+        Self {
+            params,
+            a: sample.clone(),
+            b: sample,
+            a_newer: true,
+            timestamp: now,
+            duration: Duration::from_millis(0),
+            initialized: true,
+            stale: false,
+        }
+    }
+
+    /// Replace the older sample with a new one, using `fill` to populate it.
+    fn advance<F>(&mut self, now: Instant, fill: F) -> Result<(), StatsError>
+    where
+        F: FnOnce(&mut T, &T::Params) -> Result<(), StatsError>,
+    {
+        if self.a_newer {
+            fill(&mut self.b, &self.params)?;
+        } else {
+            fill(&mut self.a, &self.params)?;
+        }
+        self.a_newer = !self.a_newer;
+        self.duration = now - self.timestamp;
+        self.timestamp = now;
+        Ok(())
+    }
+
     pub fn time_delta(&self) -> Duration {
         self.duration
     }
 
+    /// Returns true if the collector has not been able to read new data recently.
+    ///
+    /// The values returned by get_new() and get_new_and_old() will be the last data that was
+    /// successfully read.
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
     pub fn get_new_and_old(&self) -> (&T, &T) {
         if self.a_newer {
             (&self.a, &self.b)
@@ -53,126 +165,799 @@ impl<T: StatType> StatsDelta<T> {
     }
 }
 
-pub trait StatsDeltaConstructor {
-    fn new() -> Self;
-}
-
 pub trait StatsDeltaIntf {
-    fn name(&self) -> &'static str;
+    fn name(&self) -> String;
     fn update(&mut self, now: Instant) -> Result<(), StatsError>;
+    /// Serialize the newest sample, for recording.
+    fn save_sample(&self) -> Result<serde_json::Value, StatsError>;
+    /// Update using a previously recorded sample rather than reading new data from the system.
+    fn load_sample(&mut self, now: Instant, sample: serde_json::Value) -> Result<(), StatsError>;
+    /// Update using a sample read by a worker thread.
+    fn load_collected(
+        &mut self,
+        time: Instant,
+        sample: Box<dyn Any + Send>,
+    ) -> Result<(), StatsError>;
+    fn is_stale(&self) -> bool;
+    fn set_stale(&mut self, stale: bool);
+    /// The time the newest sample was read.
+    fn timestamp(&self) -> Instant;
+    /// Returns false until the first sample has been read.
+    fn is_initialized(&self) -> bool;
+    /// Update using the newest sample from another StatsDelta of the same type.
+    fn copy_newest(&mut self, other: &dyn StatsDeltaIntf);
+    fn as_any(&self) -> &dyn Any;
 }
 
-impl<T: StatType> StatsDeltaConstructor for StatsDelta<T> {
-    fn new() -> Self {
-        let now = Instant::now();
-        let mut s = T::new_zero();
-        if let Err(e) = s.update() {
-            error!("error initializing {} stats: {:?}", T::name(), e);
-            // Fall through anyway and initialize the structure with 0 values
+impl<T: StatType> StatsDeltaIntf for StatsDelta<T> {
+    fn name(&self) -> String {
+        T::instance_name(&self.params)
+    }
+
+    fn update(&mut self, now: Instant) -> Result<(), StatsError> {
+        self.advance(now, |s, params| s.update(params))
+    }
+
+    fn save_sample(&self) -> Result<serde_json::Value, StatsError> {
+        serde_json::to_value(self.get_new()).map_err(|e| StatsError::ParseError(e.to_string()))
+    }
+
+    fn load_sample(&mut self, now: Instant, sample: serde_json::Value) -> Result<(), StatsError> {
+        let sample: T =
+            serde_json::from_value(sample).map_err(|e| StatsError::ParseError(e.to_string()))?;
+        self.advance(now, |s, _params| {
+            *s = sample;
+            Ok(())
+        })
+    }
+
+    fn load_collected(
+        &mut self,
+        time: Instant,
+        sample: Box<dyn Any + Send>,
+    ) -> Result<(), StatsError> {
+        let sample = sample
+            .downcast::<Result<T, StatsError>>()
+            .map_err(|_| StatsError::ParseError("unexpected sample type".to_string()))?;
+        let sample = (*sample)?;
+        if !self.initialized {
+            // Use the first sample as both the old and new values, so that the first delta
+            // computed is not relative to 0.
+            *self = Self::from_sample(self.params.clone(), sample, time);
+            return Ok(());
         }
+        self.advance(time, |s, _params| {
+            *s = sample;
+            Ok(())
+        })
+    }
 
-        Self {
-            a: s.clone(),
-            b: s.clone(),
-            a_newer: true,
-            timestamp: now,
-            duration: Duration::from_millis(0),
+    fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    fn set_stale(&mut self, stale: bool) {
+        self.stale = stale;
+    }
+
+    fn timestamp(&self) -> Instant {
+        self.timestamp
+    }
+
+    fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    fn copy_newest(&mut self, other: &dyn StatsDeltaIntf) {
+        let Some(other) = other.as_any().downcast_ref::<Self>() else {
+            // This should only happen if we have a bug somewhere
+            error!("cannot copy {} stats from {}", self.name(), other.name());
+            return;
+        };
+        if !self.initialized {
+            *self = Self::from_sample(
+                self.params.clone(),
+                other.get_new().clone(),
+                other.timestamp,
+            );
+            return;
         }
+        // The closure never fails, so neither does advance()
+        let _ = self.advance(other.timestamp, |s, _params| {
+            s.clone_from(other.get_new());
+            Ok(())
+        });
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 }
 
-impl<T: StatType> StatsDeltaIntf for StatsDelta<T> {
-    fn name(&self) -> &'static str {
-        T::name()
+/// A sample read by a worker thread, to be passed to AllStats::receive_sample()
+pub struct CollectedSample {
+    key: CollectorKey,
+    // When the sample was read
+    time: Instant,
+    // The Result<T, StatsError> returned by the collector
+    sample: Box<dyn Any + Send>,
+}
+
+// A thread that reads the stats for one collector.
+//
+// Each collector gets its own thread, so that a collector that blocks (for instance, on a hung
+// network filesystem) does not hold up the others.
+struct Worker {
+    requests: mpsc::Sender<()>,
+    // When the outstanding read request was sent, if there is one
+    pending_since: Option<Instant>,
+}
+
+impl Worker {
+    fn spawn<T: StatType>(
+        key: CollectorKey,
+        params: T::Params,
+        results: glib::Sender<CollectedSample>,
+    ) -> std::io::Result<Worker> {
+        let (requests, receiver) = mpsc::channel::<()>();
+        std::thread::Builder::new()
+            .name("waymon-stats".to_string())
+            .spawn(move || {
+                // This exits once the collector is dropped and the request channel is closed.
+                for () in receiver {
+                    let mut sample = T::new_zero();
+                    let result = sample.update(&params).map(|()| sample);
+                    let collected = CollectedSample {
+                        key: key.clone(),
+                        time: Instant::now(),
+                        sample: Box::new(result),
+                    };
+                    if results.send(collected).is_err() {
+                        break;
+                    }
+                }
+            })?;
+        Ok(Worker {
+            requests,
+            pending_since: None,
+        })
     }
 
-    fn update(&mut self, now: Instant) -> Result<(), StatsError> {
-        if self.a_newer {
-            self.b.update()?;
-        } else {
-            self.a.update()?;
+    // Mark the stats as stale if the outstanding read request has taken too long.
+    fn check_timeout(&self, now: Instant, stats: &SharedStats, timeout: Duration) {
+        let Some(since) = self.pending_since else {
+            return;
+        };
+        if now - since > timeout {
+            let mut s = stats.borrow_mut();
+            if !s.is_stale() {
+                warn!(
+                    "reading {} has taken more than {:?}, showing stale data",
+                    s.name(),
+                    timeout
+                );
+                s.set_stale(true);
+            }
+        }
+    }
+
+    // Ask the worker to read a new sample, unless it is still busy with the previous one.
+    fn request(&mut self, now: Instant) {
+        if self.pending_since.is_some() {
+            return;
+        }
+        if self.requests.send(()).is_ok() {
+            self.pending_since = Some(now);
         }
-        self.a_newer = !self.a_newer;
-        self.duration = now - self.timestamp;
-        self.timestamp = now;
-        Ok(())
     }
 }
 
+/// A type-erased handle to one of the StatsDelta objects in AllStats
+pub type SharedStats = Rc<RefCell<dyn StatsDeltaIntf>>;
+
+/// Where AllStats gets its data from
 #[derive(Default)]
-pub struct AllStats {
-    proc_stats: Option<Rc<RefCell<StatsDelta<ProcStat>>>>,
-    disk_stats: Option<Rc<RefCell<StatsDelta<ProcDiskStats>>>>,
-    net_stats: Option<Rc<RefCell<StatsDelta<NetDevStats>>>>,
-    mem_stats: Option<Rc<RefCell<StatsDelta<MemoryStats>>>>,
-    cpu_pressure: Option<Rc<RefCell<StatsDelta<CpuPressure>>>>,
-    io_pressure: Option<Rc<RefCell<StatsDelta<IoPressure>>>>,
-    mem_pressure: Option<Rc<RefCell<StatsDelta<MemoryPressure>>>>,
+pub enum StatsSource {
+    /// Read stats from the system
+    #[default]
+    Live,
+    /// Read stats from the system, and also save each sample to a file
+    Record(Recorder),
+    /// Replay stats previously saved with Record
+    Replay(Replayer),
 }
 
-impl AllStats {
-    pub fn new() -> Self {
-        Default::default()
+/// How often a consumer of stats (normally a single widget) wants new data.
+///
+/// Each collector is read at the fastest interval of any schedule that uses it.  Consumers get a
+/// separate StatsDelta for each schedule, which only advances once per interval, so the deltas
+/// computed by a slower widget span its whole interval rather than just the latest read.
+pub struct UpdateSchedule {
+    interval: Duration,
+    // Set when any of the stats using this schedule receive new data
+    updated: Cell<bool>,
+}
+
+impl UpdateSchedule {
+    pub fn new(interval: Duration) -> Rc<UpdateSchedule> {
+        Rc::new(UpdateSchedule {
+            interval,
+            updated: Cell::new(false),
+        })
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns true if any stats using this schedule have new data since the last call.
+    pub fn take_updated(&self) -> bool {
+        self.updated.replace(false)
+    }
+}
+
+// The interval for stats requested outside of AllStats::with_schedule()
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Returns true if a periodic event is due.
+//
+// We only check on each tick, so events that will become due within `slack` count as due now.
+fn is_due(due: Option<Instant>, now: Instant, slack: Duration) -> bool {
+    match due {
+        Some(due) => now + slack >= due,
+        None => true,
+    }
+}
+
+// Compute when a periodic event is next due, after running it at `now`.
+//
+// This advances from the previous due time rather than from `now`, so that running a little
+// early or late does not change the average rate, unless we have fallen a whole interval behind.
+fn next_due(due: Option<Instant>, now: Instant, interval: Duration) -> Instant {
+    match due {
+        Some(due) if due + interval > now => due + interval,
+        _ => now + interval,
+    }
+}
+
+// Collectors are identified by their type, plus the Debug representation of their parameters.
+type CollectorKey = (TypeId, String);
+
+// The StatsDelta handed out to consumers using one particular schedule
+struct View {
+    schedule: Weak<UpdateSchedule>,
+    stats: SharedStats,
+    // The same object as `stats`, but with its concrete type, so that get() can downcast it.
+    typed: Rc<dyn Any>,
+    // When the collector's next sample should be copied into this view
+    next_due: Option<Instant>,
+}
+
+impl View {
+    // The number of references to the StatsDelta held by the View itself
+    const OWN_REFS: usize = 2;
+
+    fn is_in_use(&self) -> bool {
+        Rc::strong_count(&self.typed) > Self::OWN_REFS
+    }
+}
+
+struct Collector {
+    // Updated each time the collector is read, and copied into the views according to their
+    // schedules.  This is also what gets recorded and replayed.
+    stats: SharedStats,
+    views: Vec<View>,
+    // The thread reading this collector's stats, if collecting on worker threads
+    worker: Option<Worker>,
+    // When the collector should next be read
+    next_read: Option<Instant>,
+    // Set when `stats` has new data that has not been written to the recording yet
+    unrecorded: bool,
+}
+
+impl Collector {
+    fn new<T: StatType>(
+        key: CollectorKey,
+        params: T::Params,
+        source: &StatsSource,
+        worker_results: Option<&glib::Sender<CollectedSample>>,
+    ) -> Collector {
+        let mut worker = None;
+        let delta = match (source, worker_results) {
+            // When replaying, start from the current recorded sample rather than reading from
+            // the system.
+            (StatsSource::Replay(replayer), _) => replayer.initial_stats::<T>(params),
+            (_, Some(results)) => {
+                match Worker::spawn::<T>(key, params.clone(), results.clone()) {
+                    Ok(w) => worker = Some(w),
+                    Err(e) => error!(
+                        "unable to start thread for {}, reading it synchronously: {}",
+                        T::instance_name(&params),
+                        e
+                    ),
+                }
+                StatsDelta::<T>::new_uninitialized(params)
+            }
+            (_, None) => StatsDelta::<T>::new(params),
+        };
+        Collector {
+            unrecorded: delta.initialized && !matches!(source, StatsSource::Replay(_)),
+            stats: Rc::new(RefCell::new(delta)),
+            views: Vec::new(),
+            worker,
+            next_read: None,
+        }
     }
 
-    pub fn get_proc_stats(&mut self) -> Rc<RefCell<StatsDelta<ProcStat>>> {
-        Self::get_stat(&mut self.proc_stats)
+    /// The collector is read at the fastest interval of any of its views.
+    fn interval(&self) -> Duration {
+        self.views
+            .iter()
+            .filter_map(|view| view.schedule.upgrade())
+            .map(|schedule| schedule.interval)
+            .min()
+            .unwrap_or(DEFAULT_INTERVAL)
     }
 
-    pub fn get_disk_stats(&mut self) -> Rc<RefCell<StatsDelta<ProcDiskStats>>> {
-        Self::get_stat(&mut self.disk_stats)
+    /// Copy the newest sample into each view that is due for one.
+    fn update_views(&mut self) {
+        // Reads do not happen at exactly the collector's interval, so give each view the sample
+        // closest to its due time.
+        let slack = self.interval() / 2;
+        let Ok(stats) = self.stats.try_borrow() else {
+            // This should only happen if we have a bug somewhere
+            error!("error updating stats views: stats data is currently borrowed");
+            return;
+        };
+        let time = stats.timestamp();
+        for view in &mut self.views {
+            let Some(schedule) = view.schedule.upgrade() else {
+                continue;
+            };
+            let Ok(mut v) = view.stats.try_borrow_mut() else {
+                error!("error updating stats views: stats data is currently borrowed");
+                continue;
+            };
+            if v.is_stale() != stats.is_stale() {
+                v.set_stale(stats.is_stale());
+            }
+            let has_newer = stats.is_initialized() && (!v.is_initialized() || v.timestamp() < time);
+            if !has_newer || !is_due(view.next_due, time, slack) {
+                continue;
+            }
+            v.copy_newest(&*stats);
+            view.next_due = Some(next_due(view.next_due, time, schedule.interval));
+            schedule.updated.set(true);
+        }
     }
+}
+
+pub struct AllStats {
+    source: StatsSource,
+    collectors: HashMap<CollectorKey, Collector>,
+    // If set, new collectors read their stats on worker threads, and send the results here
+    worker_results: Option<glib::Sender<CollectedSample>>,
+    // How long to wait for a worker thread before showing its stats as stale
+    timeout: Duration,
+    // The schedule that get() currently adds stats to
+    schedule: Rc<UpdateSchedule>,
+}
 
-    pub fn get_net_stats(&mut self) -> Rc<RefCell<StatsDelta<NetDevStats>>> {
-        Self::get_stat(&mut self.net_stats)
+impl AllStats {
+    pub fn new(source: StatsSource) -> Self {
+        Self {
+            source,
+            collectors: HashMap::new(),
+            worker_results: None,
+            timeout: Duration::from_secs(2),
+            schedule: UpdateSchedule::new(DEFAULT_INTERVAL),
+        }
     }
 
-    pub fn get_mem_stats(&mut self) -> Rc<RefCell<StatsDelta<MemoryStats>>> {
-        Self::get_stat(&mut self.mem_stats)
+    /// Read stats on worker threads, rather than synchronously in update().
+    ///
+    /// Samples are delivered through the returned channel, which should be attached to the main
+    /// context with a callback that passes them to receive_sample().  This only affects
+    /// collectors created after this call.
+    pub fn use_worker_threads(&mut self) -> glib::Receiver<CollectedSample> {
+        // glib 0.18 deprecates MainContext::channel() in favor of async channels, but it is
+        // still the simplest way to hand data from a thread to the main loop.
+        #[allow(deprecated)]
+        let (sender, receiver) = glib::MainContext::channel(glib::Priority::DEFAULT);
+        self.worker_results = Some(sender);
+        receiver
     }
 
-    pub fn get_cpu_pressure(&mut self) -> Rc<RefCell<StatsDelta<CpuPressure>>> {
-        Self::get_stat(&mut self.cpu_pressure)
+    /// Set how long a worker thread can take to read new stats before they are shown as stale.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// If replaying recorded stats, returns how long to wait before advancing to the next sample.
+    pub fn replay_interval(&self) -> Option<Duration> {
+        match &self.source {
+            StatsSource::Replay(replayer) => replayer.interval(),
+            _ => None,
+        }
     }
 
-    pub fn get_io_pressure(&mut self) -> Rc<RefCell<StatsDelta<IoPressure>>> {
-        Self::get_stat(&mut self.io_pressure)
+    /// Call `f`, with any stats it requests through get() updated according to `schedule`.
+    ///
+    /// This is used when creating a widget, so that the widget's stats follow its interval.
+    pub fn with_schedule<R>(
+        &mut self,
+        schedule: &Rc<UpdateSchedule>,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        let old_schedule = std::mem::replace(&mut self.schedule, schedule.clone());
+        let result = f(self);
+        self.schedule = old_schedule;
+        result
     }
 
-    pub fn get_mem_pressure(&mut self) -> Rc<RefCell<StatsDelta<MemoryPressure>>> {
-        Self::get_stat(&mut self.mem_pressure)
+    /// Get the stats for a collector, creating it if it is not already in use.
+    ///
+    /// Consumers that request the same collector with the same parameters and schedule share a
+    /// single StatsDelta object.
+    pub fn get<T: StatType>(&mut self, params: T::Params) -> Rc<RefCell<StatsDelta<T>>> {
+        let key = (TypeId::of::<T>(), format!("{:?}", params));
+        let source = &self.source;
+        let worker_results = self.worker_results.as_ref();
+        let collector = self
+            .collectors
+            .entry(key.clone())
+            .or_insert_with(|| Collector::new::<T>(key, params.clone(), source, worker_results));
+
+        let schedule = Rc::downgrade(&self.schedule);
+        if let Some(view) = collector
+            .views
+            .iter()
+            .find(|view| view.schedule.ptr_eq(&schedule))
+        {
+            if let Ok(stats) = view.typed.clone().downcast::<RefCell<StatsDelta<T>>>() {
+                return stats;
+            }
+        }
+
+        let mut delta = StatsDelta::<T>::new_uninitialized(params);
+        let mut next = None;
+        {
+            let current = collector.stats.borrow();
+            if current.is_initialized() {
+                delta.copy_newest(&*current);
+                next = Some(next_due(None, current.timestamp(), self.schedule.interval));
+            }
+        }
+        let stats = Rc::new(RefCell::new(delta));
+        collector.views.push(View {
+            schedule,
+            stats: stats.clone(),
+            typed: stats.clone(),
+            next_due: next,
+        });
+        stats
     }
 
+    /// Read the collectors that are due, and pass new data on to their consumers.
     pub fn update(&mut self, now: Instant) {
-        Self::update_stat(&mut self.proc_stats, now);
-        Self::update_stat(&mut self.disk_stats, now);
-        Self::update_stat(&mut self.net_stats, now);
-        Self::update_stat(&mut self.mem_stats, now);
-        Self::update_stat(&mut self.cpu_pressure, now);
-        Self::update_stat(&mut self.io_pressure, now);
-        Self::update_stat(&mut self.mem_pressure, now);
-    }
-
-    fn get_stat<T: StatType>(
-        stat: &mut Option<Rc<RefCell<StatsDelta<T>>>>,
-    ) -> Rc<RefCell<StatsDelta<T>>> {
-        stat.get_or_insert_with(|| Rc::new(RefCell::new(StatsDelta::<T>::new())))
-            .clone()
-    }
-
-    fn update_stat<T: StatType>(stat: &mut Option<Rc<RefCell<StatsDelta<T>>>>, now: Instant) {
-        if let Some(stat_cell) = stat {
-            match stat_cell.try_borrow_mut() {
-                Ok(mut s) => {
-                    if let Err(e) = s.update(now) {
-                        error!("error updating {}: {:?}", s.name(), e);
+        // Drop views, and then collectors, that are no longer used by any widget, e.g. after a
+        // config reload.
+        for collector in self.collectors.values_mut() {
+            collector.views.retain(View::is_in_use);
+        }
+        self.collectors
+            .retain(|_key, collector| !collector.views.is_empty());
+
+        if let StatsSource::Replay(replayer) = &mut self.source {
+            // Recordings are replayed at the rate they were recorded
+            let stats: Vec<SharedStats> = self
+                .collectors
+                .values()
+                .map(|collector| collector.stats.clone())
+                .collect();
+            replayer.replay_next(&stats);
+            for collector in self.collectors.values_mut() {
+                collector.update_views();
+            }
+            return;
+        }
+
+        // The tick interval is normally the fastest collector interval, so this treats reads due
+        // before the next tick as being due now.
+        let slack = self
+            .collectors
+            .values()
+            .map(Collector::interval)
+            .min()
+            .unwrap_or_default()
+            / 2;
+        for collector in self.collectors.values_mut() {
+            if let Some(worker) = &collector.worker {
+                worker.check_timeout(now, &collector.stats, self.timeout);
+            }
+            if is_due(collector.next_read, now, slack) {
+                collector.next_read =
+                    Some(next_due(collector.next_read, now, collector.interval()));
+                match &mut collector.worker {
+                    Some(worker) => worker.request(now),
+                    None => {
+                        Self::update_stat(&collector.stats, now);
+                        collector.unrecorded = true;
                     }
                 }
-                Err(_) => {
-                    // This should only happen if we have a bug somewhere
-                    error!("error updating stats struct: stats data is currently borrowed");
+            }
+            collector.update_views();
+        }
+
+        if let StatsSource::Record(recorder) = &mut self.source {
+            let stats: Vec<SharedStats> = self
+                .collectors
+                .values_mut()
+                .filter_map(|collector| {
+                    std::mem::take(&mut collector.unrecorded).then(|| collector.stats.clone())
+                })
+                .collect();
+            if stats.is_empty() {
+                return;
+            }
+            if let Err(e) = recorder.write_sample(now, &stats) {
+                error!("error writing stats recording, recording stopped: {:#}", e);
+                self.source = StatsSource::Live;
+            }
+        }
+    }
+
+    /// Store a sample read by a worker thread.
+    pub fn receive_sample(&mut self, collected: CollectedSample) {
+        // The collector may have been dropped while the sample was being read
+        let Some(collector) = self.collectors.get_mut(&collected.key) else {
+            return;
+        };
+        if let Some(worker) = &mut collector.worker {
+            worker.pending_since = None;
+        }
+
+        {
+            let Ok(mut s) = collector.stats.try_borrow_mut() else {
+                // This should only happen if we have a bug somewhere
+                error!("error updating stats struct: stats data is currently borrowed");
+                return;
+            };
+            if s.is_stale() {
+                info!("reading {} has recovered", s.name());
+                s.set_stale(false);
+            }
+            match s.load_collected(collected.time, collected.sample) {
+                Ok(()) => collector.unrecorded = true,
+                Err(e) => error!("error updating {}: {:?}", s.name(), e),
+            }
+        }
+        collector.update_views();
+    }
+
+    fn update_stat(stat: &SharedStats, now: Instant) {
+        match stat.try_borrow_mut() {
+            Ok(mut s) => {
+                if let Err(e) = s.update(now) {
+                    error!("error updating {}: {:?}", s.name(), e);
                 }
             }
+            Err(_) => {
+                // This should only happen if we have a bug somewhere
+                error!("error updating stats struct: stats data is currently borrowed");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::VecDeque;
+
+    // A fake collector whose samples are supplied by the test
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    struct FakeCounters {
+        count64: u64,
+        count32: u32,
+    }
+
+    thread_local! {
+        static SAMPLES: RefCell<VecDeque<FakeCounters>> = const { RefCell::new(VecDeque::new()) };
+    }
+
+    impl StatType for FakeCounters {
+        type Params = ();
+
+        fn new_zero() -> Self {
+            Default::default()
+        }
+
+        fn update(&mut self, _params: &()) -> Result<(), StatsError> {
+            *self = SAMPLES
+                .with(|samples| samples.borrow_mut().pop_front())
+                .ok_or_else(|| StatsError::ParseError("no more samples".to_string()))?;
+            Ok(())
+        }
+
+        fn name() -> &'static str {
+            "fake"
+        }
+    }
+
+    fn sample(count64: u64, count32: u32) -> FakeCounters {
+        FakeCounters { count64, count32 }
+    }
+
+    /// Feed the samples through a StatsDelta, and return the deltas computed after each update
+    fn compute_deltas(samples: &[FakeCounters]) -> Vec<(u64, u32)> {
+        SAMPLES.with(|s| s.borrow_mut().extend(samples.iter().cloned()));
+        let mut delta = StatsDelta::<FakeCounters>::new(());
+        let mut results = Vec::new();
+        let mut now = Instant::now();
+        for _ in 1..samples.len() {
+            now += Duration::from_secs(1);
+            delta.update(now).unwrap();
+            let (new, old) = delta.get_new_and_old();
+            results.push((
+                counter_delta(new.count64, old.count64),
+                counter_delta_u32(new.count32, old.count32),
+            ));
+        }
+        results
+    }
+
+    #[test]
+    fn test_increasing() {
+        let deltas = compute_deltas(&[sample(100, 10), sample(250, 15), sample(250, 40)]);
+        assert_eq!(deltas, vec![(150, 5), (0, 25)]);
+    }
+
+    #[test]
+    fn test_wrap() {
+        let deltas = compute_deltas(&[
+            sample(100, u32::MAX - 9),
+            // The 32-bit counter wraps around
+            sample(200, 20),
+            sample(300, 25),
+        ]);
+        assert_eq!(deltas, vec![(100, 30), (100, 5)]);
+    }
+
+    #[test]
+    fn test_reset() {
+        let deltas = compute_deltas(&[
+            sample(5_000_000_000, 1_000_000),
+            // The counters are reset, and count up from 0 again.  Both report no change for the
+            // sample where the reset happened.
+            sample(300, 40),
+            sample(700, 100),
+            sample(1000, 1_000),
+            // The old 32-bit value is below the wrap range, so this is a reset too
+            sample(10, 1),
+            // A 64-bit counter is never treated as wrapping, even if the old value would have
+            // been near the 32-bit limit
+            sample(u64::from(u32::MAX) - 99, 2),
+            sample(50, 3),
+        ]);
+        assert_eq!(
+            deltas,
+            vec![
+                (0, 0),
+                (400, 60),
+                (300, 900),
+                (0, 0),
+                (4294967186, 1),
+                (0, 1)
+            ]
+        );
+    }
+
+    // A fake collector with one instance per ID, whose value is just its ID
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    struct FakeInstance {
+        id: u32,
+    }
+
+    impl StatType for FakeInstance {
+        type Params = u32;
+
+        fn new_zero() -> Self {
+            Default::default()
+        }
+
+        fn update(&mut self, params: &u32) -> Result<(), StatsError> {
+            self.id = *params;
+            Ok(())
+        }
+
+        fn name() -> &'static str {
+            "fake_instance"
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut all_stats = AllStats::new(StatsSource::Live);
+        let a1 = all_stats.get::<FakeInstance>(1);
+        let a2 = all_stats.get::<FakeInstance>(1);
+        let b = all_stats.get::<FakeInstance>(2);
+        assert!(Rc::ptr_eq(&a1, &a2));
+        assert!(!Rc::ptr_eq(&a1, &b));
+        assert_eq!(a1.borrow().get_new().id, 1);
+        assert_eq!(b.borrow().get_new().id, 2);
+        assert_eq!(all_stats.collectors.len(), 2);
+
+        // Collectors that are no longer referenced by any widget are dropped on the next update
+        drop(b);
+        all_stats.update(Instant::now());
+        assert_eq!(all_stats.collectors.len(), 1);
+        assert!(Rc::ptr_eq(&a1, &all_stats.get::<FakeInstance>(1)));
+    }
+
+    #[test]
+    fn test_schedules() {
+        SAMPLES.with(|s| s.borrow_mut().extend((0..=7).map(|n| sample(n * 10, 0))));
+        let mut all_stats = AllStats::new(StatsSource::Live);
+        let fast = UpdateSchedule::new(Duration::from_secs(1));
+        let slow = UpdateSchedule::new(Duration::from_secs(3));
+        let fast_stats = all_stats.with_schedule(&fast, |s| s.get::<FakeCounters>(()));
+        let slow_stats = all_stats.with_schedule(&slow, |s| s.get::<FakeCounters>(()));
+        assert!(!Rc::ptr_eq(&fast_stats, &slow_stats));
+        assert_eq!(all_stats.collectors.len(), 1);
+
+        let start = Instant::now();
+        let mut slow_updates = Vec::new();
+        for tick in 1..=6 {
+            all_stats.update(start + Duration::from_secs(tick));
+            // The collector is read on every tick, for the faster schedule
+            assert!(fast.take_updated());
+            assert_eq!(fast_stats.borrow().get_new().count64, tick * 10);
+            if slow.take_updated() {
+                slow_updates.push(tick);
+            }
         }
+        assert_eq!(slow_updates, vec![3, 6]);
+
+        // The slower schedule's deltas span its whole interval
+        let s = slow_stats.borrow();
+        let (new, old) = s.get_new_and_old();
+        assert_eq!((new.count64, old.count64), (60, 30));
+        assert_eq!(s.time_delta(), Duration::from_secs(3));
+        drop(s);
+
+        // Once the fast consumer is gone, the collector is read at the slower interval
+        drop(fast_stats);
+        all_stats.update(start + Duration::from_secs(7));
+        let collector = all_stats.collectors.values().next().unwrap();
+        assert_eq!(collector.views.len(), 1);
+        assert_eq!(collector.interval(), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_load_collected() {
+        let start = Instant::now();
+        let mut delta = StatsDelta::<FakeInstance>::new_uninitialized(5);
+        assert_eq!(delta.get_new().id, 0);
+
+        // The first sample is used as both the old and new values
+        let sample: Result<FakeInstance, StatsError> = Ok(FakeInstance { id: 7 });
+        delta.load_collected(start, Box::new(sample)).unwrap();
+        let (new, old) = delta.get_new_and_old();
+        assert_eq!((new.id, old.id), (7, 7));
+
+        let sample: Result<FakeInstance, StatsError> = Ok(FakeInstance { id: 9 });
+        let later = start + Duration::from_millis(1500);
+        delta.load_collected(later, Box::new(sample)).unwrap();
+        let (new, old) = delta.get_new_and_old();
+        assert_eq!((new.id, old.id), (9, 7));
+        assert_eq!(delta.time_delta(), Duration::from_millis(1500));
+
+        // Errors leave the existing samples untouched
+        let sample: Result<FakeInstance, StatsError> =
+            Err(StatsError::ParseError("bad data".to_string()));
+        assert!(delta.load_collected(later, Box::new(sample)).is_err());
+        assert_eq!(delta.get_new().id, 9);
     }
 }
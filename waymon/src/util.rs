@@ -35,3 +35,12 @@ pub fn humanify_f64(value: f64, sigfigs: u32) -> String {
         format_f64_helper(value, 1_000_000_000_000.0, "TB", sigfigs)
     }
 }
+
+/// Convert a SystemTime to seconds since the Unix epoch.
+///
+/// Times before the epoch are treated as the epoch itself.
+pub fn unix_time(time: std::time::SystemTime) -> f64 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
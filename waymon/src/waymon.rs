@@ -1,10 +1,12 @@
 use crate::bar::Bar;
 use crate::config::{BarConfig, Config, MonitorRule, NO_BAR_NAME};
-use crate::stats::AllStats;
+use crate::history::{collect_bars, HistoryStore, SavedHistory};
+use crate::read::{set_system_roots, SystemRoots};
+use crate::stats::{AllStats, StatsSource};
 use anyhow::Result;
 use gtk::pango::EllipsizeMode;
 use gtk::prelude::*;
-use gtk::{gdk, glib};
+use gtk::{gdk, gio, glib};
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
@@ -12,14 +14,26 @@ use std::rc::Rc;
 use std::time::{Duration, Instant};
 use tracing::{debug, error, info, warn};
 
+const CONFIG_FILE_NAME: &str = "config.toml";
+const CSS_FILE_NAME: &str = "style.css";
+
 /// A singleton containing global state for the application
 pub struct Waymon {
     pub display: gdk::Display,
     config_dir: PathBuf,
     pub config: Config,
+    // System root directories specified on the command line.
+    // These take precedence over the settings in the config file.
+    root_overrides: SystemRoots,
+    css_provider: Option<gtk::CssProvider>,
+    // We hold on to the file monitors so they stay alive for as long as we are running.
+    file_monitors: Vec<gio::FileMonitor>,
+    config_changed: bool,
+    css_changed: bool,
     timeout_id: Option<glib::source::SourceId>,
     monitors: HashMap<gdk::Monitor, MonitorState>,
     pub all_stats: AllStats,
+    history: HistoryStore,
 }
 
 /// A helper class that just wraps an Rc<RefCell<Waymon>>
@@ -28,8 +42,13 @@ pub struct WaymonState {
 }
 
 impl WaymonState {
-    pub fn new(config_dir: &Path) -> Result<WaymonState> {
-        let waymon = Waymon::new(&config_dir)?;
+    pub fn new(
+        config_dir: &Path,
+        root_overrides: SystemRoots,
+        stats_source: StatsSource,
+        history_path: Option<PathBuf>,
+    ) -> Result<WaymonState> {
+        let waymon = Waymon::new(&config_dir, root_overrides, stats_source, history_path)?;
         Ok(WaymonState {
             cell: Rc::new(RefCell::new(waymon)),
         })
@@ -39,46 +58,152 @@ impl WaymonState {
         let mut waymon = self.cell.borrow_mut();
         waymon.start(self.cell.clone());
     }
+
+    /// Save state that should persist across restarts, before exiting.
+    pub fn shutdown(&self) {
+        let mut waymon = self.cell.borrow_mut();
+        waymon.save_history(Instant::now());
+    }
 }
 
 impl Waymon {
-    pub fn new(config_dir: &Path) -> Result<Waymon> {
-        let waymon = Waymon {
+    pub fn new(
+        config_dir: &Path,
+        root_overrides: SystemRoots,
+        stats_source: StatsSource,
+        history_path: Option<PathBuf>,
+    ) -> Result<Waymon> {
+        let mut waymon = Waymon {
             display: gdk::Display::default()
                 .ok_or_else(|| anyhow::anyhow!("failed to get GTK display"))?,
             config_dir: config_dir.to_path_buf(),
-            config: Config::load(&config_dir.join("config.toml"))?,
+            config: Config::load(&config_dir.join(CONFIG_FILE_NAME))?,
+            root_overrides,
+            css_provider: None,
+            file_monitors: Vec::new(),
+            config_changed: false,
+            css_changed: false,
             timeout_id: None,
             monitors: HashMap::new(),
-            all_stats: AllStats::new(),
+            all_stats: AllStats::new(stats_source),
+            history: HistoryStore::open(history_path),
         };
+        waymon.apply_stats_config();
         Ok(waymon)
     }
 
     pub fn css_path(&self) -> PathBuf {
-        self.config_dir.join("style.css")
+        self.config_dir.join(CSS_FILE_NAME)
+    }
+
+    pub fn toml_config_path(&self) -> PathBuf {
+        self.config_dir.join(CONFIG_FILE_NAME)
     }
 
-    /*
     pub fn reload_config(&mut self) -> Result<()> {
         let config_path = self.toml_config_path();
         self.config = Config::load(&config_path)?;
+        self.apply_stats_config();
         Ok(())
     }
-    */
+
+    fn apply_stats_config(&mut self) {
+        set_system_roots(self.root_overrides.or(&self.config.system_roots));
+        self.all_stats.set_timeout(self.config.collector_timeout);
+    }
 
     pub fn start(&mut self, rc: Rc<RefCell<Waymon>>) {
+        self.start_stats_threads(&rc);
+        self.load_css();
+        self.watch_config_files(&rc);
+        self.create_bars(rc.clone());
+        self.start_timeout(rc);
+    }
+
+    fn start_stats_threads(&mut self, rc: &Rc<RefCell<Waymon>>) {
+        // Collect stats on worker threads, so that a slow read does not block the UI
+        let receiver = self.all_stats.use_worker_threads();
+        let rc_clone = rc.clone();
+        receiver.attach(None, move |sample| {
+            rc_clone.borrow_mut().all_stats.receive_sample(sample);
+            glib::ControlFlow::Continue
+        });
+    }
+
+    fn load_css(&mut self) {
+        // Load the CSS into a new provider, and then swap it in place of the old one, if there
+        // was one.
         let css = gtk::CssProvider::new();
         css.connect_parsing_error(report_css_parsing_error);
         css.load_from_path(self.css_path());
+        if let Some(old_css) = self.css_provider.take() {
+            gtk::style_context_remove_provider_for_display(&self.display, &old_css);
+        }
         gtk::style_context_add_provider_for_display(
             &self.display,
             &css,
             gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
         );
+        self.css_provider = Some(css);
+    }
 
-        self.create_bars(rc.clone());
-        self.start_timeout(rc);
+    fn watch_config_files(&mut self, rc: &Rc<RefCell<Waymon>>) {
+        // Note that it is fine to monitor files that do not exist yet.  We will be notified if
+        // they are created later.
+        let paths = [self.toml_config_path(), self.css_path()];
+        for path in paths {
+            let file = gio::File::for_path(&path);
+            let monitor =
+                match file.monitor_file(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE) {
+                    Ok(monitor) => monitor,
+                    Err(err) => {
+                        warn!("unable to watch {} for changes: {}", path.display(), err);
+                        continue;
+                    }
+                };
+
+            let rc_clone = rc.clone();
+            let is_css = path == self.css_path();
+            monitor.connect_changed(move |_monitor, _file, _other_file, event| {
+                // Ignore Changed events, and wait for the ChangesDoneHint instead, so that we
+                // don't try to parse a file that is still in the middle of being written.
+                match event {
+                    gio::FileMonitorEvent::ChangesDoneHint
+                    | gio::FileMonitorEvent::Created
+                    | gio::FileMonitorEvent::Deleted
+                    | gio::FileMonitorEvent::Renamed
+                    | gio::FileMonitorEvent::MovedIn
+                    | gio::FileMonitorEvent::MovedOut => (),
+                    _ => return,
+                }
+                let mut waymon = rc_clone.borrow_mut();
+                if is_css {
+                    waymon.css_changed = true;
+                } else {
+                    waymon.config_changed = true;
+                }
+            });
+            self.file_monitors.push(monitor);
+        }
+    }
+
+    fn process_config_changes(&mut self) {
+        if self.css_changed {
+            self.css_changed = false;
+            info!("reloading {}", self.css_path().display());
+            self.load_css();
+        }
+
+        if self.config_changed {
+            self.config_changed = false;
+            info!("reloading {}", self.toml_config_path().display());
+            if let Err(err) = self.reload_config() {
+                // Keep running with the old configuration
+                error!("error reloading config: {:#}", err);
+                return;
+            }
+            self.configure_monitor_bars();
+        }
     }
 
     fn create_bars(&mut self, rc: Rc<RefCell<Waymon>>) {
@@ -91,21 +216,36 @@ impl Waymon {
         self.process_monitor_change(&monitors, &rc);
     }
 
-    pub fn add_widget_label(container: &gtk::Box, text: &str) {
+    pub fn add_widget_label(container: &gtk::Box, text: &str) -> gtk::Label {
         let label = gtk::Label::new(None);
         label.add_css_class("chart-header");
-        label.set_markup(&format!("<span font_desc=\"12.0\">{}</span>", text));
+        Self::set_widget_label(&label, text);
         // If the label text is very long,
         // truncate it rather than expanding the width of the widget.
         label.set_width_chars(1);
         label.set_hexpand(true);
         label.set_ellipsize(EllipsizeMode::End);
         container.append(&label);
+        label
+    }
+
+    pub fn set_widget_label(label: &gtk::Label, text: &str) {
+        label.set_markup(&format!("<span font_desc=\"12.0\">{}</span>", text));
+    }
+
+    /// How often to check whether any stats or widgets are due to be updated.
+    ///
+    /// This is normally the shortest configured interval, but when replaying recorded stats it is
+    /// determined by the recording.
+    fn tick_interval(&self) -> Duration {
+        self.all_stats
+            .replay_interval()
+            .unwrap_or_else(|| self.config.min_interval())
     }
 
     fn start_timeout(&mut self, rc: Rc<RefCell<Waymon>>) {
         assert_eq!(self.timeout_id, None);
-        self.timeout_id = Some(glib::timeout_add_local(self.config.interval, move || {
+        self.timeout_id = Some(glib::timeout_add_local(self.tick_interval(), move || {
             Self::on_tick_callback(&rc)
         }));
     }
@@ -113,14 +253,17 @@ impl Waymon {
     fn on_tick_callback(rc: &Rc<RefCell<Waymon>>) -> glib::ControlFlow {
         let mut waymon = rc.borrow_mut();
 
-        let old_interval = waymon.config.interval;
+        let old_interval = waymon.tick_interval();
         waymon.process_tick();
-        let new_interval = waymon.config.interval;
+        let new_interval = waymon.tick_interval();
         if new_interval != old_interval {
-            info!(
-                "update interval from {:?} to {:?}",
-                old_interval, new_interval
-            );
+            // When replaying, the interval follows the recorded timestamps and changes constantly
+            if waymon.all_stats.replay_interval().is_none() {
+                info!(
+                    "update interval from {:?} to {:?}",
+                    old_interval, new_interval
+                );
+            }
             let new_ref = rc.clone();
             waymon.timeout_id = Some(glib::timeout_add_local(new_interval, move || {
                 Self::on_tick_callback(&new_ref)
@@ -135,8 +278,8 @@ impl Waymon {
         let now = Instant::now();
         self.all_stats.update(now);
 
-        // TODO: check if config file or css file has been updated,
-        // and reload if needed
+        // Reload the config and CSS files if they have been changed
+        self.process_config_changes();
 
         // Update the bars on all monitors
         let mut monitors_changed = false;
@@ -163,6 +306,21 @@ impl Waymon {
         if monitors_changed {
             self.configure_monitor_bars();
         }
+
+        if self.history.is_save_due(now) {
+            self.save_history(now);
+        }
+    }
+
+    fn save_history(&mut self, now: Instant) {
+        let bars = self
+            .monitors
+            .values()
+            .filter_map(|mon_state| match mon_state {
+                MonitorState::Bar(bar) => Some(bar.save_history()),
+                _ => None,
+            });
+        self.history.save(now, collect_bars(bars));
     }
 
     fn process_monitor_change(&mut self, monitors: &gtk::gio::ListModel, rc: &Rc<RefCell<Waymon>>) {
@@ -306,7 +464,13 @@ impl Waymon {
 
         // Make sure a bar exists for every monitor
         for (mon, mon_state) in self.monitors.iter_mut() {
-            Self::ensure_bar_config(mon, mon_state, Some(primary_config), &mut self.all_stats);
+            Self::ensure_bar_config(
+                mon,
+                mon_state,
+                Some(primary_config),
+                &mut self.all_stats,
+                self.history.loaded(),
+            );
         }
     }
 
@@ -315,6 +479,7 @@ impl Waymon {
         mon_state: &mut MonitorState,
         config: Option<&BarConfig>,
         all_stats: &mut AllStats,
+        history: Option<&SavedHistory>,
     ) {
         match mon_state {
             MonitorState::Pending(_) => {
@@ -324,7 +489,7 @@ impl Waymon {
             }
             MonitorState::Bar(bar) => {
                 if let Some(bar_config) = config {
-                    bar.ensure_config(bar_config);
+                    bar.ensure_config(bar_config, all_stats);
                 } else {
                     // Clear the state.  The Bar drop() function will destroy the window.
                     *mon_state = MonitorState::NoBar;
@@ -333,7 +498,7 @@ impl Waymon {
             MonitorState::NoBar => {
                 if let Some(bar_config) = config {
                     debug!("add bar for monitor {}", monitor_desc(mon));
-                    let bar = Bar::new(mon.clone(), bar_config, all_stats);
+                    let bar = Bar::new(mon.clone(), bar_config, all_stats, history);
                     *mon_state = MonitorState::Bar(bar);
                 }
             }
@@ -346,9 +511,21 @@ impl Waymon {
 
         for (mon, mon_state) in self.monitors.iter_mut() {
             if Some(mon) == primary_mon.as_ref() {
-                Self::ensure_bar_config(mon, mon_state, Some(primary_config), &mut self.all_stats);
+                Self::ensure_bar_config(
+                    mon,
+                    mon_state,
+                    Some(primary_config),
+                    &mut self.all_stats,
+                    self.history.loaded(),
+                );
             } else {
-                Self::ensure_bar_config(mon, mon_state, None, &mut self.all_stats);
+                Self::ensure_bar_config(
+                    mon,
+                    mon_state,
+                    None,
+                    &mut self.all_stats,
+                    self.history.loaded(),
+                );
             }
         }
     }
@@ -431,7 +608,13 @@ impl Waymon {
             }
 
             let bar_config = Self::pick_monitor_config(mon, &self.config);
-            Self::ensure_bar_config(mon, mon_state, bar_config, &mut self.all_stats);
+            Self::ensure_bar_config(
+                mon,
+                mon_state,
+                bar_config,
+                &mut self.all_stats,
+                self.history.loaded(),
+            );
         }
     }
 
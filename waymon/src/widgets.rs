@@ -1,17 +1,50 @@
+pub mod cgroup;
 pub mod cpu;
+pub mod cpu_cores;
 pub mod disk_io;
+pub mod fs_usage;
 pub mod mem;
 pub mod net;
+pub mod power_supply;
+pub mod swap;
+pub mod temperature;
 pub mod timeseries;
+pub mod top;
+pub mod vmstat;
 pub mod pressure;
 
+use crate::history::WidgetHistory;
 use crate::stats::AllStats;
+use crate::widgets::timeseries::TimeAxis;
 use std::cell::RefCell;
 use std::rc::Rc;
 
 pub trait Widget {
     fn update(&mut self);
 
+    /// Change the time axis used by this widget's charts.
+    ///
+    /// This is called by the Bar when its width or gridline settings change.  Widgets should
+    /// preserve as much of their existing history as possible.
+    fn set_time_axis(&mut self, time_axis: &TimeAxis);
+
+    /// Return the data in this widget's charts, so it can be restored after waymon restarts.
+    ///
+    /// Each chart is stored under a name that is unique within the widget.
+    fn save_history(&self) -> WidgetHistory;
+
+    /// Restore chart data previously returned by save_history().
+    ///
+    /// Widgets that create charts dynamically should hold on to the history for charts that do
+    /// not exist yet, and restore it if they are created later.
+    fn restore_history(&mut self, history: WidgetHistory);
+
+    /// Returns true if the stats shown by this widget have not been updated recently, because
+    /// the collector is taking too long to read them.
+    fn is_stale(&self) -> bool {
+        false
+    }
+
     /// Return the top-level gtk::Widget for this widget.
     ///
     /// This is called by the Bar in order to add the gtk widget to it's window.
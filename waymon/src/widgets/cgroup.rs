@@ -0,0 +1,481 @@
+use crate::collectors::cgroup::{relative_cgroup_path, Cgroup, CgroupStats};
+use crate::collectors::pressure::PressureStats;
+use crate::history::WidgetHistory;
+use crate::stats::{counter_delta, AllStats, StatsDelta};
+use crate::util::humanify_f64;
+use crate::waymon::Waymon;
+use crate::widgets::timeseries::{
+    Chart, ChartDrawCallback, Color, StackedTimeseriesChart, TimeAxis,
+};
+use crate::widgets::Widget;
+use gtk::cairo;
+use gtk::glib;
+use gtk::prelude::*;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+
+// The number of child cgroups that get their own series.  Any others are combined into one
+// additional "other" series.
+const MAX_CHILDREN: usize = 5;
+const NUM_SERIES: usize = MAX_CHILDREN + 1;
+
+// How quickly a child's ranking follows changes in its usage.  Each update moves its score this
+// fraction of the way towards its current share of the usage.
+const SCORE_SMOOTHING: f64 = 0.3;
+// A child without a series only takes over the series of the least busy child when its score is
+// higher by both this ratio and this margin, so that children with similar usage do not keep
+// swapping colors.
+const SWITCH_RATIO: f64 = 1.5;
+const SWITCH_MARGIN: f64 = 0.05;
+
+const SERIES_COLORS: [(f64, f64, f64); NUM_SERIES] = [
+    (0.0, 0.0, 1.0),
+    (0.85, 0.0, 0.0),
+    (0.0, 0.5, 0.0),
+    (0.9, 0.6, 0.0),
+    (0.5, 0.0, 0.5),
+    (0.5, 0.5, 0.5),
+];
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct CgroupWidgetConfig {
+    pub label: String,
+
+    // The cgroup whose children to show, relative to /sys/fs/cgroup.
+    // Defaults to the root cgroup.
+    #[serde(default)]
+    pub cgroup: String,
+
+    // The height of each of the CPU, memory, and I/O charts
+    #[serde(default = "default_cgroup_chart_height")]
+    pub chart_height: u32,
+}
+
+fn default_cgroup_chart_height() -> u32 {
+    40
+}
+
+/// The usage of one child cgroup over the last update interval.
+#[derive(Default)]
+struct ChildUsage {
+    // CPU usage, as a percentage of one CPU
+    cpu_percent: f64,
+    memory_bytes: f64,
+    // Bytes read and written per second
+    io_rate: f64,
+}
+
+impl ChildUsage {
+    fn new(new: &Cgroup, old: Option<&Cgroup>, delta_secs: f64) -> Self {
+        let mut usage = ChildUsage {
+            memory_bytes: new.memory_current as f64,
+            ..Default::default()
+        };
+        // Cgroups that were just created have nothing to compare against yet
+        if let Some(old) = old {
+            if delta_secs > 0.0 {
+                let cpu =
+                    Duration::from_micros(counter_delta(new.cpu_usage_usec, old.cpu_usage_usec));
+                usage.cpu_percent = 100.0 * cpu.as_secs_f64() / delta_secs;
+                let io_bytes = counter_delta(new.io_read_bytes, old.io_read_bytes)
+                    + counter_delta(new.io_write_bytes, old.io_write_bytes);
+                usage.io_rate = io_bytes as f64 / delta_secs;
+            }
+        }
+        usage
+    }
+
+    /// How busy this child is compared to all of the children, as the sum of its shares of the
+    /// total CPU, memory, and I/O usage.
+    fn share_of(&self, total: &ChildUsage) -> f64 {
+        let share = |value: f64, total: f64| if total > 0.0 { value / total } else { 0.0 };
+        share(self.cpu_percent, total.cpu_percent)
+            + share(self.memory_bytes, total.memory_bytes)
+            + share(self.io_rate, total.io_rate)
+    }
+
+    fn add(&mut self, other: &ChildUsage) {
+        self.cpu_percent += other.cpu_percent;
+        self.memory_bytes += other.memory_bytes;
+        self.io_rate += other.io_rate;
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "{:.0}% CPU, {}, {}/s I/O",
+            self.cpu_percent,
+            humanify_f64(self.memory_bytes, 3),
+            humanify_f64(self.io_rate, 2),
+        )
+    }
+}
+
+/// Picks which child cgroups get their own series.
+///
+/// The busiest children are shown, rather than the first ones by name, since the root cgroup
+/// usually has several mostly idle children (e.g., "dev-hugepages.mount" and "init.scope") that
+/// sort before the interesting ones.  A child keeps its series until another child has been
+/// clearly busier for a while, so that colors do not flicker between children.
+#[derive(Default)]
+struct ChildSlots {
+    // The child shown in each series
+    slots: [Option<String>; MAX_CHILDREN],
+    // A moving average of each child's share of the usage
+    scores: HashMap<String, f64>,
+}
+
+impl ChildSlots {
+    fn update(&mut self, children: &[(&str, ChildUsage)]) {
+        let mut total = ChildUsage::default();
+        for (_, usage) in children {
+            total.add(usage);
+        }
+        let mut scores = HashMap::new();
+        for (name, usage) in children {
+            let share = usage.share_of(&total);
+            let score = match self.scores.get(*name) {
+                Some(old) => old + SCORE_SMOOTHING * (share - old),
+                None => share,
+            };
+            scores.insert(name.to_string(), score);
+        }
+        self.scores = scores;
+
+        for slot in &mut self.slots {
+            if let Some(name) = slot {
+                if !self.scores.contains_key(name) {
+                    *slot = None;
+                }
+            }
+        }
+
+        // Fill empty slots with the busiest remaining children, and then replace the least busy
+        // children if others are now much busier.
+        while let Some((name, score)) = self.busiest_unslotted() {
+            if let Some(slot) = self.slots.iter_mut().find(|slot| slot.is_none()) {
+                *slot = Some(name);
+                continue;
+            }
+            let (idx, least_score) = self.least_busy_slot();
+            if score <= least_score * SWITCH_RATIO + SWITCH_MARGIN {
+                break;
+            }
+            self.slots[idx] = Some(name);
+        }
+    }
+
+    fn score(&self, name: &str) -> f64 {
+        self.scores.get(name).copied().unwrap_or(0.0)
+    }
+
+    fn busiest_unslotted(&self) -> Option<(String, f64)> {
+        self.scores
+            .iter()
+            .filter(|(name, _)| self.slot_for(name).is_none())
+            // Break ties by name, so that the result does not depend on the hash order
+            .max_by(|(a_name, a), (b_name, b)| a.total_cmp(b).then_with(|| b_name.cmp(a_name)))
+            .map(|(name, score)| (name.clone(), *score))
+    }
+
+    /// The slot of the least busy child.  This must only be called when all slots are in use.
+    fn least_busy_slot(&self) -> (usize, f64) {
+        self.slots
+            .iter()
+            .enumerate()
+            .map(|(idx, slot)| (idx, self.score(slot.as_deref().unwrap_or_default())))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap()
+    }
+
+    fn slot_for(&self, name: &str) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| slot.as_deref() == Some(name))
+    }
+}
+
+pub struct CgroupWidget {
+    stats: Rc<RefCell<StatsDelta<CgroupStats>>>,
+    container: gtk::Box,
+    da: gtk::DrawingArea,
+    chart_height: u32,
+    // The child cgroup shown in each series
+    slots: ChildSlots,
+    cpu_chart: StackedTimeseriesChart<f64, NUM_SERIES>,
+    memory_chart: StackedTimeseriesChart<f64, NUM_SERIES>,
+    io_chart: StackedTimeseriesChart<f64, NUM_SERIES>,
+    // The latest usage of each series
+    usage: [ChildUsage; NUM_SERIES],
+    // The number of children combined into the "other" series
+    num_other: usize,
+    // The fraction of time that some tasks in the cgroup were stalled waiting for CPU, if the
+    // kernel reports CPU pressure
+    cpu_pressure: Option<f64>,
+}
+
+impl CgroupWidgetConfig {
+    pub fn create_widget(
+        &self,
+        all_stats: &mut AllStats,
+        time_axis: &TimeAxis,
+    ) -> Rc<RefCell<CgroupWidget>> {
+        let new_chart = || {
+            let mut chart = StackedTimeseriesChart::new(time_axis);
+            for (idx, (r, g, b)) in SERIES_COLORS.iter().enumerate() {
+                chart.set_color(idx, Color::new(*r, *g, *b));
+            }
+            chart
+        };
+        let widget_rc = Rc::new(RefCell::new(CgroupWidget {
+            stats: all_stats.get::<CgroupStats>(relative_cgroup_path(&self.cgroup)),
+            container: gtk::Box::new(gtk::Orientation::Vertical, /*spacing*/ 0),
+            da: gtk::DrawingArea::new(),
+            chart_height: self.chart_height,
+            slots: Default::default(),
+            cpu_chart: new_chart(),
+            memory_chart: new_chart(),
+            io_chart: new_chart(),
+            usage: Default::default(),
+            num_other: 0,
+            cpu_pressure: None,
+        }));
+        {
+            let widget = widget_rc.borrow();
+            Waymon::add_widget_label(&widget.container, &self.label);
+            Chart::configure(&widget.da, 3 * self.chart_height, widget_rc.clone());
+            widget.container.append(&widget.da);
+        }
+        widget_rc
+    }
+}
+
+fn y_scale_for(max_value: f64, height: i32) -> f64 {
+    if max_value <= 0.0 {
+        1.0
+    } else {
+        ((height - 2) as f64) / max_value
+    }
+}
+
+fn color_markup(idx: usize) -> String {
+    let (r, g, b) = SERIES_COLORS[idx];
+    let byte = |c: f64| (c * 255.0).round() as u8;
+    format!(
+        "<span foreground=\"#{:02x}{:02x}{:02x}\">■</span>",
+        byte(r),
+        byte(g),
+        byte(b)
+    )
+}
+
+impl CgroupWidget {
+    /// Describe each series in the tooltip, since the bar is too narrow for a legend.
+    fn tooltip(&self) -> String {
+        let mut lines = Vec::new();
+        for (idx, slot) in self.slots.slots.iter().enumerate() {
+            if let Some(name) = slot {
+                lines.push(format!(
+                    "{} {}: {}",
+                    color_markup(idx),
+                    glib::markup_escape_text(name),
+                    self.usage[idx].describe()
+                ));
+            }
+        }
+        if self.num_other > 0 {
+            lines.push(format!(
+                "{} {} others: {}",
+                color_markup(MAX_CHILDREN),
+                self.num_other,
+                self.usage[MAX_CHILDREN].describe()
+            ));
+        }
+        lines.join("\n")
+    }
+
+    fn total(&self) -> ChildUsage {
+        let mut total = ChildUsage::default();
+        for usage in &self.usage {
+            total.add(usage);
+        }
+        total
+    }
+}
+
+impl ChartDrawCallback for CgroupWidget {
+    fn draw(&self, cr: &cairo::Context, width: i32, _height: i32) {
+        let total = self.total();
+        // Leave a 1 pixel gap between each chart
+        let chart_height = self.chart_height.saturating_sub(1) as i32;
+        for row in 0..3 {
+            let _ = cr.save();
+            cr.translate(0.0, (row as u32 * self.chart_height) as f64);
+            cr.rectangle(0.0, 0.0, width as f64, chart_height as f64);
+            cr.clip();
+            let (chart, annotation) = match row {
+                0 => {
+                    let mut text = format!("CPU {:.0}%", total.cpu_percent);
+                    if let Some(pressure) = self.cpu_pressure {
+                        text.push_str(&format!("\nstall {:.0}%", pressure * 100.0));
+                    }
+                    (&self.cpu_chart, text)
+                }
+                1 => (
+                    &self.memory_chart,
+                    format!("Mem {}", humanify_f64(total.memory_bytes, 2)),
+                ),
+                _ => (
+                    &self.io_chart,
+                    format!("I/O {}/s", humanify_f64(total.io_rate, 2)),
+                ),
+            };
+            let y_scale = y_scale_for(chart.max_value(), chart_height);
+            chart.draw(cr, width, chart_height, y_scale);
+            Chart::draw_annotation(&self.da, cr, width, chart_height, &annotation);
+            let _ = cr.restore();
+        }
+    }
+}
+
+impl Widget for CgroupWidget {
+    fn update(&mut self) {
+        {
+            let stats_rc = self.stats.clone();
+            let s = stats_rc.borrow();
+            let (new, old) = s.get_new_and_old();
+            let delta_secs = s.time_delta().as_secs_f64();
+
+            let children: Vec<(&str, ChildUsage)> = new
+                .children
+                .iter()
+                .map(|child| {
+                    let usage = ChildUsage::new(child, old.get(&child.name), delta_secs);
+                    (child.name.as_str(), usage)
+                })
+                .collect();
+            self.slots.update(&children);
+            self.usage = Default::default();
+            self.num_other = 0;
+            for (name, usage) in &children {
+                let idx = match self.slots.slot_for(name) {
+                    Some(idx) => idx,
+                    None => {
+                        self.num_other += 1;
+                        MAX_CHILDREN
+                    }
+                };
+                self.usage[idx].add(usage);
+            }
+
+            self.cpu_pressure = match (&new.cgroup.cpu_pressure, &old.cgroup.cpu_pressure) {
+                (Some(new), Some(old)) if delta_secs > 0.0 => {
+                    let some = Duration::from_micros(counter_delta(new.some_us(), old.some_us()));
+                    Some(some.as_secs_f64() / delta_secs)
+                }
+                _ => None,
+            };
+        }
+
+        let values = |f: fn(&ChildUsage) -> f64| -> [f64; NUM_SERIES] {
+            std::array::from_fn(|idx| f(&self.usage[idx]))
+        };
+        let cpu = values(|u| u.cpu_percent);
+        let memory = values(|u| u.memory_bytes);
+        let io = values(|u| u.io_rate);
+        self.cpu_chart.add_values(&cpu);
+        self.memory_chart.add_values(&memory);
+        self.io_chart.add_values(&io);
+
+        self.da.set_tooltip_markup(Some(&self.tooltip()));
+        // Mark that the drawing area needs to be redrawn
+        self.da.queue_draw();
+    }
+
+    fn set_time_axis(&mut self, time_axis: &TimeAxis) {
+        self.cpu_chart.set_time_axis(time_axis);
+        self.memory_chart.set_time_axis(time_axis);
+        self.io_chart.set_time_axis(time_axis);
+    }
+
+    fn save_history(&self) -> WidgetHistory {
+        WidgetHistory::from([
+            ("cpu".to_string(), self.cpu_chart.save_history()),
+            ("memory".to_string(), self.memory_chart.save_history()),
+            ("io".to_string(), self.io_chart.save_history()),
+        ])
+    }
+
+    fn restore_history(&mut self, mut history: WidgetHistory) {
+        self.cpu_chart.restore_from(&mut history, "cpu");
+        self.memory_chart.restore_from(&mut history, "memory");
+        self.io_chart.restore_from(&mut history, "io");
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stats.borrow().is_stale()
+    }
+
+    fn gtk_widget(&self) -> &gtk::Box {
+        &self.container
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(cpu_percent: f64, memory_mb: f64) -> ChildUsage {
+        ChildUsage {
+            cpu_percent,
+            memory_bytes: memory_mb * 1024.0 * 1024.0,
+            io_rate: 0.0,
+        }
+    }
+
+    fn shown(slots: &ChildSlots) -> Vec<String> {
+        let mut names: Vec<String> = slots.slots.iter().flatten().cloned().collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_busiest_children_shown() {
+        // A typical systemd root cgroup, where the busy children sort last by name
+        let mut children = vec![
+            ("dev-hugepages.mount", usage(0.0, 0.1)),
+            ("dev-mqueue.mount", usage(0.0, 0.1)),
+            ("init.scope", usage(0.1, 8.0)),
+            ("proc-sys-fs-binfmt_misc.mount", usage(0.0, 0.1)),
+            ("sys-fs-fuse-connections.mount", usage(0.0, 0.1)),
+            ("sys-kernel-debug.mount", usage(0.0, 0.1)),
+            ("system.slice", usage(20.0, 900.0)),
+            ("user.slice", usage(150.0, 6000.0)),
+        ];
+        let mut slots = ChildSlots::default();
+        slots.update(&children);
+        let initial = shown(&slots);
+        assert!(initial.contains(&"system.slice".to_string()));
+        assert!(initial.contains(&"user.slice".to_string()));
+        assert!(initial.contains(&"init.scope".to_string()));
+
+        // A brief change in usage between idle children does not move them between series
+        children[1].1 = usage(0.1, 0.1);
+        slots.update(&children);
+        assert_eq!(shown(&slots), initial);
+
+        // A child that stays much busier takes over the series of the least busy one
+        children.push(("machine.slice", usage(80.0, 4000.0)));
+        slots.update(&children);
+        assert!(shown(&slots).contains(&"machine.slice".to_string()));
+        assert!(shown(&slots).contains(&"user.slice".to_string()));
+
+        // Children that are removed free up their series
+        children.retain(|(name, _)| *name != "user.slice");
+        slots.update(&children);
+        assert!(!shown(&slots).contains(&"user.slice".to_string()));
+        assert_eq!(shown(&slots).len(), MAX_CHILDREN);
+    }
+}
@@ -1,34 +1,240 @@
-use crate::collectors::procstat::ProcStat;
+use crate::collectors::procstat::{CpuStats, ProcStat, Ticks};
 use crate::config::CpuWidgetConfig;
+use crate::history::WidgetHistory;
 use crate::stats::{AllStats, StatsDelta};
 use crate::waymon::Waymon;
-use crate::widgets::timeseries::{Chart, ChartDrawCallback, StackedTimeseriesChart};
+use crate::widgets::timeseries::{
+    Chart, ChartDrawCallback, Color, StackedTimeseriesChart, TimeAxis,
+};
 use crate::widgets::Widget;
 use gtk::cairo;
 use gtk::prelude::*;
+use serde::Deserialize;
 use std::cell::RefCell;
 use std::rc::Rc;
+use tracing::warn;
+
+const NUM_CPU_CATEGORIES: usize = 8;
+
+/// The categories of CPU time reported in /proc/stat that can be shown in the chart
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CpuCategory {
+    Nice,
+    User,
+    System,
+    Iowait,
+    Irq,
+    Softirq,
+    Steal,
+    Guest,
+}
+
+impl CpuCategory {
+    fn default_color(&self) -> Color {
+        match self {
+            CpuCategory::Nice => Color::new(0.0, 0.0, 1.0),
+            CpuCategory::User => Color::new(0.0, 0.0, 0.667),
+            CpuCategory::System => Color::new(0.0, 0.0, 0.333),
+            CpuCategory::Iowait => Color::new(0.9, 0.6, 0.0),
+            CpuCategory::Irq => Color::new(0.5, 0.0, 0.5),
+            CpuCategory::Softirq => Color::new(0.75, 0.35, 0.75),
+            CpuCategory::Steal => Color::new(0.85, 0.0, 0.0),
+            CpuCategory::Guest => Color::new(0.0, 0.5, 0.0),
+        }
+    }
+
+    fn annotation_name(&self) -> &'static str {
+        match self {
+            CpuCategory::Nice => "nice",
+            CpuCategory::User => "user",
+            CpuCategory::System => "sys",
+            CpuCategory::Iowait => "iowait",
+            CpuCategory::Irq => "irq",
+            CpuCategory::Softirq => "softirq",
+            CpuCategory::Steal => "steal",
+            CpuCategory::Guest => "guest",
+        }
+    }
+
+    // The nice, user, and system categories are only included in the total usage annotation by
+    // default.  The other categories are less common, so we show them individually when they are
+    // enabled.
+    fn annotate_by_default(&self) -> bool {
+        !matches!(
+            self,
+            CpuCategory::Nice | CpuCategory::User | CpuCategory::System
+        )
+    }
+}
+
+/// Configuration for one of the series shown in the CPU chart.
+///
+/// In the config file this can be specified either as just the category name, or as a table with
+/// "category", "color", and "annotate" fields.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(from = "TomlCpuSeriesConfig")]
+pub struct CpuSeriesConfig {
+    pub category: CpuCategory,
+    pub color: Option<Color>,
+    pub annotate: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TomlCpuSeriesConfig {
+    Category(CpuCategory),
+    Full {
+        category: CpuCategory,
+        #[serde(default)]
+        color: Option<Color>,
+        #[serde(default)]
+        annotate: Option<bool>,
+    },
+}
+
+impl From<TomlCpuSeriesConfig> for CpuSeriesConfig {
+    fn from(c: TomlCpuSeriesConfig) -> Self {
+        match c {
+            TomlCpuSeriesConfig::Category(category) => CpuSeriesConfig {
+                category,
+                color: None,
+                annotate: None,
+            },
+            TomlCpuSeriesConfig::Full {
+                category,
+                color,
+                annotate,
+            } => CpuSeriesConfig {
+                category,
+                color,
+                annotate,
+            },
+        }
+    }
+}
+
+pub fn default_cpu_categories() -> Vec<CpuSeriesConfig> {
+    [CpuCategory::Nice, CpuCategory::User, CpuCategory::System]
+        .into_iter()
+        .map(|category| CpuSeriesConfig {
+            category,
+            color: None,
+            annotate: None,
+        })
+        .collect()
+}
 
 pub struct CpuWidget {
     stats: Rc<RefCell<StatsDelta<ProcStat>>>,
     container: gtk::Box,
     da: gtk::DrawingArea,
-    chart: StackedTimeseriesChart<f64, 3>,
+    // The categories being charted, in the order they are stacked in the chart,
+    // along with whether each one should be shown in the annotation.
+    categories: Vec<(CpuCategory, bool)>,
+    // Any unused series in the chart are always set to 0.
+    chart: StackedTimeseriesChart<f64, NUM_CPU_CATEGORIES>,
     usage_ratio: f64,
+    category_pcts: [f64; NUM_CPU_CATEGORIES],
+}
+
+/// The number of ticks spent in each category between two samples
+struct CpuTicksDelta {
+    nice: u64,
+    user: u64,
+    system: u64,
+    idle: u64,
+    iowait: u64,
+    irq: u64,
+    softirq: u64,
+    steal: u64,
+    guest: u64,
+    guest_nice: u64,
+}
+
+impl CpuTicksDelta {
+    fn new(new: &CpuStats, old: &CpuStats) -> Self {
+        let delta = |new: Ticks, old: Ticks| new.delta_since(old).value();
+        Self {
+            nice: delta(new.nice, old.nice),
+            user: delta(new.user, old.user),
+            system: delta(new.system, old.system),
+            idle: delta(new.idle, old.idle),
+            iowait: delta(new.iowait, old.iowait),
+            irq: delta(new.irq, old.irq),
+            softirq: delta(new.softirq, old.softirq),
+            steal: delta(new.steal, old.steal),
+            guest: delta(new.guest, old.guest),
+            guest_nice: delta(new.guest_nice, old.guest_nice),
+        }
+    }
+
+    fn total(&self) -> u64 {
+        // Note that the kernel also includes guest time in the user and nice counters, so we do
+        // not count guest time separately here.
+        self.nice
+            + self.user
+            + self.system
+            + self.idle
+            + self.iowait
+            + self.irq
+            + self.softirq
+            + self.steal
+    }
+
+    fn get(&self, category: CpuCategory, guest_shown: bool) -> u64 {
+        match category {
+            // If guest time is being charted separately, subtract it from user and nice time so
+            // that it is not counted twice.  The kernel adds guest time to user time, and
+            // guest_nice time to nice time.
+            CpuCategory::User if guest_shown => self.user.saturating_sub(self.guest),
+            CpuCategory::User => self.user,
+            CpuCategory::Nice if guest_shown => self.nice.saturating_sub(self.guest_nice),
+            CpuCategory::Nice => self.nice,
+            CpuCategory::System => self.system,
+            CpuCategory::Iowait => self.iowait,
+            CpuCategory::Irq => self.irq,
+            CpuCategory::Softirq => self.softirq,
+            CpuCategory::Steal => self.steal,
+            CpuCategory::Guest => self.guest + self.guest_nice,
+        }
+    }
 }
 
 impl CpuWidget {
     pub fn new(
         config: &CpuWidgetConfig,
         all_stats: &mut AllStats,
-        history_length: usize,
+        time_axis: &TimeAxis,
     ) -> Rc<RefCell<CpuWidget>> {
+        let mut categories: Vec<(CpuCategory, bool)> = Vec::new();
+        let mut chart = StackedTimeseriesChart::new(time_axis);
+        for series in &config.categories {
+            if categories.iter().any(|(c, _)| *c == series.category) {
+                warn!(
+                    "CPU category {:?} listed more than once in widget {:?}",
+                    series.category, config.label
+                );
+                continue;
+            }
+            let color = series
+                .color
+                .unwrap_or_else(|| series.category.default_color());
+            chart.set_color(categories.len(), color);
+            let annotate = series
+                .annotate
+                .unwrap_or_else(|| series.category.annotate_by_default());
+            categories.push((series.category, annotate));
+        }
+
         let widget_rc = Rc::new(RefCell::new(CpuWidget {
-            stats: all_stats.get_proc_stats(),
+            stats: all_stats.get::<ProcStat>(()),
             container: gtk::Box::new(gtk::Orientation::Vertical, /*spacing*/ 0),
             da: gtk::DrawingArea::new(),
-            chart: StackedTimeseriesChart::new(history_length),
+            categories,
+            chart,
             usage_ratio: 0.0,
+            category_pcts: [0.0; NUM_CPU_CATEGORIES],
         }));
         {
             let widget = widget_rc.borrow();
@@ -57,13 +263,18 @@ impl ChartDrawCallback for CpuWidget {
 
         let y_scale = (height as f64) / y_max;
         self.chart.draw(cr, width, height, y_scale);
-        Chart::draw_annotation(
-            &self.da,
-            cr,
-            width,
-            height,
-            &format!("{}%", (self.usage_ratio * 100.0) as u32),
-        );
+
+        let mut annotation = format!("{}%", (self.usage_ratio * 100.0) as u32);
+        for (idx, (category, annotate)) in self.categories.iter().enumerate() {
+            if *annotate {
+                annotation.push_str(&format!(
+                    "\n{} {:.0}%",
+                    category.annotation_name(),
+                    self.category_pcts[idx]
+                ));
+            }
+        }
+        Chart::draw_annotation(&self.da, cr, width, height, &annotation);
     }
 }
 
@@ -71,26 +282,66 @@ impl Widget for CpuWidget {
     fn update(&mut self) {
         let s = self.stats.borrow();
         let (new, old) = s.get_new_and_old();
-        let user = new.cpu.user - old.cpu.user;
-        let nice = new.cpu.nice - old.cpu.nice;
-        let system = new.cpu.system - old.cpu.system;
-        let idle = new.cpu.idle - old.cpu.idle;
-        let total_used = user + system + nice;
-        let total = total_used + idle;
-        self.usage_ratio = total_used / total;
+        let delta = CpuTicksDelta::new(&new.cpu, &old.cpu);
+        let total = delta.total();
+        let guest_shown = self
+            .categories
+            .iter()
+            .any(|(c, _)| *c == CpuCategory::Guest);
 
-        let total_f64 = total.value() as f64;
-        let nice_pct = 100.0 * (nice.value() as f64) / total_f64;
-        let user_pct = 100.0 * (user.value() as f64) / total_f64;
-        let system_pct = 100.0 * (system.value() as f64) / total_f64;
+        self.category_pcts = [0.0; NUM_CPU_CATEGORIES];
+        if total > 0 {
+            for (idx, (category, _)) in self.categories.iter().enumerate() {
+                let ticks = delta.get(*category, guest_shown);
+                self.category_pcts[idx] = 100.0 * (ticks as f64) / (total as f64);
+            }
+        }
+        self.usage_ratio = self.category_pcts.iter().sum::<f64>() / 100.0;
 
-        self.chart.add_values(&[nice_pct, user_pct, system_pct]);
+        self.chart.add_values(&self.category_pcts);
 
         // Mark that the drawing area needs to be redrawn
         self.da.queue_draw();
     }
 
+    fn set_time_axis(&mut self, time_axis: &TimeAxis) {
+        self.chart.set_time_axis(time_axis);
+    }
+
+    fn save_history(&self) -> WidgetHistory {
+        WidgetHistory::from([("chart".to_string(), self.chart.save_history())])
+    }
+
+    fn restore_history(&mut self, mut history: WidgetHistory) {
+        self.chart.restore_from(&mut history, "chart");
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stats.borrow().is_stale()
+    }
+
     fn gtk_widget<'a>(&'a self) -> &'a gtk::Box {
         &self.container
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guest_ticks() {
+        let old = ProcStat::parse("cpu  1000 500 300 9000 10 0 5 0 0 0\n");
+        // 400 of the new user ticks were guest time, and 50 of the new nice ticks were guest_nice
+        let new = ProcStat::parse("cpu  1600 600 400 9500 10 0 5 0 400 50\n");
+        let delta = CpuTicksDelta::new(&new.cpu, &old.cpu);
+        assert_eq!(delta.total(), 1300);
+
+        assert_eq!(delta.get(CpuCategory::User, false), 600);
+        assert_eq!(delta.get(CpuCategory::Nice, false), 100);
+
+        assert_eq!(delta.get(CpuCategory::User, true), 200);
+        assert_eq!(delta.get(CpuCategory::Nice, true), 50);
+        assert_eq!(delta.get(CpuCategory::Guest, true), 450);
+    }
+}
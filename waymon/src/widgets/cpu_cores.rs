@@ -0,0 +1,308 @@
+use crate::collectors::procstat::{CpuStats, ProcStat};
+use crate::config::default_chart_height;
+use crate::history::WidgetHistory;
+use crate::stats::{AllStats, StatsDelta};
+use crate::waymon::Waymon;
+use crate::widgets::timeseries::{Chart, ChartDrawCallback, StackedTimeseriesChart, TimeAxis};
+use crate::widgets::Widget;
+use gtk::cairo;
+use gtk::prelude::*;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// How the per-core data is displayed
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CpuCoresDisplay {
+    /// Show one heatmap row per core
+    #[default]
+    Heatmap,
+    /// Show one small stacked chart per core
+    Charts,
+}
+
+/// The order in which cores are displayed
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CpuCoresSort {
+    /// Sort by CPU ID
+    #[default]
+    Id,
+    /// Sort by current load, busiest first
+    Load,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct CpuCoresWidgetConfig {
+    pub label: String,
+
+    #[serde(default)]
+    pub display: CpuCoresDisplay,
+    #[serde(default)]
+    pub sort: CpuCoresSort,
+    // If set, only show this many of the busiest cores
+    #[serde(default)]
+    pub max_cores: Option<usize>,
+
+    // The total height of the heatmap.  Only used in heatmap mode.
+    #[serde(default = "default_chart_height")]
+    pub height: u32,
+    // The height of each individual core's chart.  Only used in charts mode.
+    #[serde(default = "default_core_height")]
+    pub core_height: u32,
+}
+
+fn default_core_height() -> u32 {
+    20
+}
+
+struct CoreState {
+    // The kernel's ID for this CPU
+    id: u64,
+    chart: StackedTimeseriesChart<f64, 3>,
+    usage_ratio: f64,
+}
+
+fn core_history_key(id: u64) -> String {
+    format!("cpu{}", id)
+}
+
+fn find_cpu(cpus: &[CpuStats], id: u64) -> Option<&CpuStats> {
+    cpus.iter().find(|cpu| cpu.id == id)
+}
+
+pub struct CpuCoresWidget {
+    stats: Rc<RefCell<StatsDelta<ProcStat>>>,
+    container: gtk::Box,
+    da: gtk::DrawingArea,
+    display: CpuCoresDisplay,
+    sort: CpuCoresSort,
+    max_cores: Option<usize>,
+    core_height: u32,
+    time_axis: TimeAxis,
+    // Every core seen so far, sorted by ID
+    cores: Vec<CoreState>,
+    // The indices into `cores` of the cores to display, in the order they should be displayed
+    shown: Vec<usize>,
+    // Saved history for cores that have not been seen yet
+    restored_history: WidgetHistory,
+}
+
+impl CpuCoresWidgetConfig {
+    pub fn create_widget(
+        &self,
+        all_stats: &mut AllStats,
+        time_axis: &TimeAxis,
+    ) -> Rc<RefCell<CpuCoresWidget>> {
+        let widget_rc = Rc::new(RefCell::new(CpuCoresWidget {
+            stats: all_stats.get::<ProcStat>(()),
+            container: gtk::Box::new(gtk::Orientation::Vertical, /*spacing*/ 0),
+            da: gtk::DrawingArea::new(),
+            display: self.display,
+            sort: self.sort,
+            max_cores: self.max_cores,
+            core_height: self.core_height,
+            time_axis: time_axis.clone(),
+            cores: Vec::new(),
+            shown: Vec::new(),
+            restored_history: WidgetHistory::new(),
+        }));
+        {
+            let widget = widget_rc.borrow();
+            Waymon::add_widget_label(&widget.container, &self.label);
+            // In charts mode the height is updated once we know how many cores there are
+            Chart::configure(&widget.da, self.height, widget_rc.clone());
+            widget.container.append(&widget.da);
+        }
+        widget_rc
+    }
+}
+
+impl CpuCoresWidget {
+    /// Compute the nice, user, and system percentages for a single core,
+    /// plus the total fraction of time the core was in use.
+    fn compute_usage(new: &CpuStats, old: &CpuStats) -> ([f64; 3], f64) {
+        let user = new.user.delta_since(old.user);
+        let nice = new.nice.delta_since(old.nice);
+        let system = new.system.delta_since(old.system);
+        let idle = new.idle.delta_since(old.idle);
+        let total_used = user + system + nice;
+        let total = total_used + idle;
+        if total.value() == 0 {
+            // This can happen for offline CPUs
+            return ([0.0, 0.0, 0.0], 0.0);
+        }
+
+        let total_f64 = total.value() as f64;
+        let nice_pct = 100.0 * (nice.value() as f64) / total_f64;
+        let user_pct = 100.0 * (user.value() as f64) / total_f64;
+        let system_pct = 100.0 * (system.value() as f64) / total_f64;
+        ([nice_pct, user_pct, system_pct], total_used / total)
+    }
+
+    fn update_shown(&mut self) {
+        let old_num_shown = self.shown.len();
+
+        let mut by_load: Vec<usize> = (0..self.cores.len()).collect();
+        by_load.sort_by(|&a, &b| {
+            self.cores[b]
+                .usage_ratio
+                .total_cmp(&self.cores[a].usage_ratio)
+        });
+        if let Some(max_cores) = self.max_cores {
+            by_load.truncate(max_cores);
+        }
+        if self.sort == CpuCoresSort::Id {
+            by_load.sort();
+        }
+        self.shown = by_load;
+
+        if self.display == CpuCoresDisplay::Charts && self.shown.len() != old_num_shown {
+            self.da
+                .set_content_height((self.shown.len() as u32 * self.core_height) as i32);
+        }
+    }
+
+    fn draw_charts(&self, cr: &cairo::Context, width: i32) {
+        // Leave a 1 pixel gap between each core's chart
+        let chart_height = self.core_height.saturating_sub(1) as i32;
+        let y_scale = (chart_height as f64) / 100.0;
+        for (row, &core_idx) in self.shown.iter().enumerate() {
+            let core = &self.cores[core_idx];
+            let _ = cr.save();
+            cr.translate(0.0, (row as u32 * self.core_height) as f64);
+            cr.rectangle(0.0, 0.0, width as f64, chart_height as f64);
+            cr.clip();
+            core.chart.draw(cr, width, chart_height, y_scale);
+            Chart::draw_annotation(
+                &self.da,
+                cr,
+                width,
+                chart_height,
+                &format!("{}: {}%", core.id, (core.usage_ratio * 100.0) as u32),
+            );
+            let _ = cr.restore();
+        }
+    }
+
+    fn draw_heatmap(&self, cr: &cairo::Context, width: i32, height: i32) {
+        if self.shown.is_empty() {
+            return;
+        }
+
+        let row_height = (height as f64) / (self.shown.len() as f64);
+        for (row, &core_idx) in self.shown.iter().enumerate() {
+            self.cores[core_idx].chart.draw_heatmap_row(
+                cr,
+                width,
+                (row as f64) * row_height,
+                row_height,
+                100.0,
+            );
+        }
+        // Draw the gridlines on top, since the heatmap covers the whole chart
+        self.cores[self.shown[0]]
+            .chart
+            .draw_gridlines(cr, width, height);
+
+        let busiest = self
+            .shown
+            .iter()
+            .max_by(|&&a, &&b| {
+                self.cores[a]
+                    .usage_ratio
+                    .total_cmp(&self.cores[b].usage_ratio)
+            })
+            .copied()
+            .unwrap_or(0);
+        Chart::draw_annotation(
+            &self.da,
+            cr,
+            width,
+            height,
+            &format!(
+                "max: {}%\ncpu{}",
+                (self.cores[busiest].usage_ratio * 100.0) as u32,
+                self.cores[busiest].id
+            ),
+        );
+    }
+}
+
+impl ChartDrawCallback for CpuCoresWidget {
+    fn draw(&self, cr: &cairo::Context, width: i32, height: i32) {
+        match self.display {
+            CpuCoresDisplay::Charts => self.draw_charts(cr, width),
+            CpuCoresDisplay::Heatmap => self.draw_heatmap(cr, width, height),
+        }
+    }
+}
+
+impl Widget for CpuCoresWidget {
+    fn update(&mut self) {
+        {
+            let s = self.stats.borrow();
+            let (new, old) = s.get_new_and_old();
+
+            // The set of CPUs can change over time if CPUs are hotplugged, and offline CPUs are
+            // not listed at all, so cores are matched up by ID rather than position.
+            // We never remove cores once we have seen them, but they will simply show 0 usage if
+            // they disappear.
+            for cpu in &new.cpus {
+                if let Err(pos) = self.cores.binary_search_by_key(&cpu.id, |core| core.id) {
+                    let mut chart = StackedTimeseriesChart::new(&self.time_axis);
+                    chart.restore_from(&mut self.restored_history, &core_history_key(cpu.id));
+                    self.cores.insert(
+                        pos,
+                        CoreState {
+                            id: cpu.id,
+                            chart,
+                            usage_ratio: 0.0,
+                        },
+                    );
+                }
+            }
+
+            for core in &mut self.cores {
+                let (values, usage_ratio) =
+                    match (find_cpu(&new.cpus, core.id), find_cpu(&old.cpus, core.id)) {
+                        (Some(new_cpu), Some(old_cpu)) => Self::compute_usage(new_cpu, old_cpu),
+                        _ => ([0.0, 0.0, 0.0], 0.0),
+                    };
+                core.usage_ratio = usage_ratio;
+                core.chart.add_values(&values);
+            }
+        }
+        self.update_shown();
+
+        // Mark that the drawing area needs to be redrawn
+        self.da.queue_draw();
+    }
+
+    fn set_time_axis(&mut self, time_axis: &TimeAxis) {
+        self.time_axis = time_axis.clone();
+        for core in &mut self.cores {
+            core.chart.set_time_axis(time_axis);
+        }
+    }
+
+    fn save_history(&self) -> WidgetHistory {
+        self.cores
+            .iter()
+            .map(|core| (core_history_key(core.id), core.chart.save_history()))
+            .collect()
+    }
+
+    fn restore_history(&mut self, history: WidgetHistory) {
+        self.restored_history = history;
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stats.borrow().is_stale()
+    }
+
+    fn gtk_widget(&self) -> &gtk::Box {
+        &self.container
+    }
+}
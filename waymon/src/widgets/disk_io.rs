@@ -1,37 +1,125 @@
-use crate::collectors::diskstats::{ProcDiskStats, BYTES_PER_SECTOR};
-use crate::config::default_chart_height;
-use crate::stats::{AllStats, StatsDelta};
+use crate::collectors::diskstats::{
+    is_device_mapper, is_partition, DiskStats, ProcDiskStats, BYTES_PER_SECTOR,
+};
+use crate::config::{default_chart_height, DevicePattern};
+use crate::history::WidgetHistory;
+use crate::stats::{counter_delta, counter_delta_u32, AllStats, StatsDelta};
 use crate::util::humanify_f64;
 use crate::waymon::Waymon;
-use crate::widgets::timeseries::{Chart, ChartDrawCallback, StackedTimeseriesChart};
+use crate::widgets::timeseries::{
+    Chart, ChartDrawCallback, Color, StackedTimeseriesChart, TimeAxis,
+};
 use crate::widgets::Widget;
 use gtk::cairo;
 use gtk::prelude::*;
 use serde::Deserialize;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 use std::time::Duration;
 use tracing::warn;
 
+/// What the disk_io widget charts
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiskIoDisplay {
+    /// Chart read and write throughput in bytes per second
+    #[default]
+    Throughput,
+    /// Chart average read and write latency, IOPS, and average queue depth
+    Latency,
+}
+
+// The charts used in latency mode.  These are drawn as three rows within the drawing area.
+struct LatencyCharts {
+    // Average milliseconds per read and per write
+    read_latency: StackedTimeseriesChart<f64, 1>,
+    write_latency: StackedTimeseriesChart<f64, 1>,
+    // Reads and writes completed per second
+    iops: StackedTimeseriesChart<f64, 2>,
+    // Average number of I/O operations in flight
+    queue_depth: StackedTimeseriesChart<f64, 1>,
+}
+
+impl LatencyCharts {
+    fn new(time_axis: &TimeAxis) -> Self {
+        let mut write_latency = StackedTimeseriesChart::new(time_axis);
+        write_latency.set_color(0, Color::new(0.85, 0.0, 0.0));
+        Self {
+            read_latency: StackedTimeseriesChart::new(time_axis),
+            write_latency,
+            iops: StackedTimeseriesChart::new(time_axis),
+            queue_depth: StackedTimeseriesChart::new(time_axis),
+        }
+    }
+
+    fn set_time_axis(&mut self, time_axis: &TimeAxis) {
+        self.read_latency.set_time_axis(time_axis);
+        self.write_latency.set_time_axis(time_axis);
+        self.iops.set_time_axis(time_axis);
+        self.queue_depth.set_time_axis(time_axis);
+    }
+
+    fn save_history(&self, history: &mut WidgetHistory) {
+        history.insert("read_latency".to_string(), self.read_latency.save_history());
+        history.insert(
+            "write_latency".to_string(),
+            self.write_latency.save_history(),
+        );
+        history.insert("iops".to_string(), self.iops.save_history());
+        history.insert("queue_depth".to_string(), self.queue_depth.save_history());
+    }
+
+    fn restore_history(&mut self, history: &mut WidgetHistory) {
+        self.read_latency.restore_from(history, "read_latency");
+        self.write_latency.restore_from(history, "write_latency");
+        self.iops.restore_from(history, "iops");
+        self.queue_depth.restore_from(history, "queue_depth");
+    }
+}
+
 // We suppress the non_snake_case warning here so that we can more clearly disambiguate Bps (bytes
 // per second) from bps (bits per second).
 #[allow(non_snake_case)]
 pub struct DiskIoWidget {
-    disk: String,
+    config: DiskIoWidgetConfig,
+    // Whether each disk name we have seen is selected by the config.
+    // Checking for partitions requires reading sysfs, so we cache the results.
+    selected: HashMap<String, bool>,
     stats: Rc<RefCell<StatsDelta<ProcDiskStats>>>,
     container: gtk::Box,
     da: gtk::DrawingArea,
+    display: DiskIoDisplay,
     chart: StackedTimeseriesChart<f64, 2>,
+    latency_charts: LatencyCharts,
     disk_present: bool,
     busy_fraction: f64,
     read_Bps: f64,
     write_Bps: f64,
+    read_latency_ms: f64,
+    write_latency_ms: f64,
+    read_iops: f64,
+    write_iops: f64,
+    queue_depth: f64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 pub struct DiskIoWidgetConfig {
     pub label: String,
-    pub disk: String,
+    // The disks to show.  If this matches multiple disks their I/O is summed.
+    pub disk: DevicePattern,
+    // Disks to ignore, even if they match the disk pattern
+    #[serde(default)]
+    pub exclude: Option<DevicePattern>,
+    // Ignore partitions and device-mapper devices, so that I/O is not counted more than once
+    // when the disk pattern matches both a disk and its partitions.
+    #[serde(default)]
+    pub exclude_partitions: bool,
+    #[serde(default)]
+    pub exclude_dm: bool,
+
+    #[serde(default)]
+    pub display: DiskIoDisplay,
 
     #[serde(default = "default_chart_height")]
     pub height: u32,
@@ -41,20 +129,28 @@ impl DiskIoWidgetConfig {
     pub fn create_widget(
         &self,
         all_stats: &mut AllStats,
-        history_length: usize,
+        time_axis: &TimeAxis,
     ) -> Rc<RefCell<DiskIoWidget>> {
         let widget_rc = Rc::new(RefCell::new(DiskIoWidget {
-            disk: self.disk.clone(),
-            stats: all_stats.get_disk_stats(),
+            config: self.clone(),
+            selected: HashMap::new(),
+            stats: all_stats.get::<ProcDiskStats>(()),
             container: gtk::Box::new(gtk::Orientation::Vertical, /*spacing*/ 0),
             da: gtk::DrawingArea::new(),
-            chart: StackedTimeseriesChart::new(history_length),
+            display: self.display,
+            chart: StackedTimeseriesChart::new(time_axis),
+            latency_charts: LatencyCharts::new(time_axis),
             // Initialize disk_present to true so that we will log a warning once
             // if it is actually not present.
             disk_present: true,
             busy_fraction: 0.0,
             read_Bps: 0.0,
             write_Bps: 0.0,
+            read_latency_ms: 0.0,
+            write_latency_ms: 0.0,
+            read_iops: 0.0,
+            write_iops: 0.0,
+            queue_depth: 0.0,
         }));
         {
             let widget = widget_rc.borrow();
@@ -66,14 +162,17 @@ impl DiskIoWidgetConfig {
     }
 }
 
-impl ChartDrawCallback for DiskIoWidget {
-    fn draw(&self, cr: &cairo::Context, width: i32, height: i32) {
-        let max_value = self.chart.max_value();
-        let y_scale = if max_value <= 0.0 {
-            1.0
-        } else {
-            ((height - 2) as f64) / max_value
-        };
+fn y_scale_for(max_value: f64, height: i32) -> f64 {
+    if max_value <= 0.0 {
+        1.0
+    } else {
+        ((height - 2) as f64) / max_value
+    }
+}
+
+impl DiskIoWidget {
+    fn draw_throughput(&self, cr: &cairo::Context, width: i32, height: i32) {
+        let y_scale = y_scale_for(self.chart.max_value(), height);
         self.chart.draw(cr, width, height, y_scale);
 
         if self.disk_present {
@@ -88,38 +187,217 @@ impl ChartDrawCallback for DiskIoWidget {
             Chart::draw_annotation(&self.da, cr, width, height, "Not Present");
         }
     }
+
+    fn draw_latency(&self, cr: &cairo::Context, width: i32, height: i32) {
+        if !self.disk_present {
+            Chart::draw_annotation(&self.da, cr, width, height, "Not Present");
+            return;
+        }
+
+        // Split the drawing area into three rows, leaving a 1 pixel gap between them
+        let row_height = height / 3;
+        let chart_height = row_height - 1;
+        let charts = &self.latency_charts;
+        for row in 0..3 {
+            let _ = cr.save();
+            cr.translate(0.0, (row * row_height) as f64);
+            cr.rectangle(0.0, 0.0, width as f64, chart_height as f64);
+            cr.clip();
+            let annotation = match row {
+                0 => {
+                    // Draw read and write latency as separate lines on the same scale
+                    let max_value = f64::max(
+                        charts.read_latency.max_value(),
+                        charts.write_latency.max_value(),
+                    );
+                    let y_scale = y_scale_for(max_value, chart_height);
+                    charts.read_latency.draw_gridlines(cr, width, chart_height);
+                    charts
+                        .read_latency
+                        .draw_line(cr, width, chart_height, y_scale);
+                    charts
+                        .write_latency
+                        .draw_line(cr, width, chart_height, y_scale);
+                    format!(
+                        "{:.1}ms R\n{:.1}ms W",
+                        self.read_latency_ms, self.write_latency_ms
+                    )
+                }
+                1 => {
+                    let y_scale = y_scale_for(charts.iops.max_value(), chart_height);
+                    charts.iops.draw(cr, width, chart_height, y_scale);
+                    format!("{:.0} IOPS", self.read_iops + self.write_iops)
+                }
+                _ => {
+                    let y_scale = y_scale_for(charts.queue_depth.max_value(), chart_height);
+                    charts.queue_depth.draw(cr, width, chart_height, y_scale);
+                    format!("queue {:.1}", self.queue_depth)
+                }
+            };
+            Chart::draw_annotation(&self.da, cr, width, chart_height, &annotation);
+            let _ = cr.restore();
+        }
+    }
+
+    fn add_values(&mut self) {
+        match self.display {
+            DiskIoDisplay::Throughput => {
+                self.chart.add_values(&[self.read_Bps, self.write_Bps]);
+            }
+            DiskIoDisplay::Latency => {
+                let charts = &mut self.latency_charts;
+                charts.read_latency.add_values(&[self.read_latency_ms]);
+                charts.write_latency.add_values(&[self.write_latency_ms]);
+                charts.iops.add_values(&[self.read_iops, self.write_iops]);
+                charts.queue_depth.add_values(&[self.queue_depth]);
+            }
+        }
+    }
+}
+
+impl ChartDrawCallback for DiskIoWidget {
+    fn draw(&self, cr: &cairo::Context, width: i32, height: i32) {
+        match self.display {
+            DiskIoDisplay::Throughput => self.draw_throughput(cr, width, height),
+            DiskIoDisplay::Latency => self.draw_latency(cr, width, height),
+        }
+    }
+}
+
+/// The change in stats between two samples, summed across all selected disks
+#[derive(Default)]
+struct DiskDelta {
+    reads: u64,
+    writes: u64,
+    sectors_read: u64,
+    sectors_written: u64,
+    ms_reading: u64,
+    ms_writing: u64,
+    weighted_ms: u64,
+    // The busy time of the busiest disk.  Summing this across disks could exceed 100%.
+    max_ms_busy: u32,
+}
+
+impl DiskDelta {
+    fn add(&mut self, new: &DiskStats, old: &DiskStats) {
+        self.reads += counter_delta(new.num_reads, old.num_reads);
+        self.writes += counter_delta(new.num_writes, old.num_writes);
+        self.sectors_read += counter_delta(new.num_sectors_read, old.num_sectors_read);
+        self.sectors_written += counter_delta(new.num_sectors_written, old.num_sectors_written);
+
+        // The millisecond fields are only 32 bits wide, and wrap fairly often on busy disks
+        let ms_delta = |new: u32, old: u32| u64::from(counter_delta_u32(new, old));
+        self.ms_reading += ms_delta(new.ms_reading, old.ms_reading);
+        self.ms_writing += ms_delta(new.ms_writing, old.ms_writing);
+        self.weighted_ms += ms_delta(new.weighted_ms_doing_io, old.weighted_ms_doing_io);
+        let ms_busy = counter_delta_u32(new.ms_doing_io, old.ms_doing_io);
+        self.max_ms_busy = std::cmp::max(self.max_ms_busy, ms_busy);
+    }
+}
+
+impl DiskIoWidget {
+    fn is_selected(&mut self, name: &str) -> bool {
+        if let Some(&selected) = self.selected.get(name) {
+            return selected;
+        }
+
+        let config = &self.config;
+        let excluded = match &config.exclude {
+            Some(exclude) => exclude.is_match(name),
+            None => false,
+        };
+        let selected = config.disk.is_match(name)
+            && !excluded
+            && (!config.exclude_dm || !is_device_mapper(name))
+            && (!config.exclude_partitions || !is_partition(name));
+        self.selected.insert(name.to_string(), selected);
+        selected
+    }
+}
+
+/// Compute the average milliseconds per operation
+fn per_op(ms: u64, ops: u64) -> f64 {
+    if ops == 0 {
+        return 0.0;
+    }
+    (ms as f64) / (ops as f64)
 }
 
 impl Widget for DiskIoWidget {
     fn update(&mut self) {
-        let s = self.stats.borrow();
+        let stats_rc = self.stats.clone();
+        let s = stats_rc.borrow();
         let (new_stats, old_stats) = s.get_new_and_old();
-        if let (Some(new), Some(old)) = (
-            new_stats.disks.get(&self.disk),
-            old_stats.disks.get(&self.disk),
-        ) {
+
+        // Sum the I/O across all selected disks that are present in both samples
+        let mut any_present = false;
+        let mut delta = DiskDelta::default();
+        for (name, new) in &new_stats.disks {
+            if !self.is_selected(name) {
+                continue;
+            }
+            if let Some(old) = old_stats.disks.get(name) {
+                any_present = true;
+                delta.add(new, old);
+            }
+        }
+
+        if any_present {
             self.disk_present = true;
-            let ms_busy = Duration::from_millis((new.ms_doing_io - old.ms_doing_io) as u64);
+            let ms_busy = Duration::from_millis(delta.max_ms_busy as u64);
             let delta_secs = s.time_delta().as_secs_f64();
             self.busy_fraction = ms_busy.as_secs_f64() / delta_secs;
 
-            let sectors_read = new.num_sectors_read - old.num_sectors_read;
-            let sectors_written = new.num_sectors_written - old.num_sectors_written;
-            let read_bytes = sectors_read * BYTES_PER_SECTOR;
-            let write_bytes = sectors_written * BYTES_PER_SECTOR;
+            let read_bytes = delta.sectors_read * BYTES_PER_SECTOR;
+            let write_bytes = delta.sectors_written * BYTES_PER_SECTOR;
             self.read_Bps = (read_bytes as f64) / delta_secs;
             self.write_Bps = (write_bytes as f64) / delta_secs;
-            self.chart.add_values(&[self.read_Bps, self.write_Bps])
+
+            self.read_latency_ms = per_op(delta.ms_reading, delta.reads);
+            self.write_latency_ms = per_op(delta.ms_writing, delta.writes);
+            self.read_iops = (delta.reads as f64) / delta_secs;
+            self.write_iops = (delta.writes as f64) / delta_secs;
+            // The weighted time is incremented by the number of I/Os in progress for each
+            // millisecond, so dividing by the elapsed time gives the average queue depth.
+            self.queue_depth = (delta.weighted_ms as f64) / (delta_secs * 1000.0);
+            self.add_values();
         } else if self.disk_present {
-            warn!("disk {} not present", &self.disk);
+            warn!("disk {} not present", &self.config.disk);
             self.disk_present = false;
-            self.chart.add_values(&[0.0, 0.0]);
+            self.read_Bps = 0.0;
+            self.write_Bps = 0.0;
+            self.read_latency_ms = 0.0;
+            self.write_latency_ms = 0.0;
+            self.read_iops = 0.0;
+            self.write_iops = 0.0;
+            self.queue_depth = 0.0;
+            self.add_values();
         }
 
         // Mark that the drawing area needs to be redrawn
         self.da.queue_draw();
     }
 
+    fn set_time_axis(&mut self, time_axis: &TimeAxis) {
+        self.chart.set_time_axis(time_axis);
+        self.latency_charts.set_time_axis(time_axis);
+    }
+
+    fn save_history(&self) -> WidgetHistory {
+        let mut history = WidgetHistory::from([("chart".to_string(), self.chart.save_history())]);
+        self.latency_charts.save_history(&mut history);
+        history
+    }
+
+    fn restore_history(&mut self, mut history: WidgetHistory) {
+        self.chart.restore_from(&mut history, "chart");
+        self.latency_charts.restore_history(&mut history);
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stats.borrow().is_stale()
+    }
+
     fn gtk_widget<'a>(&'a self) -> &'a gtk::Box {
         &self.container
     }
@@ -0,0 +1,259 @@
+use crate::collectors::fs_usage::{FsUsage, FsUsageStats};
+use crate::history::WidgetHistory;
+use crate::stats::{AllStats, StatsDelta};
+use crate::util::humanify_f64;
+use crate::waymon::Waymon;
+use crate::widgets::timeseries::{
+    Chart, ChartDrawCallback, Color, StackedTimeseriesChart, TimeAxis,
+};
+use crate::widgets::Widget;
+use gtk::cairo;
+use gtk::prelude::*;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct FsUsageWidgetConfig {
+    pub label: String,
+
+    // The mount points to show.  If empty, all filesystems backed by a block device are shown.
+    #[serde(default)]
+    pub mount_points: Vec<String>,
+
+    // The height of each filesystem's chart
+    #[serde(default = "default_fs_height")]
+    pub fs_height: u32,
+}
+
+fn default_fs_height() -> u32 {
+    50
+}
+
+struct FsState {
+    mount_point: String,
+    // The used and reserved space, as a percentage of the total size
+    chart: StackedTimeseriesChart<f64, 2>,
+    // The percentage of inodes in use
+    inode_chart: StackedTimeseriesChart<f64, 1>,
+    // The latest usage data, or None if the filesystem is not currently mounted
+    usage: Option<FsUsage>,
+}
+
+impl FsState {
+    fn new(mount_point: &str, time_axis: &TimeAxis, restored_history: &mut WidgetHistory) -> Self {
+        let mut chart = StackedTimeseriesChart::new(time_axis);
+        chart.set_color(0, Color::new(0.0, 0.0, 1.0));
+        chart.set_color(1, Color::new(0.9, 0.6, 0.0));
+        chart.restore_from(restored_history, mount_point);
+        let mut inode_chart = StackedTimeseriesChart::new(time_axis);
+        inode_chart.set_color(0, Color::new(0.0, 0.5, 0.0));
+        inode_chart.restore_from(restored_history, &Self::inode_history_key(mount_point));
+        Self {
+            mount_point: mount_point.to_string(),
+            chart,
+            inode_chart,
+            usage: None,
+        }
+    }
+
+    fn inode_history_key(mount_point: &str) -> String {
+        format!("{} inodes", mount_point)
+    }
+
+    fn add_sample(&mut self) {
+        let (used_pct, reserved_pct, inode_pct) = match &self.usage {
+            Some(usage) => (
+                percent(usage.used_bytes(), usage.total_bytes),
+                percent(usage.reserved_bytes(), usage.total_bytes),
+                percent(usage.used_inodes(), usage.total_inodes),
+            ),
+            None => (0.0, 0.0, 0.0),
+        };
+        self.chart.add_values(&[used_pct, reserved_pct]);
+        self.inode_chart.add_values(&[inode_pct]);
+    }
+
+    fn annotation(&self) -> String {
+        let Some(usage) = &self.usage else {
+            return format!("{}\nNot Mounted", self.mount_point);
+        };
+
+        let mut text = format!(
+            "{} {:.0}%\n{}/{}",
+            self.mount_point,
+            percent(usage.used_bytes(), usage.total_bytes),
+            humanify_f64(usage.used_bytes() as f64, 2),
+            humanify_f64(usage.total_bytes as f64, 2),
+        );
+        // Some filesystems (e.g., btrfs) do not have a fixed number of inodes, and report 0
+        if usage.total_inodes > 0 {
+            text.push_str(&format!(
+                "\ninodes {:.0}%",
+                percent(usage.used_inodes(), usage.total_inodes)
+            ));
+        }
+        text
+    }
+}
+
+fn percent(value: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    100.0 * (value as f64) / (total as f64)
+}
+
+pub struct FsUsageWidget {
+    stats: Rc<RefCell<StatsDelta<FsUsageStats>>>,
+    container: gtk::Box,
+    da: gtk::DrawingArea,
+    // If true, the filesystems to show are discovered from the list of mounts
+    discover: bool,
+    fs_height: u32,
+    time_axis: TimeAxis,
+    filesystems: Vec<FsState>,
+    // Saved history for filesystems that have not been seen yet
+    restored_history: WidgetHistory,
+}
+
+impl FsUsageWidgetConfig {
+    pub fn create_widget(
+        &self,
+        all_stats: &mut AllStats,
+        time_axis: &TimeAxis,
+    ) -> Rc<RefCell<FsUsageWidget>> {
+        let filesystems: Vec<FsState> = self
+            .mount_points
+            .iter()
+            .map(|mount_point| FsState::new(mount_point, time_axis, &mut WidgetHistory::new()))
+            .collect();
+        let widget_rc = Rc::new(RefCell::new(FsUsageWidget {
+            stats: all_stats.get::<FsUsageStats>(self.mount_points.clone()),
+            container: gtk::Box::new(gtk::Orientation::Vertical, /*spacing*/ 0),
+            da: gtk::DrawingArea::new(),
+            discover: self.mount_points.is_empty(),
+            fs_height: self.fs_height,
+            time_axis: time_axis.clone(),
+            filesystems,
+            restored_history: WidgetHistory::new(),
+        }));
+        {
+            let widget = widget_rc.borrow();
+            Waymon::add_widget_label(&widget.container, &self.label);
+            // The height is updated in update() if the number of filesystems changes
+            let height = std::cmp::max(widget.filesystems.len(), 1) as u32 * self.fs_height;
+            Chart::configure(&widget.da, height, widget_rc.clone());
+            widget.container.append(&widget.da);
+        }
+        widget_rc
+    }
+}
+
+impl FsUsageWidget {
+    /// Update the list of filesystems to show, based on the currently mounted filesystems.
+    ///
+    /// In discover mode the collector only reports the filesystems that should be shown.
+    fn update_discovered(&mut self, stats: &FsUsageStats) {
+        let mut old_filesystems = std::mem::take(&mut self.filesystems);
+        for usage in &stats.filesystems {
+            let state = match old_filesystems
+                .iter()
+                .position(|fs| fs.mount_point == usage.mount_point)
+            {
+                Some(idx) => old_filesystems.swap_remove(idx),
+                None => FsState::new(
+                    &usage.mount_point,
+                    &self.time_axis,
+                    &mut self.restored_history,
+                ),
+            };
+            self.filesystems.push(state);
+        }
+    }
+}
+
+impl ChartDrawCallback for FsUsageWidget {
+    fn draw(&self, cr: &cairo::Context, width: i32, _height: i32) {
+        // Leave a 1 pixel gap between each filesystem's chart
+        let chart_height = self.fs_height.saturating_sub(1) as i32;
+        let y_scale = ((chart_height - 1) as f64) / 100.0;
+        for (row, fs) in self.filesystems.iter().enumerate() {
+            let _ = cr.save();
+            cr.translate(0.0, (row as u32 * self.fs_height) as f64);
+            cr.rectangle(0.0, 0.0, width as f64, chart_height as f64);
+            cr.clip();
+            fs.chart.draw(cr, width, chart_height, y_scale);
+            if fs.inode_chart.max_value() > 0.0 {
+                fs.inode_chart.draw_line(cr, width, chart_height, y_scale);
+            }
+            Chart::draw_annotation(&self.da, cr, width, chart_height, &fs.annotation());
+            let _ = cr.restore();
+        }
+    }
+}
+
+impl Widget for FsUsageWidget {
+    fn update(&mut self) {
+        let old_num_filesystems = self.filesystems.len();
+        {
+            let stats_rc = self.stats.clone();
+            let s = stats_rc.borrow();
+            let new_stats = s.get_new();
+            if self.discover {
+                self.update_discovered(new_stats);
+            }
+            for fs in &mut self.filesystems {
+                fs.usage = new_stats.get(&fs.mount_point).cloned();
+                fs.add_sample();
+            }
+        }
+
+        if self.filesystems.len() != old_num_filesystems {
+            let num_rows = std::cmp::max(self.filesystems.len(), 1) as u32;
+            self.da
+                .set_content_height((num_rows * self.fs_height) as i32);
+        }
+
+        // Mark that the drawing area needs to be redrawn
+        self.da.queue_draw();
+    }
+
+    fn set_time_axis(&mut self, time_axis: &TimeAxis) {
+        self.time_axis = time_axis.clone();
+        for fs in &mut self.filesystems {
+            fs.chart.set_time_axis(time_axis);
+            fs.inode_chart.set_time_axis(time_axis);
+        }
+    }
+
+    fn save_history(&self) -> WidgetHistory {
+        let mut history = WidgetHistory::new();
+        for fs in &self.filesystems {
+            history.insert(fs.mount_point.clone(), fs.chart.save_history());
+            history.insert(
+                FsState::inode_history_key(&fs.mount_point),
+                fs.inode_chart.save_history(),
+            );
+        }
+        history
+    }
+
+    fn restore_history(&mut self, mut history: WidgetHistory) {
+        // Filesystems that are discovered later will pick up their history when they are added
+        for fs in &mut self.filesystems {
+            fs.chart.restore_from(&mut history, &fs.mount_point);
+            fs.inode_chart
+                .restore_from(&mut history, &FsState::inode_history_key(&fs.mount_point));
+        }
+        self.restored_history = history;
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stats.borrow().is_stale()
+    }
+
+    fn gtk_widget(&self) -> &gtk::Box {
+        &self.container
+    }
+}
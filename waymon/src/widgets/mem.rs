@@ -1,20 +1,178 @@
 use crate::collectors::meminfo::MemoryStats;
 use crate::config::MemWidgetConfig;
+use crate::history::WidgetHistory;
 use crate::stats::{AllStats, StatsDelta};
 use crate::util::humanify_f64;
 use crate::waymon::Waymon;
-use crate::widgets::timeseries::{Chart, ChartDrawCallback, StackedTimeseriesChart};
+use crate::widgets::timeseries::{
+    Chart, ChartDrawCallback, Color, StackedTimeseriesChart, TimeAxis,
+};
 use crate::widgets::Widget;
 use gtk::cairo;
 use gtk::prelude::*;
+use serde::Deserialize;
 use std::cell::RefCell;
 use std::rc::Rc;
+use tracing::warn;
+
+const NUM_MEM_CATEGORIES: usize = 13;
+
+/// The categories of memory usage from /proc/meminfo that can be shown in the chart
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MemCategory {
+    /// Memory that the kernel could not reclaim if needed: MemTotal - MemAvailable
+    Used,
+    /// Memory that is in use, but could be reclaimed if needed: MemAvailable - MemFree
+    Reclaimable,
+    Free,
+    Anon,
+    /// The page cache and buffers, not including shmem
+    Cache,
+    Shmem,
+    Slab,
+    KernelStack,
+    PageTables,
+    Dirty,
+    Writeback,
+    Zswap,
+    Hugetlb,
+}
+
+impl MemCategory {
+    fn default_color(&self) -> Color {
+        match self {
+            MemCategory::Used => Color::new(0.0, 0.0, 1.0),
+            MemCategory::Reclaimable => Color::new(0.0, 0.0, 0.5),
+            MemCategory::Free => Color::new(0.75, 0.75, 0.75),
+            MemCategory::Anon => Color::new(0.0, 0.0, 0.8),
+            MemCategory::Cache => Color::new(0.0, 0.5, 0.0),
+            MemCategory::Shmem => Color::new(0.5, 0.0, 0.5),
+            MemCategory::Slab => Color::new(0.9, 0.6, 0.0),
+            MemCategory::KernelStack => Color::new(0.6, 0.3, 0.0),
+            MemCategory::PageTables => Color::new(0.75, 0.35, 0.75),
+            MemCategory::Dirty => Color::new(0.85, 0.0, 0.0),
+            MemCategory::Writeback => Color::new(1.0, 0.4, 0.4),
+            MemCategory::Zswap => Color::new(0.0, 0.6, 0.6),
+            MemCategory::Hugetlb => Color::new(0.4, 0.4, 0.4),
+        }
+    }
+
+    fn annotation_name(&self) -> &'static str {
+        match self {
+            MemCategory::Used => "used",
+            MemCategory::Reclaimable => "reclaim",
+            MemCategory::Free => "free",
+            MemCategory::Anon => "anon",
+            MemCategory::Cache => "cache",
+            MemCategory::Shmem => "shmem",
+            MemCategory::Slab => "slab",
+            MemCategory::KernelStack => "kstack",
+            MemCategory::PageTables => "ptables",
+            MemCategory::Dirty => "dirty",
+            MemCategory::Writeback => "wback",
+            MemCategory::Zswap => "zswap",
+            MemCategory::Hugetlb => "hugetlb",
+        }
+    }
+
+    // The used, reclaimable, and free amounts are already summarized by the usage annotation, so
+    // by default the other categories are the only ones listed individually.
+    fn annotate_by_default(&self) -> bool {
+        !matches!(
+            self,
+            MemCategory::Used | MemCategory::Reclaimable | MemCategory::Free
+        )
+    }
+
+    /// The amount of memory in this category, in kilobytes
+    fn get_kb(&self, stats: &MemoryStats) -> u64 {
+        match self {
+            MemCategory::Used => stats.mem_total.saturating_sub(stats.mem_available),
+            MemCategory::Reclaimable => stats.mem_available.saturating_sub(stats.mem_free),
+            MemCategory::Free => stats.mem_free,
+            MemCategory::Anon => stats.anon_pages,
+            // The Cached value includes shmem, since shmem pages live in the page cache
+            MemCategory::Cache => (stats.cached + stats.buffers).saturating_sub(stats.shmem),
+            MemCategory::Shmem => stats.shmem,
+            MemCategory::Slab => stats.slab,
+            MemCategory::KernelStack => stats.kernel_stack,
+            MemCategory::PageTables => stats.page_tables,
+            MemCategory::Dirty => stats.dirty,
+            MemCategory::Writeback => stats.writeback,
+            MemCategory::Zswap => stats.zswap,
+            MemCategory::Hugetlb => stats.hugetlb,
+        }
+    }
+}
+
+/// Configuration for one of the series shown in the memory chart.
+///
+/// In the config file this can be specified either as just the category name, or as a table with
+/// "category", "color", and "annotate" fields.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(from = "TomlMemSeriesConfig")]
+pub struct MemSeriesConfig {
+    pub category: MemCategory,
+    pub color: Option<Color>,
+    pub annotate: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TomlMemSeriesConfig {
+    Category(MemCategory),
+    Full {
+        category: MemCategory,
+        #[serde(default)]
+        color: Option<Color>,
+        #[serde(default)]
+        annotate: Option<bool>,
+    },
+}
+
+impl From<TomlMemSeriesConfig> for MemSeriesConfig {
+    fn from(c: TomlMemSeriesConfig) -> Self {
+        match c {
+            TomlMemSeriesConfig::Category(category) => MemSeriesConfig {
+                category,
+                color: None,
+                annotate: None,
+            },
+            TomlMemSeriesConfig::Full {
+                category,
+                color,
+                annotate,
+            } => MemSeriesConfig {
+                category,
+                color,
+                annotate,
+            },
+        }
+    }
+}
+
+pub fn default_mem_categories() -> Vec<MemSeriesConfig> {
+    [MemCategory::Used, MemCategory::Reclaimable]
+        .into_iter()
+        .map(|category| MemSeriesConfig {
+            category,
+            color: None,
+            annotate: None,
+        })
+        .collect()
+}
 
 pub struct MemWidget {
     stats: Rc<RefCell<StatsDelta<MemoryStats>>>,
     container: gtk::Box,
     da: gtk::DrawingArea,
-    chart: StackedTimeseriesChart<u64, 2>,
+    // The categories being charted, in the order they are stacked in the chart,
+    // along with whether each one should be shown in the annotation.
+    categories: Vec<(MemCategory, bool)>,
+    // Any unused series in the chart are always set to 0.
+    chart: StackedTimeseriesChart<f64, NUM_MEM_CATEGORIES>,
+    category_kb: [f64; NUM_MEM_CATEGORIES],
     mem_available_kb: u64,
     mem_total_kb: u64,
 }
@@ -23,13 +181,35 @@ impl MemWidget {
     pub fn new(
         config: &MemWidgetConfig,
         all_stats: &mut AllStats,
-        history_length: usize,
+        time_axis: &TimeAxis,
     ) -> Rc<RefCell<MemWidget>> {
+        let mut categories: Vec<(MemCategory, bool)> = Vec::new();
+        let mut chart = StackedTimeseriesChart::new(time_axis);
+        for series in &config.categories {
+            if categories.iter().any(|(c, _)| *c == series.category) {
+                warn!(
+                    "memory category {:?} listed more than once in widget {:?}",
+                    series.category, config.label
+                );
+                continue;
+            }
+            let color = series
+                .color
+                .unwrap_or_else(|| series.category.default_color());
+            chart.set_color(categories.len(), color);
+            let annotate = series
+                .annotate
+                .unwrap_or_else(|| series.category.annotate_by_default());
+            categories.push((series.category, annotate));
+        }
+
         let widget_rc = Rc::new(RefCell::new(MemWidget {
-            stats: all_stats.get_mem_stats(),
+            stats: all_stats.get::<MemoryStats>(()),
             container: gtk::Box::new(gtk::Orientation::Vertical, /*spacing*/ 0),
             da: gtk::DrawingArea::new(),
-            chart: StackedTimeseriesChart::new(history_length),
+            categories,
+            chart,
+            category_kb: [0.0; NUM_MEM_CATEGORIES],
             mem_available_kb: 0,
             mem_total_kb: 0,
         }));
@@ -45,18 +225,29 @@ impl MemWidget {
 
 impl ChartDrawCallback for MemWidget {
     fn draw(&self, cr: &cairo::Context, width: i32, height: i32) {
-        let max_value = std::cmp::max(self.mem_total_kb, 1024);
-        let y_scale = (height as f64) / (max_value as f64);
+        // Categories can overlap (e.g., anon and used), so the stacked total may be larger than
+        // the total memory.
+        let max_value = f64::max(self.mem_total_kb as f64, self.chart.max_value()).max(1024.0);
+        let y_scale = (height as f64) / max_value;
         self.chart.draw(cr, width, height, y_scale);
 
-        let used_kb = self.mem_total_kb - self.mem_available_kb;
+        let used_kb = self.mem_total_kb.saturating_sub(self.mem_available_kb);
         let pct_used = 100.0 * ((used_kb as f64) / (self.mem_total_kb as f64));
-        let annotation = format!(
+        let mut annotation = format!(
             "{:.0}% used\n {}/{}",
             pct_used,
             humanify_f64((used_kb * 1024) as f64, 2),
             humanify_f64((self.mem_total_kb * 1024) as f64, 2),
         );
+        for (idx, (category, annotate)) in self.categories.iter().enumerate() {
+            if *annotate {
+                annotation.push_str(&format!(
+                    "\n{} {}",
+                    category.annotation_name(),
+                    humanify_f64(self.category_kb[idx] * 1024.0, 2)
+                ));
+            }
+        }
         Chart::draw_annotation(&self.da, cr, width, height, &annotation);
     }
 }
@@ -71,21 +262,38 @@ impl Widget for MemWidget {
         // memory that is used being for filesystem caches, temporary buffers, etc which the kernel
         // could reclaim on-demand if needed.  The mem_available stat reports how much the kernel
         // thinks it could reclaim immediately if needed, and includes the amount reported in
-        // mem_free.  Various other fields in the MemoryStats break down some of the other ways
-        // this memory is being used, but we don't bother reporting any of that here.
+        // mem_free.  By default we chart "unavailable" memory at the bottom of the chart, then
+        // "used but available" memory, and leave "free" uncolored at the top of the chart.  The
+        // categories setting can be used to break this down further.
         self.mem_total_kb = new_stats.mem_total;
         self.mem_available_kb = new_stats.mem_available;
 
-        // We graph "unavailable" memory at the bottom of the chart, then "used but available"
-        // memory, and leave "free" uncolored at the top of the chart.
-        let unavailable_kb = new_stats.mem_total - new_stats.mem_available;
-        let available_used_kb = new_stats.mem_available - new_stats.mem_free;
-        self.chart.add_values(&[unavailable_kb, available_used_kb]);
+        self.category_kb = [0.0; NUM_MEM_CATEGORIES];
+        for (idx, (category, _)) in self.categories.iter().enumerate() {
+            self.category_kb[idx] = category.get_kb(new_stats) as f64;
+        }
+        self.chart.add_values(&self.category_kb);
 
         // Mark that the drawing area needs to be redrawn
         self.da.queue_draw();
     }
 
+    fn set_time_axis(&mut self, time_axis: &TimeAxis) {
+        self.chart.set_time_axis(time_axis);
+    }
+
+    fn save_history(&self) -> WidgetHistory {
+        WidgetHistory::from([("chart".to_string(), self.chart.save_history())])
+    }
+
+    fn restore_history(&mut self, mut history: WidgetHistory) {
+        self.chart.restore_from(&mut history, "chart");
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stats.borrow().is_stale()
+    }
+
     fn gtk_widget<'a>(&'a self) -> &'a gtk::Box {
         &self.container
     }
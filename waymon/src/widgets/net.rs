@@ -1,9 +1,11 @@
 use crate::collectors::net::NetDevStats;
-use crate::config::NetWidgetConfig;
-use crate::stats::{AllStats, StatsDelta};
+use crate::collectors::route::DefaultRouteStats;
+use crate::config::{DevicePattern, NetDevice, NetWidgetConfig};
+use crate::history::WidgetHistory;
+use crate::stats::{counter_delta, AllStats, StatsDelta};
 use crate::util::humanify_f64;
 use crate::waymon::Waymon;
-use crate::widgets::timeseries::{Chart, ChartDrawCallback, StackedTimeseriesChart};
+use crate::widgets::timeseries::{Chart, ChartDrawCallback, StackedTimeseriesChart, TimeAxis};
 use crate::widgets::Widget;
 use gtk::cairo;
 use gtk::prelude::*;
@@ -15,8 +17,15 @@ use tracing::warn;
 // per second) from bps (bits per second).
 #[allow(non_snake_case)]
 pub struct NetWidget {
-    dev: String,
+    dev: NetDevice,
+    exclude: Option<DevicePattern>,
     stats: Rc<RefCell<StatsDelta<NetDevStats>>>,
+    // The default route information.  This is only used when dev is set to "auto".
+    route_stats: Option<Rc<RefCell<StatsDelta<DefaultRouteStats>>>>,
+    // The interface currently carrying the default route, in auto mode
+    active_dev: Option<String>,
+    label_text: String,
+    label: gtk::Label,
     container: gtk::Box,
     da: gtk::DrawingArea,
     chart: StackedTimeseriesChart<f64, 2>,
@@ -29,26 +38,74 @@ impl NetWidget {
     pub fn new(
         config: &NetWidgetConfig,
         all_stats: &mut AllStats,
-        history_length: usize,
+        time_axis: &TimeAxis,
     ) -> Rc<RefCell<NetWidget>> {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, /*spacing*/ 0);
+        let label = Waymon::add_widget_label(&container, &config.label);
+        let route_stats = match config.dev {
+            NetDevice::Auto => Some(all_stats.get::<DefaultRouteStats>(())),
+            NetDevice::Pattern(_) => None,
+        };
         let widget_rc = Rc::new(RefCell::new(NetWidget {
             dev: config.dev.clone(),
-            stats: all_stats.get_net_stats(),
-            container: gtk::Box::new(gtk::Orientation::Vertical, /*spacing*/ 0),
+            exclude: config.exclude.clone(),
+            stats: all_stats.get::<NetDevStats>(()),
+            route_stats,
+            active_dev: None,
+            label_text: config.label.clone(),
+            label,
+            container,
             da: gtk::DrawingArea::new(),
-            chart: StackedTimeseriesChart::new(history_length),
+            chart: StackedTimeseriesChart::new(time_axis),
             dev_present: true,
             rx_Bps: 0.0,
             tx_Bps: 0.0,
         }));
         {
             let widget = widget_rc.borrow();
-            Waymon::add_widget_label(&widget.container, &config.label);
             Chart::configure(&widget.da, config.height, widget_rc.clone());
             widget.container.append(&widget.da);
         }
         widget_rc
     }
+
+    fn is_selected(&self, name: &str) -> bool {
+        let matches = match &self.dev {
+            NetDevice::Auto => self.active_dev.as_deref() == Some(name),
+            NetDevice::Pattern(pattern) => pattern.is_match(name),
+        };
+        if !matches {
+            return false;
+        }
+        match &self.exclude {
+            Some(exclude) => !exclude.is_match(name),
+            None => true,
+        }
+    }
+
+    /// In auto mode, update the active interface to the one carrying the default route.
+    fn update_active_dev(&mut self) {
+        let Some(route_stats) = &self.route_stats else {
+            return;
+        };
+        let active_dev = route_stats
+            .borrow()
+            .get_new()
+            .default_interface()
+            .map(str::to_string);
+        if active_dev == self.active_dev {
+            return;
+        }
+
+        // Show the active interface name in the label.  We keep using the same chart, so the
+        // history continues across interface changes.
+        let text = match &active_dev {
+            Some(dev) => format!("{} ({})", self.label_text, dev),
+            None => self.label_text.clone(),
+        };
+        Waymon::set_widget_label(&self.label, &text);
+        self.active_dev = active_dev;
+    }
 }
 
 impl ChartDrawCallback for NetWidget {
@@ -76,22 +133,44 @@ impl ChartDrawCallback for NetWidget {
 
 impl Widget for NetWidget {
     fn update(&mut self) {
+        self.update_active_dev();
+
         let s = self.stats.borrow();
         let (new_stats, old_stats) = s.get_new_and_old();
-        if let (Some(new), Some(old)) = (
-            new_stats.interfaces.get(&self.dev),
-            old_stats.interfaces.get(&self.dev),
-        ) {
+
+        // Sum the traffic across all matching interfaces that are present in both samples
+        let mut any_present = false;
+        let mut rx_bytes: u64 = 0;
+        let mut tx_bytes: u64 = 0;
+        for (name, new) in &new_stats.interfaces {
+            if !self.is_selected(name) {
+                continue;
+            }
+            if let Some(old) = old_stats.interfaces.get(name) {
+                any_present = true;
+                rx_bytes += counter_delta(new.rx_bytes, old.rx_bytes);
+                tx_bytes += counter_delta(new.tx_bytes, old.tx_bytes);
+            }
+        }
+
+        if any_present {
             self.dev_present = true;
             let delta_secs = s.time_delta().as_secs_f64();
-            let rx_bytes = new.rx_bytes - old.rx_bytes;
-            let tx_bytes = new.tx_bytes - old.tx_bytes;
             self.rx_Bps = (rx_bytes as f64) / delta_secs;
             self.tx_Bps = (tx_bytes as f64) / delta_secs;
             self.chart.add_values(&[self.rx_Bps, self.tx_Bps])
-        } else if self.dev_present {
-            warn!("interface {} not present", &self.dev);
-            self.dev_present = false;
+        } else {
+            if self.dev_present {
+                match &self.dev {
+                    NetDevice::Auto => warn!("no interface found carrying the default route"),
+                    NetDevice::Pattern(_) => warn!("interface {} not present", &self.dev),
+                }
+                self.dev_present = false;
+            }
+            // Keep the chart moving while no interface is present, so that the history lines up
+            // correctly if an interface appears again later.
+            self.rx_Bps = 0.0;
+            self.tx_Bps = 0.0;
             self.chart.add_values(&[0.0, 0.0]);
         }
 
@@ -99,6 +178,26 @@ impl Widget for NetWidget {
         self.da.queue_draw();
     }
 
+    fn set_time_axis(&mut self, time_axis: &TimeAxis) {
+        self.chart.set_time_axis(time_axis);
+    }
+
+    fn save_history(&self) -> WidgetHistory {
+        WidgetHistory::from([("chart".to_string(), self.chart.save_history())])
+    }
+
+    fn restore_history(&mut self, mut history: WidgetHistory) {
+        self.chart.restore_from(&mut history, "chart");
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stats.borrow().is_stale()
+            || self
+                .route_stats
+                .as_ref()
+                .is_some_and(|route| route.borrow().is_stale())
+    }
+
     fn gtk_widget<'a>(&'a self) -> &'a gtk::Box {
         &self.container
     }
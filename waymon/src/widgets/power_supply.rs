@@ -0,0 +1,306 @@
+use crate::collectors::power_supply::{PowerSupply, PowerSupplyStats};
+use crate::config::default_chart_height;
+use crate::history::WidgetHistory;
+use crate::stats::{AllStats, StatsDelta};
+use crate::waymon::Waymon;
+use crate::widgets::timeseries::{
+    Chart, ChartDrawCallback, Color, StackedTimeseriesChart, TimeAxis,
+};
+use crate::widgets::Widget;
+use gtk::cairo;
+use gtk::prelude::*;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::Duration;
+use tracing::warn;
+
+// How much recent history to use when estimating the discharge rate
+const RATE_WINDOW: Duration = Duration::from_secs(120);
+// Many batteries only update energy_now every few seconds or more.  Wait until we have at least
+// this much history before using the change in energy to estimate the rate, and use the reported
+// power draw until then.
+const MIN_RATE_WINDOW: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct PowerSupplyWidgetConfig {
+    pub label: String,
+
+    // The name of the supply to show, e.g. "BAT0".
+    // Defaults to the first battery found.
+    #[serde(default)]
+    pub supply: Option<String>,
+
+    #[serde(default = "default_chart_height")]
+    pub height: u32,
+}
+
+struct RateSample {
+    // The time since the first sample in the window
+    time: Duration,
+    energy_wh: Option<f64>,
+    power_w: Option<f64>,
+}
+
+pub struct PowerSupplyWidget {
+    config: PowerSupplyWidgetConfig,
+    stats: Rc<RefCell<StatsDelta<PowerSupplyStats>>>,
+    container: gtk::Box,
+    da: gtk::DrawingArea,
+    charge_chart: StackedTimeseriesChart<f64, 1>,
+    power_chart: StackedTimeseriesChart<f64, 1>,
+    // The latest sample for the supply, or None if it is not present
+    supply: Option<PowerSupply>,
+    // Recent samples taken while the supply had the current charging status.
+    // These are used to estimate the time remaining.
+    rate_window: VecDeque<RateSample>,
+    rate_window_status: Option<String>,
+    rate_window_time: Duration,
+    supply_present: bool,
+}
+
+impl PowerSupplyWidgetConfig {
+    pub fn create_widget(
+        &self,
+        all_stats: &mut AllStats,
+        time_axis: &TimeAxis,
+    ) -> Rc<RefCell<PowerSupplyWidget>> {
+        let mut charge_chart = StackedTimeseriesChart::new(time_axis);
+        charge_chart.set_color(0, Color::new(0.5, 0.8, 0.5));
+        let mut power_chart = StackedTimeseriesChart::new(time_axis);
+        power_chart.set_color(0, Color::new(0.85, 0.0, 0.0));
+
+        let widget_rc = Rc::new(RefCell::new(PowerSupplyWidget {
+            config: self.clone(),
+            stats: all_stats.get::<PowerSupplyStats>(()),
+            container: gtk::Box::new(gtk::Orientation::Vertical, /*spacing*/ 0),
+            da: gtk::DrawingArea::new(),
+            charge_chart,
+            power_chart,
+            supply: None,
+            rate_window: VecDeque::new(),
+            rate_window_status: None,
+            rate_window_time: Duration::ZERO,
+            // Initialize supply_present to true so that we will log a warning once
+            // if the supply is not actually present.
+            supply_present: true,
+        }));
+        {
+            let widget = widget_rc.borrow();
+            Waymon::add_widget_label(&widget.container, &self.label);
+            Chart::configure(&widget.da, self.height, widget_rc.clone());
+            widget.container.append(&widget.da);
+        }
+        widget_rc
+    }
+}
+
+impl PowerSupplyWidget {
+    fn find_supply<'a>(&self, stats: &'a PowerSupplyStats) -> Option<&'a PowerSupply> {
+        match &self.config.supply {
+            Some(name) => stats.get(name),
+            None => stats.first_battery(),
+        }
+    }
+
+    fn update_rate_window(&mut self, time_delta: Duration) {
+        let Some(supply) = &self.supply else {
+            self.rate_window.clear();
+            self.rate_window_status = None;
+            return;
+        };
+
+        // Start a new window whenever the charging status changes, so that we don't estimate
+        // the discharge rate using samples taken while charging, or vice-versa.
+        if self.rate_window.is_empty() || self.rate_window_status != supply.status {
+            self.rate_window.clear();
+            self.rate_window_status = supply.status.clone();
+            self.rate_window_time = Duration::ZERO;
+        } else {
+            self.rate_window_time += time_delta;
+        }
+
+        self.rate_window.push_back(RateSample {
+            time: self.rate_window_time,
+            energy_wh: supply.energy_wh(),
+            power_w: supply.power_watts(),
+        });
+        while let Some(front) = self.rate_window.front() {
+            if self.rate_window_time - front.time <= RATE_WINDOW {
+                break;
+            }
+            self.rate_window.pop_front();
+        }
+    }
+
+    /// Estimate the recent rate of charge or discharge, in watts.
+    fn estimate_rate(&self) -> Option<f64> {
+        // Prefer the observed change in energy over the window, since the instantaneous power
+        // reading can fluctuate quite a bit.
+        if let (Some(first), Some(last)) = (self.rate_window.front(), self.rate_window.back()) {
+            let span = last.time - first.time;
+            if span >= MIN_RATE_WINDOW {
+                if let (Some(first_wh), Some(last_wh)) = (first.energy_wh, last.energy_wh) {
+                    let rate = (last_wh - first_wh).abs() / (span.as_secs_f64() / 3600.0);
+                    if rate > 0.0 {
+                        return Some(rate);
+                    }
+                }
+            }
+        }
+
+        // Otherwise fall back to the average reported power draw
+        let (sum, count) = self
+            .rate_window
+            .iter()
+            .filter_map(|s| s.power_w)
+            .fold((0.0, 0), |(sum, count), p| (sum + p, count + 1));
+        if count == 0 || sum <= 0.0 {
+            return None;
+        }
+        Some(sum / (count as f64))
+    }
+
+    /// Estimate the time until the battery is empty (when discharging) or full (when charging).
+    fn estimate_time_remaining(&self, supply: &PowerSupply) -> Option<Duration> {
+        let energy = supply.energy_wh()?;
+        let remaining_wh = if supply.is_discharging() {
+            energy
+        } else if supply.is_charging() {
+            (supply.energy_full_wh()? - energy).max(0.0)
+        } else {
+            return None;
+        };
+        let hours = remaining_wh / self.estimate_rate()?;
+        // Ignore absurdly long estimates, which generally just mean the supply is nearly idle
+        if !hours.is_finite() || hours > 999.0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64(hours * 3600.0))
+    }
+
+    fn annotation(&self) -> String {
+        let Some(supply) = &self.supply else {
+            return "Not Present".to_string();
+        };
+
+        let mut text = match supply.charge_percent() {
+            Some(pct) => format!("{:.0}%", pct),
+            None => "?%".to_string(),
+        };
+        if let Some(power) = supply.power_watts() {
+            text.push_str(&format!(" {:.1}W", power));
+        }
+
+        if let Some(remaining) = self.estimate_time_remaining(supply) {
+            let suffix = if supply.is_charging() {
+                "to full"
+            } else {
+                "left"
+            };
+            text.push_str(&format!("\n{} {}", format_duration(remaining), suffix));
+        } else if let Some(status) = &supply.status {
+            text.push_str(&format!("\n{}", status));
+        }
+        text
+    }
+}
+
+/// Format a time estimate as hours and minutes, e.g. "2h05m" or "45m"
+fn format_duration(d: Duration) -> String {
+    let minutes = d.as_secs() / 60;
+    if minutes >= 60 {
+        format!("{}h{:02}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+impl ChartDrawCallback for PowerSupplyWidget {
+    fn draw(&self, cr: &cairo::Context, width: i32, height: i32) {
+        // The charge level is drawn as a filled chart scaled to 100%, and the power draw is
+        // drawn as a line on top of it, scaled to the maximum power draw in the history.
+        let charge_scale = (height as f64) / 100.0;
+        self.charge_chart.draw(cr, width, height, charge_scale);
+
+        let max_power = self.power_chart.max_value().max(1.0);
+        let power_scale = ((height - 2) as f64) / max_power;
+        self.power_chart.draw_line(cr, width, height, power_scale);
+
+        Chart::draw_annotation(&self.da, cr, width, height, &self.annotation());
+    }
+}
+
+impl Widget for PowerSupplyWidget {
+    fn update(&mut self) {
+        let time_delta = {
+            let s = self.stats.borrow();
+            self.supply = self.find_supply(s.get_new()).cloned();
+            s.time_delta()
+        };
+
+        if self.supply.is_none() && self.supply_present {
+            match &self.config.supply {
+                Some(name) => warn!("power supply {:?} not found", name),
+                None => warn!("no battery found for widget {:?}", self.config.label),
+            }
+        }
+        self.supply_present = self.supply.is_some();
+
+        self.update_rate_window(time_delta);
+
+        let (charge, power) = match &self.supply {
+            Some(supply) => (
+                supply.charge_percent().unwrap_or(0.0),
+                supply.power_watts().unwrap_or(0.0),
+            ),
+            None => (0.0, 0.0),
+        };
+        self.charge_chart.add_values(&[charge]);
+        self.power_chart.add_values(&[power]);
+
+        // Mark that the drawing area needs to be redrawn
+        self.da.queue_draw();
+    }
+
+    fn set_time_axis(&mut self, time_axis: &TimeAxis) {
+        self.charge_chart.set_time_axis(time_axis);
+        self.power_chart.set_time_axis(time_axis);
+    }
+
+    fn save_history(&self) -> WidgetHistory {
+        WidgetHistory::from([
+            ("charge".to_string(), self.charge_chart.save_history()),
+            ("power".to_string(), self.power_chart.save_history()),
+        ])
+    }
+
+    fn restore_history(&mut self, mut history: WidgetHistory) {
+        self.charge_chart.restore_from(&mut history, "charge");
+        self.power_chart.restore_from(&mut history, "power");
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stats.borrow().is_stale()
+    }
+
+    fn gtk_widget(&self) -> &gtk::Box {
+        &self.container
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(59)), "0m");
+        assert_eq!(format_duration(Duration::from_secs(45 * 60 + 30)), "45m");
+        assert_eq!(
+            format_duration(Duration::from_secs(2 * 3600 + 5 * 60)),
+            "2h05m"
+        );
+    }
+}
@@ -1,8 +1,12 @@
-use crate::collectors::pressure::{CpuPressure, IoPressure, MemoryPressure, PressureStats};
+use crate::collectors::cgroup::relative_cgroup_path;
+use crate::collectors::pressure::{
+    CpuPressure, IoPressure, MemoryPressure, PressureAverages, PressureStats,
+};
 use crate::config::default_chart_height;
-use crate::stats::{AllStats, StatType, StatsDelta};
+use crate::history::WidgetHistory;
+use crate::stats::{counter_delta, AllStats, StatType, StatsDelta};
 use crate::waymon::Waymon;
-use crate::widgets::timeseries::{Chart, ChartDrawCallback, StackedTimeseriesChart};
+use crate::widgets::timeseries::{Chart, ChartDrawCallback, StackedTimeseriesChart, TimeAxis};
 use crate::widgets::Widget;
 use gtk::cairo;
 use gtk::prelude::*;
@@ -18,22 +22,27 @@ pub struct PressureWidget<T: PressureStats + StatType + 'static> {
     chart: StackedTimeseriesChart<f64, 2>,
     some_fraction: f64,
     full_fraction: f64,
+    // The kernel's running averages, as of the latest sample
+    some_avg: PressureAverages,
+    full_avg: PressureAverages,
 }
 
 impl<T: PressureStats + StatType + 'static> PressureWidget<T> {
     pub fn new(
         stats: Rc<RefCell<StatsDelta<T>>>,
         label: &str,
-        history_length: usize,
+        time_axis: &TimeAxis,
         height: u32,
     ) -> Rc<RefCell<PressureWidget<T>>> {
         let widget_rc = Rc::new(RefCell::new(PressureWidget::<T> {
             stats,
             container: gtk::Box::new(gtk::Orientation::Vertical, /*spacing*/ 0),
             da: gtk::DrawingArea::new(),
-            chart: StackedTimeseriesChart::new(history_length),
+            chart: StackedTimeseriesChart::new(time_axis),
             some_fraction: 0.0,
             full_fraction: 0.0,
+            some_avg: PressureAverages::default(),
+            full_avg: PressureAverages::default(),
         }));
         {
             let widget = widget_rc.borrow();
@@ -45,72 +54,85 @@ impl<T: PressureStats + StatType + 'static> PressureWidget<T> {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 pub struct CpuPressureWidgetConfig {
     pub label: String,
     #[serde(default = "default_chart_height")]
     pub height: u32,
+    // Show the pressure of this cgroup, rather than of the whole system
+    #[serde(default)]
+    pub cgroup: Option<String>,
 }
 
 impl CpuPressureWidgetConfig {
     pub fn create_widget(
         &self,
         all_stats: &mut AllStats,
-        history_length: usize,
+        time_axis: &TimeAxis,
     ) -> Rc<RefCell<PressureWidget<CpuPressure>>> {
         PressureWidget::<CpuPressure>::new(
-            all_stats.get_cpu_pressure(),
+            all_stats.get::<CpuPressure>(self.cgroup.as_deref().map(relative_cgroup_path)),
             &self.label,
-            history_length,
+            time_axis,
             self.height,
         )
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 pub struct IoPressureWidgetConfig {
     pub label: String,
     #[serde(default = "default_chart_height")]
     pub height: u32,
+    // Show the pressure of this cgroup, rather than of the whole system
+    #[serde(default)]
+    pub cgroup: Option<String>,
 }
 
 impl IoPressureWidgetConfig {
     pub fn create_widget(
         &self,
         all_stats: &mut AllStats,
-        history_length: usize,
+        time_axis: &TimeAxis,
     ) -> Rc<RefCell<PressureWidget<IoPressure>>> {
         PressureWidget::<IoPressure>::new(
-            all_stats.get_io_pressure(),
+            all_stats.get::<IoPressure>(self.cgroup.as_deref().map(relative_cgroup_path)),
             &self.label,
-            history_length,
+            time_axis,
             self.height,
         )
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 pub struct MemPressureWidgetConfig {
     pub label: String,
     #[serde(default = "default_chart_height")]
     pub height: u32,
+    // Show the pressure of this cgroup, rather than of the whole system
+    #[serde(default)]
+    pub cgroup: Option<String>,
 }
 
 impl MemPressureWidgetConfig {
     pub fn create_widget(
         &self,
         all_stats: &mut AllStats,
-        history_length: usize,
+        time_axis: &TimeAxis,
     ) -> Rc<RefCell<PressureWidget<MemoryPressure>>> {
         PressureWidget::<MemoryPressure>::new(
-            all_stats.get_mem_pressure(),
+            all_stats.get::<MemoryPressure>(self.cgroup.as_deref().map(relative_cgroup_path)),
             &self.label,
-            history_length,
+            time_axis,
             self.height,
         )
     }
 }
 
+fn format_averages(avg: &PressureAverages) -> String {
+    format!("{:.1} {:.1} {:.1}", avg.avg10, avg.avg60, avg.avg300)
+}
+
 impl<T: PressureStats + StatType> ChartDrawCallback for PressureWidget<T> {
     fn draw(&self, cr: &cairo::Context, width: i32, height: i32) {
         let max_value = self.chart.max_value();
@@ -121,10 +143,13 @@ impl<T: PressureStats + StatType> ChartDrawCallback for PressureWidget<T> {
         };
         self.chart.draw(cr, width, height, y_scale);
 
+        // Below each current value, show the kernel's 10s, 60s, and 300s averages
         let annotation = format!(
-            "some: {:.0}%\nfull: {:.0}%\n",
+            "some: {:.0}%\n {}\nfull: {:.0}%\n {}\n",
             self.some_fraction * 100.0,
+            format_averages(&self.some_avg),
             self.full_fraction * 100.0,
+            format_averages(&self.full_avg),
         );
         Chart::draw_annotation(&self.da, cr, width, height, &annotation);
     }
@@ -135,8 +160,8 @@ impl<T: PressureStats + StatType> Widget for PressureWidget<T> {
         let s = self.stats.borrow();
         let (new, old) = s.get_new_and_old();
 
-        let some = Duration::from_micros((new.some_us() - old.some_us()) as u64);
-        let full = Duration::from_micros((new.full_us() - old.full_us()) as u64);
+        let some = Duration::from_micros(counter_delta(new.some_us(), old.some_us()));
+        let full = Duration::from_micros(counter_delta(new.full_us(), old.full_us()));
         let delta_secs = s.time_delta().as_secs_f64();
 
         // According to the documentation, it seems like the "some" count should include the "full"
@@ -147,6 +172,8 @@ impl<T: PressureStats + StatType> Widget for PressureWidget<T> {
 
         self.some_fraction = some.as_secs_f64() / delta_secs;
         self.full_fraction = full.as_secs_f64() / delta_secs;
+        self.some_avg = new.some_avg().clone();
+        self.full_avg = new.full_avg().clone();
 
         self.chart
             .add_values(&[some_exclusive.as_secs_f64(), full.as_secs_f64()]);
@@ -155,6 +182,22 @@ impl<T: PressureStats + StatType> Widget for PressureWidget<T> {
         self.da.queue_draw();
     }
 
+    fn set_time_axis(&mut self, time_axis: &TimeAxis) {
+        self.chart.set_time_axis(time_axis);
+    }
+
+    fn save_history(&self) -> WidgetHistory {
+        WidgetHistory::from([("chart".to_string(), self.chart.save_history())])
+    }
+
+    fn restore_history(&mut self, mut history: WidgetHistory) {
+        self.chart.restore_from(&mut history, "chart");
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stats.borrow().is_stale()
+    }
+
     fn gtk_widget<'a>(&'a self) -> &'a gtk::Box {
         &self.container
     }
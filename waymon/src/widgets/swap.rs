@@ -0,0 +1,188 @@
+use crate::collectors::meminfo::MemoryStats;
+use crate::collectors::vmstat::VmStats;
+use crate::config::default_chart_height;
+use crate::history::WidgetHistory;
+use crate::stats::{counter_delta, AllStats, StatsDelta};
+use crate::util::humanify_f64;
+use crate::waymon::Waymon;
+use crate::widgets::timeseries::{
+    Chart, ChartDrawCallback, Color, StackedTimeseriesChart, TimeAxis,
+};
+use crate::widgets::Widget;
+use gtk::cairo;
+use gtk::prelude::*;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct SwapWidgetConfig {
+    pub label: String,
+
+    // The total height of the usage and swap rate charts
+    #[serde(default = "default_chart_height")]
+    pub height: u32,
+}
+
+pub struct SwapWidget {
+    mem_stats: Rc<RefCell<StatsDelta<MemoryStats>>>,
+    vm_stats: Rc<RefCell<StatsDelta<VmStats>>>,
+    container: gtk::Box,
+    da: gtk::DrawingArea,
+    // Swap in use, split into pages that are only in swap and pages that are also cached in
+    // memory, in kilobytes
+    usage_chart: StackedTimeseriesChart<f64, 2>,
+    // Bytes swapped in and out per second
+    rate_chart: StackedTimeseriesChart<f64, 2>,
+    swap_total_kb: u64,
+    swap_used_kb: u64,
+    swap_in_rate: f64,
+    swap_out_rate: f64,
+}
+
+impl SwapWidgetConfig {
+    pub fn create_widget(
+        &self,
+        all_stats: &mut AllStats,
+        time_axis: &TimeAxis,
+    ) -> Rc<RefCell<SwapWidget>> {
+        let mut rate_chart = StackedTimeseriesChart::new(time_axis);
+        rate_chart.set_color(0, Color::new(0.0, 0.0, 1.0));
+        rate_chart.set_color(1, Color::new(0.85, 0.0, 0.0));
+        let widget_rc = Rc::new(RefCell::new(SwapWidget {
+            mem_stats: all_stats.get::<MemoryStats>(()),
+            vm_stats: all_stats.get::<VmStats>(()),
+            container: gtk::Box::new(gtk::Orientation::Vertical, /*spacing*/ 0),
+            da: gtk::DrawingArea::new(),
+            usage_chart: StackedTimeseriesChart::new(time_axis),
+            rate_chart,
+            swap_total_kb: 0,
+            swap_used_kb: 0,
+            swap_in_rate: 0.0,
+            swap_out_rate: 0.0,
+        }));
+        {
+            let widget = widget_rc.borrow();
+            Waymon::add_widget_label(&widget.container, &self.label);
+            Chart::configure(&widget.da, self.height, widget_rc.clone());
+            widget.container.append(&widget.da);
+        }
+        widget_rc
+    }
+}
+
+impl SwapWidget {
+    fn draw_usage(&self, cr: &cairo::Context, width: i32, height: i32) {
+        if self.swap_total_kb == 0 {
+            Chart::draw_annotation(&self.da, cr, width, height, "No Swap");
+            return;
+        }
+        let y_scale = ((height - 1) as f64) / (self.swap_total_kb as f64);
+        self.usage_chart.draw(cr, width, height, y_scale);
+        let annotation = format!(
+            "{:.0}% used\n {}/{}",
+            100.0 * (self.swap_used_kb as f64) / (self.swap_total_kb as f64),
+            humanify_f64((self.swap_used_kb * 1024) as f64, 2),
+            humanify_f64((self.swap_total_kb * 1024) as f64, 2),
+        );
+        Chart::draw_annotation(&self.da, cr, width, height, &annotation);
+    }
+
+    fn draw_rates(&self, cr: &cairo::Context, width: i32, height: i32) {
+        let max_value = self.rate_chart.max_value();
+        let y_scale = if max_value <= 0.0 {
+            1.0
+        } else {
+            ((height - 2) as f64) / max_value
+        };
+        self.rate_chart.draw(cr, width, height, y_scale);
+        let annotation = format!(
+            "in {}/s\nout {}/s",
+            humanify_f64(self.swap_in_rate, 2),
+            humanify_f64(self.swap_out_rate, 2),
+        );
+        Chart::draw_annotation(&self.da, cr, width, height, &annotation);
+    }
+}
+
+impl ChartDrawCallback for SwapWidget {
+    fn draw(&self, cr: &cairo::Context, width: i32, height: i32) {
+        // Split the drawing area into two rows, leaving a 1 pixel gap between them
+        let row_height = height / 2;
+        let chart_height = row_height - 1;
+        for row in 0..2 {
+            let _ = cr.save();
+            cr.translate(0.0, (row * row_height) as f64);
+            cr.rectangle(0.0, 0.0, width as f64, chart_height as f64);
+            cr.clip();
+            if row == 0 {
+                self.draw_usage(cr, width, chart_height);
+            } else {
+                self.draw_rates(cr, width, chart_height);
+            }
+            let _ = cr.restore();
+        }
+    }
+}
+
+impl Widget for SwapWidget {
+    fn update(&mut self) {
+        {
+            let s = self.mem_stats.borrow();
+            let mem = s.get_new();
+            self.swap_total_kb = mem.swap_total;
+            self.swap_used_kb = mem.swap_used();
+            let cached_kb = std::cmp::min(mem.swap_cached, self.swap_used_kb);
+            self.usage_chart
+                .add_values(&[(self.swap_used_kb - cached_kb) as f64, cached_kb as f64]);
+        }
+
+        {
+            let s = self.vm_stats.borrow();
+            let (new, old) = s.get_new_and_old();
+            let delta_secs = s.time_delta().as_secs_f64();
+            // The old sample has no page size if this is the first update
+            if old.page_size == 0 || delta_secs <= 0.0 {
+                self.swap_in_rate = 0.0;
+                self.swap_out_rate = 0.0;
+            } else {
+                let page_size = new.page_size;
+                let rate = |new: u64, old: u64| -> f64 {
+                    (counter_delta(new, old) * page_size) as f64 / delta_secs
+                };
+                self.swap_in_rate = rate(new.pswpin, old.pswpin);
+                self.swap_out_rate = rate(new.pswpout, old.pswpout);
+            }
+        }
+        self.rate_chart
+            .add_values(&[self.swap_in_rate, self.swap_out_rate]);
+
+        // Mark that the drawing area needs to be redrawn
+        self.da.queue_draw();
+    }
+
+    fn set_time_axis(&mut self, time_axis: &TimeAxis) {
+        self.usage_chart.set_time_axis(time_axis);
+        self.rate_chart.set_time_axis(time_axis);
+    }
+
+    fn save_history(&self) -> WidgetHistory {
+        WidgetHistory::from([
+            ("usage".to_string(), self.usage_chart.save_history()),
+            ("rate".to_string(), self.rate_chart.save_history()),
+        ])
+    }
+
+    fn restore_history(&mut self, mut history: WidgetHistory) {
+        self.usage_chart.restore_from(&mut history, "usage");
+        self.rate_chart.restore_from(&mut history, "rate");
+    }
+
+    fn is_stale(&self) -> bool {
+        self.mem_stats.borrow().is_stale() || self.vm_stats.borrow().is_stale()
+    }
+
+    fn gtk_widget(&self) -> &gtk::Box {
+        &self.container
+    }
+}
@@ -0,0 +1,273 @@
+use crate::collectors::temperature::TemperatureStats;
+use crate::config::{default_chart_height, ConfigRegex};
+use crate::history::WidgetHistory;
+use crate::stats::{AllStats, StatsDelta};
+use crate::waymon::Waymon;
+use crate::widgets::timeseries::{
+    Chart, ChartDrawCallback, Color, StackedTimeseriesChart, TimeAxis,
+};
+use crate::widgets::Widget;
+use gtk::cairo;
+use gtk::prelude::*;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::rc::Rc;
+use tracing::warn;
+
+// Colors used for the sensor lines, in order
+const SENSOR_COLORS: [(f64, f64, f64); 6] = [
+    (0.0, 0.0, 1.0),
+    (0.85, 0.0, 0.0),
+    (0.0, 0.5, 0.0),
+    (0.9, 0.6, 0.0),
+    (0.5, 0.0, 0.5),
+    (0.0, 0.6, 0.6),
+];
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct TemperatureWidgetConfig {
+    pub label: String,
+
+    // Regular expressions used to select sensors by chip name and by sensor label.
+    // All sensors are shown if these are not specified.
+    #[serde(default)]
+    pub chip: Option<ConfigRegex>,
+    #[serde(default)]
+    pub sensor: Option<ConfigRegex>,
+
+    #[serde(default = "default_chart_height")]
+    pub height: u32,
+}
+
+struct SensorHistory {
+    chip: String,
+    label: String,
+    chart: StackedTimeseriesChart<f64, 1>,
+    // The current temperature, or None if the sensor was not present in the latest sample
+    temp: Option<f64>,
+    crit: Option<f64>,
+}
+
+pub struct TemperatureWidget {
+    config: TemperatureWidgetConfig,
+    stats: Rc<RefCell<StatsDelta<TemperatureStats>>>,
+    container: gtk::Box,
+    da: gtk::DrawingArea,
+    time_axis: TimeAxis,
+    sensors: Vec<SensorHistory>,
+    sensors_present: bool,
+    // Saved history for sensors that have not been seen yet
+    restored_history: WidgetHistory,
+}
+
+impl TemperatureWidgetConfig {
+    pub fn create_widget(
+        &self,
+        all_stats: &mut AllStats,
+        time_axis: &TimeAxis,
+    ) -> Rc<RefCell<TemperatureWidget>> {
+        let widget_rc = Rc::new(RefCell::new(TemperatureWidget {
+            config: self.clone(),
+            stats: all_stats.get::<TemperatureStats>(()),
+            container: gtk::Box::new(gtk::Orientation::Vertical, /*spacing*/ 0),
+            da: gtk::DrawingArea::new(),
+            time_axis: time_axis.clone(),
+            sensors: Vec::new(),
+            // Initialize sensors_present to true so that we will log a warning once
+            // if no sensors actually match.
+            sensors_present: true,
+            restored_history: WidgetHistory::new(),
+        }));
+        {
+            let widget = widget_rc.borrow();
+            Waymon::add_widget_label(&widget.container, &self.label);
+            Chart::configure(&widget.da, self.height, widget_rc.clone());
+            widget.container.append(&widget.da);
+        }
+        widget_rc
+    }
+}
+
+impl TemperatureWidget {
+    fn is_selected(&self, chip: &str, label: &str) -> bool {
+        let matches = |re: &Option<ConfigRegex>, s: &str| match re {
+            Some(re) => re.is_match(s),
+            None => true,
+        };
+        matches(&self.config.chip, chip) && matches(&self.config.sensor, label)
+    }
+
+    /// Find the history entry for a sensor, adding a new one if needed.
+    ///
+    /// Multiple sensors can have the same chip name and label (e.g., if there are multiple NVMe
+    /// drives), so we match them up with our history entries in order, skipping entries that
+    /// have already been updated in this sample.
+    fn find_sensor(
+        sensors: &mut Vec<SensorHistory>,
+        time_axis: &TimeAxis,
+        restored_history: &mut WidgetHistory,
+        chip: &str,
+        label: &str,
+    ) -> usize {
+        if let Some(idx) = sensors
+            .iter()
+            .position(|h| h.temp.is_none() && h.chip == chip && h.label == label)
+        {
+            return idx;
+        }
+
+        let (r, g, b) = SENSOR_COLORS[sensors.len() % SENSOR_COLORS.len()];
+        let mut chart = StackedTimeseriesChart::new(time_axis);
+        chart.set_color(0, Color::new(r, g, b));
+        chart.restore_from(restored_history, &Self::history_key(sensors, chip, label));
+        sensors.push(SensorHistory {
+            chip: chip.to_string(),
+            label: label.to_string(),
+            chart,
+            temp: None,
+            crit: None,
+        });
+        sensors.len() - 1
+    }
+
+    /// The key used to save the history of the next sensor with this chip name and label.
+    fn history_key(sensors: &[SensorHistory], chip: &str, label: &str) -> String {
+        let index = sensors
+            .iter()
+            .filter(|h| h.chip == chip && h.label == label)
+            .count();
+        format!("{}/{}/{}", chip, label, index)
+    }
+
+    /// The lowest critical threshold of any of the sensors being shown
+    fn min_crit(&self) -> Option<f64> {
+        self.sensors
+            .iter()
+            .filter_map(|s| s.crit)
+            .min_by(|a, b| a.total_cmp(b))
+    }
+}
+
+impl ChartDrawCallback for TemperatureWidget {
+    fn draw(&self, cr: &cairo::Context, width: i32, height: i32) {
+        let min_crit = self.min_crit();
+        let max_value = self
+            .sensors
+            .iter()
+            .map(|s| s.chart.max_value())
+            .fold(min_crit.unwrap_or(0.0), f64::max);
+        let y_scale = if max_value <= 0.0 {
+            1.0
+        } else {
+            ((height - 2) as f64) / max_value
+        };
+
+        if let Some(sensor) = self.sensors.first() {
+            sensor.chart.draw_gridlines(cr, width, height);
+        }
+        for sensor in &self.sensors {
+            sensor.chart.draw_line(cr, width, height, y_scale);
+        }
+        if let Some(crit) = min_crit {
+            let y = (height as f64) - crit * y_scale;
+            cr.set_source_rgb(0.85, 0.0, 0.0);
+            cr.set_line_width(1.0);
+            cr.move_to(0.0, y);
+            cr.line_to(width as f64, y);
+            let _ = cr.stroke();
+        }
+
+        // Report the hottest sensor in the annotation
+        let hottest = self
+            .sensors
+            .iter()
+            .filter(|s| s.temp.is_some())
+            .max_by(|a, b| a.temp.unwrap_or(0.0).total_cmp(&b.temp.unwrap_or(0.0)));
+        let annotation = match hottest {
+            Some(sensor) => {
+                let mut text = format!("{:.0}°C", sensor.temp.unwrap_or(0.0));
+                if self.sensors.len() > 1 {
+                    text.push_str(&format!("\n{}", sensor.label));
+                }
+                if let Some(crit) = min_crit {
+                    text.push_str(&format!("\ncrit {:.0}°C", crit));
+                }
+                text
+            }
+            None => "Not Present".to_string(),
+        };
+        Chart::draw_annotation(&self.da, cr, width, height, &annotation);
+    }
+}
+
+impl Widget for TemperatureWidget {
+    fn update(&mut self) {
+        let s = self.stats.borrow();
+        let new_stats = s.get_new();
+
+        for sensor in &mut self.sensors {
+            sensor.temp = None;
+        }
+
+        for sensor in &new_stats.sensors {
+            if !self.is_selected(&sensor.chip, &sensor.label) {
+                continue;
+            }
+
+            let idx = Self::find_sensor(
+                &mut self.sensors,
+                &self.time_axis,
+                &mut self.restored_history,
+                &sensor.chip,
+                &sensor.label,
+            );
+            let history = &mut self.sensors[idx];
+            history.temp = Some(sensor.temp);
+            history.crit = sensor.crit;
+        }
+
+        let mut any_present = false;
+        for sensor in &mut self.sensors {
+            any_present |= sensor.temp.is_some();
+            sensor.chart.add_values(&[sensor.temp.unwrap_or(0.0)]);
+        }
+        if !any_present && self.sensors_present {
+            warn!(
+                "no temperature sensors found matching widget {:?}",
+                self.config.label
+            );
+        }
+        self.sensors_present = any_present;
+
+        // Mark that the drawing area needs to be redrawn
+        self.da.queue_draw();
+    }
+
+    fn set_time_axis(&mut self, time_axis: &TimeAxis) {
+        self.time_axis = time_axis.clone();
+        for sensor in &mut self.sensors {
+            sensor.chart.set_time_axis(time_axis);
+        }
+    }
+
+    fn save_history(&self) -> WidgetHistory {
+        let mut history = WidgetHistory::new();
+        for (idx, sensor) in self.sensors.iter().enumerate() {
+            let key = Self::history_key(&self.sensors[..idx], &sensor.chip, &sensor.label);
+            history.insert(key, sensor.chart.save_history());
+        }
+        history
+    }
+
+    fn restore_history(&mut self, history: WidgetHistory) {
+        self.restored_history = history;
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stats.borrow().is_stale()
+    }
+
+    fn gtk_widget(&self) -> &gtk::Box {
+        &self.container
+    }
+}
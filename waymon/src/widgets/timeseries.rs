@@ -1,8 +1,14 @@
+use crate::history::{ChartHistory, ChartLevelHistory, ChartPoint, WidgetHistory};
+use crate::util::unix_time;
 use gtk::cairo;
 use gtk::prelude::*;
+use serde::{Deserialize, Deserializer};
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::iter::Sum;
+use std::marker::PhantomData;
 use std::rc::{Rc, Weak};
+use std::time::{Duration, SystemTime};
 
 pub struct Chart {}
 
@@ -71,6 +77,7 @@ impl Chart {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Color {
     pub r: f64,
     pub g: f64,
@@ -81,6 +88,30 @@ impl Color {
     pub fn new(r: f64, g: f64, b: f64) -> Color {
         Color { r, g, b }
     }
+
+    /// Parse a color from a "#rrggbb" string
+    pub fn parse(s: &str) -> Result<Color, String> {
+        let invalid = || format!("invalid color {:?}: expected a \"#rrggbb\" string", s);
+        let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let component = |idx: usize| -> Result<f64, String> {
+            let value = u8::from_str_radix(&hex[idx..idx + 2], 16).map_err(|_| invalid())?;
+            Ok((value as f64) / 255.0)
+        };
+        Ok(Color::new(component(0)?, component(2)?, component(4)?))
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Color::parse(&s).map_err(serde::de::Error::custom)
+    }
 }
 
 // We unfortunately can't use the standard Into() trait, since it isn't implemented for i64 and u64
@@ -103,14 +134,235 @@ impl IntoF64Lossy for u64 {
     }
 }
 
+// The reverse of IntoF64Lossy, used when restoring saved chart history.
+pub trait FromF64Lossy {
+    fn from_f64_lossy(v: f64) -> Self;
+}
+impl FromF64Lossy for f64 {
+    fn from_f64_lossy(v: f64) -> Self {
+        v
+    }
+}
+impl FromF64Lossy for u64 {
+    fn from_f64_lossy(v: f64) -> Self {
+        v as u64
+    }
+}
+
+/// How the x axis of a chart maps to time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeAxis {
+    // The width of the chart, in pixels
+    pub width: usize,
+    // How often data points are added to the chart
+    pub interval: Duration,
+    // The time spans that the chart can show.  Data is kept for each of them, so that the chart
+    // can switch between them without losing its history.
+    pub windows: Vec<Duration>,
+    // The index in `windows` of the window currently being shown
+    pub selected: usize,
+    // If set, draw a vertical gridline at each multiple of this duration
+    pub gridlines: Option<Duration>,
+}
+
+impl TimeAxis {
+    /// Create a time axis that shows one data point per pixel.
+    pub fn new(width: usize, interval: Duration) -> Self {
+        Self {
+            width,
+            interval,
+            windows: vec![interval * (width as u32)],
+            selected: 0,
+            gridlines: None,
+        }
+    }
+
+    /// Switch to the next window in the list, or to the previous one if `forward` is false.
+    ///
+    /// Returns false if there is no window to switch to.
+    pub fn select_next(&mut self, forward: bool) -> bool {
+        let selected = if forward {
+            self.selected + 1
+        } else {
+            match self.selected.checked_sub(1) {
+                Some(selected) => selected,
+                None => return false,
+            }
+        };
+        if selected >= self.windows.len() {
+            return false;
+        }
+        self.selected = selected;
+        true
+    }
+
+    // The amount of time covered by each pixel when showing `window`, in seconds.
+    //
+    // Each pixel covers at least one update interval, so windows shorter than the chart width
+    // times the interval are widened.
+    fn pixel_secs(&self, window: Duration) -> f64 {
+        let secs = window.as_secs_f64() / (std::cmp::max(self.width, 1) as f64);
+        secs.max(self.interval.as_secs_f64())
+    }
+}
+
+// The combined data points for one pixel of a chart
+#[derive(Clone, Copy)]
+struct Bucket<const NUM_SERIES: usize> {
+    // The wall clock time of the first data point, in seconds since the Unix epoch.  We use wall
+    // clock time rather than Instant since the monotonic clock does not advance while the system
+    // is suspended, and since the times need to remain meaningful when history is restored after
+    // a restart.
+    time: f64,
+    // The number of data points combined into this bucket
+    count: u32,
+    // The sum of each series over all of the data points
+    sums: [f64; NUM_SERIES],
+    // The smallest and largest total value of any one data point
+    min_total: f64,
+    max_total: f64,
+}
+
+impl<const NUM_SERIES: usize> Bucket<NUM_SERIES> {
+    fn new(time: f64, values: [f64; NUM_SERIES]) -> Self {
+        let total = values.iter().sum();
+        Self {
+            time,
+            count: 1,
+            sums: values,
+            min_total: total,
+            max_total: total,
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.count += other.count;
+        for (sum, other_sum) in self.sums.iter_mut().zip(&other.sums) {
+            *sum += other_sum;
+        }
+        self.min_total = self.min_total.min(other.min_total);
+        self.max_total = self.max_total.max(other.max_total);
+    }
+
+    fn avg(&self, series_idx: usize) -> f64 {
+        self.sums[series_idx] / (self.count as f64)
+    }
+
+    fn avg_total(&self) -> f64 {
+        self.sums.iter().sum::<f64>() / (self.count as f64)
+    }
+}
+
+// The data shown for one of the chart's time windows
+struct Level<const NUM_SERIES: usize> {
+    // The amount of time covered by each bucket, in seconds
+    pixel_secs: f64,
+    // The buckets, from oldest to newest.  Only buckets that fall within the window are kept.
+    buckets: VecDeque<Bucket<NUM_SERIES>>,
+}
+
+impl<const NUM_SERIES: usize> Level<NUM_SERIES> {
+    fn new(pixel_secs: f64) -> Self {
+        Self {
+            pixel_secs,
+            buckets: VecDeque::new(),
+        }
+    }
+
+    /// Create a level with a copy of the data from whichever of `sources` is the best match.
+    ///
+    /// This prefers the level with the closest resolution that is not coarser than the new one,
+    /// since its buckets can be combined without losing any information.
+    fn rebuild_from(pixel_secs: f64, sources: &[Self], axis: &TimeAxis) -> Self {
+        let source = sources
+            .iter()
+            .filter(|l| l.pixel_secs <= pixel_secs)
+            .max_by(|a, b| a.pixel_secs.total_cmp(&b.pixel_secs))
+            .or_else(|| {
+                sources
+                    .iter()
+                    .min_by(|a, b| a.pixel_secs.total_cmp(&b.pixel_secs))
+            });
+        let mut level = Self::new(pixel_secs);
+        for bucket in source.iter().flat_map(|l| &l.buckets) {
+            level.add(bucket, axis);
+        }
+        level
+    }
+
+    fn add(&mut self, bucket: &Bucket<NUM_SERIES>, axis: &TimeAxis) {
+        // If the clock has gone backwards, discard any data points that now appear to be in the
+        // future.
+        if self.buckets.back().is_some_and(|b| b.time > bucket.time) {
+            self.buckets.retain(|b| b.time <= bucket.time);
+        }
+
+        // Data points are combined into the newest bucket until it covers a full pixel.  Allow
+        // some slack, so that small variations in when updates happen do not leave empty pixels.
+        let slack = axis.interval.as_secs_f64() / 2.0;
+        match self.buckets.back_mut() {
+            Some(last) if bucket.time - last.time < self.pixel_secs - slack => last.merge(bucket),
+            _ => self.buckets.push_back(*bucket),
+        }
+        self.expire_old_buckets(axis.width);
+    }
+
+    // Drop buckets that are too old to be shown, or that no longer fit in the chart.
+    fn expire_old_buckets(&mut self, width: usize) {
+        let Some(newest) = self.buckets.back().map(|b| b.time) else {
+            return;
+        };
+        let oldest_allowed = newest - (width as f64) * self.pixel_secs;
+        while let Some(oldest) = self.buckets.front() {
+            if oldest.time > oldest_allowed && self.buckets.len() <= width {
+                break;
+            }
+            self.buckets.pop_front();
+        }
+    }
+
+    fn max_total(&self) -> f64 {
+        self.buckets.iter().map(|b| b.max_total).fold(0.0, f64::max)
+    }
+
+    // The number of whole pixels between a bucket at `time` and one at `newest`
+    fn pixels_before(&self, newest: f64, time: f64) -> f64 {
+        ((newest - time) / self.pixel_secs).round()
+    }
+
+    // Iterate over the buckets from newest to oldest, along with the x position of each one.
+    //
+    // The newest bucket is drawn at the right edge of the chart, and the others are placed
+    // according to how much older they are, so any time when we were not collecting data shows up
+    // as a gap.
+    fn iter_newest_first(
+        &self,
+        width: i32,
+        x_offset: f64,
+    ) -> impl Iterator<Item = (f64, &Bucket<NUM_SERIES>)> {
+        let newest = self.buckets.back().map_or(0.0, |b| b.time);
+        self.buckets
+            .iter()
+            .rev()
+            .map(move |b| {
+                let x = (width as f64) - x_offset - self.pixels_before(newest, b.time);
+                (x, b)
+            })
+            .take_while(|(x, _)| *x >= 0.0)
+    }
+}
+
 pub struct StackedTimeseriesChart<T, const NUM_SERIES: usize>
 where
-    T: Copy + Default + PartialOrd + Sum + IntoF64Lossy,
+    T: Copy + Default + PartialOrd + Sum + IntoF64Lossy + FromF64Lossy,
 {
-    data: Vec<[T; NUM_SERIES]>,
+    axis: TimeAxis,
+    // The data for each of the axis windows
+    levels: Vec<Level<NUM_SERIES>>,
     colors: [Color; NUM_SERIES],
-    next_index: usize,
-    max_value: T,
+    // The largest total value in the window being shown
+    max_value: f64,
+    _value_type: PhantomData<T>,
 }
 
 fn get_default_color(idx: usize, num_colors: usize) -> Color {
@@ -120,92 +372,260 @@ fn get_default_color(idx: usize, num_colors: usize) -> Color {
 
 impl<T, const NUM_SERIES: usize> StackedTimeseriesChart<T, NUM_SERIES>
 where
-    T: Copy + Default + PartialOrd + Sum + IntoF64Lossy,
+    T: Copy + Default + PartialOrd + Sum + IntoF64Lossy + FromF64Lossy,
 {
-    pub fn new(ts_size: usize) -> StackedTimeseriesChart<T, NUM_SERIES> {
-        let mut chart = StackedTimeseriesChart::<T, NUM_SERIES> {
-            data: Vec::with_capacity(ts_size),
+    pub fn new(axis: &TimeAxis) -> StackedTimeseriesChart<T, NUM_SERIES> {
+        StackedTimeseriesChart::<T, NUM_SERIES> {
+            axis: axis.clone(),
+            levels: axis
+                .windows
+                .iter()
+                .map(|window| Level::new(axis.pixel_secs(*window)))
+                .collect(),
             colors: core::array::from_fn(|idx| get_default_color(idx, NUM_SERIES)),
-            next_index: 0,
-            max_value: Default::default(),
-        };
-        chart
-            .data
-            .resize(ts_size, core::array::from_fn(|_| Default::default()));
-        chart
+            max_value: 0.0,
+            _value_type: PhantomData,
+        }
     }
 
-    // Returns the maximum total value stored in the timeseries.
+    // Returns the maximum total value shown in the chart.
+    //
+    // When each pixel covers several data points, this is the largest single data point, rather
+    // than the largest average.
     pub fn max_value(&self) -> T {
-        self.max_value
+        T::from_f64_lossy(self.max_value)
     }
 
+    /// Add a data point for the current time.
     pub fn add_values(&mut self, v: &[T; NUM_SERIES]) {
+        self.add_values_at(SystemTime::now(), v);
+    }
+
+    /// Add a data point for the specified time.
+    pub fn add_values_at(&mut self, time: SystemTime, v: &[T; NUM_SERIES]) {
         // Our chart can only show positive values.  Filter out any negative numbers.
-        let x = v.map(|n| {
+        let values = v.map(|n| {
             if n >= Default::default() {
-                n
+                n.into_f64_lossy()
             } else {
-                Default::default()
+                0.0
             }
         });
+        let bucket = Bucket::new(unix_time(time), values);
+        for level in &mut self.levels {
+            level.add(&bucket, &self.axis);
+        }
+        self.update_max();
+    }
 
-        // Update our stored maximum value, if these new data points are a new maximum,
-        // or if the value we are expiring was the old maximum.
-        let total: T = x.into_iter().sum();
-        if total >= self.max_value {
-            self.max_value = total;
-            self.data[self.next_index] = x;
-        } else {
-            let expired_total: T = self.data[self.next_index].into_iter().sum();
-            self.data[self.next_index] = x;
-            if expired_total >= self.max_value {
-                self.max_value = self.compute_max();
+    // The level for the window currently being shown
+    fn shown_level(&self) -> Option<&Level<NUM_SERIES>> {
+        self.levels.get(self.axis.selected)
+    }
+
+    fn update_max(&mut self) {
+        self.max_value = self.shown_level().map_or(0.0, |l| l.max_total());
+    }
+
+    /// Return the data points, from oldest to newest, in a form that can be saved to disk.
+    pub fn save_history(&self) -> ChartHistory {
+        let levels = self
+            .levels
+            .iter()
+            .map(|level| ChartLevelHistory {
+                pixel_secs: level.pixel_secs,
+                points: level
+                    .buckets
+                    .iter()
+                    .map(|b| ChartPoint {
+                        time: b.time,
+                        values: (0..NUM_SERIES).map(|idx| b.avg(idx)).collect(),
+                        min: b.min_total,
+                        max: b.max_total,
+                        count: b.count,
+                    })
+                    .collect(),
+            })
+            .collect();
+        ChartHistory::new(levels)
+    }
+
+    /// Add data points previously returned by save_history().
+    ///
+    /// The saved data for each window is restored from whichever saved window has the closest
+    /// matching resolution.  Saved points with the wrong number of series are ignored.
+    pub fn restore_history(&mut self, history: &ChartHistory) {
+        let saved: Vec<Level<NUM_SERIES>> = history
+            .levels()
+            .iter()
+            .map(|saved_level| {
+                let mut level = Level::new(saved_level.pixel_secs);
+                level.buckets = saved_level
+                    .points
+                    .iter()
+                    .filter(|p| p.values.len() == NUM_SERIES && p.count > 0)
+                    .map(|p| Bucket {
+                        time: p.time,
+                        count: p.count,
+                        sums: core::array::from_fn(|idx| p.values[idx] * (p.count as f64)),
+                        min_total: p.min,
+                        max_total: p.max,
+                    })
+                    .collect();
+                level
+            })
+            .collect();
+
+        for level in &mut self.levels {
+            let mut restored = Level::rebuild_from(level.pixel_secs, &saved, &self.axis);
+            for bucket in &level.buckets {
+                restored.add(bucket, &self.axis);
             }
+            *level = restored;
         }
+        self.update_max();
+    }
 
-        self.next_index += 1;
-        if self.next_index >= self.data.len() {
-            self.next_index = 0;
+    /// Restore the history stored under `key`, if there is any, removing it from `history`.
+    pub fn restore_from(&mut self, history: &mut WidgetHistory, key: &str) {
+        if let Some(chart_history) = history.remove(key) {
+            self.restore_history(&chart_history);
         }
     }
 
-    fn compute_max(&self) -> T {
-        let mut max_value: T = Default::default();
-        for &entry in &self.data {
-            let total: T = entry.into_iter().sum();
-            if total > max_value {
-                max_value = total;
+    /// Draw a vertical line at each multiple of the configured gridline interval.
+    ///
+    /// This is done automatically by draw(), but callers that only use draw_line() or
+    /// draw_heatmap_row() should call this first.
+    pub fn draw_gridlines(&self, cr: &cairo::Context, width: i32, height: i32) {
+        let Some(gridlines) = self.axis.gridlines else {
+            return;
+        };
+        let Some(level) = self.shown_level() else {
+            return;
+        };
+        let Some(newest) = level.buckets.back() else {
+            return;
+        };
+        let interval = gridlines.as_secs_f64();
+        // Skip gridlines that would be too close together to be useful
+        if interval < 2.0 * level.pixel_secs {
+            return;
+        }
+
+        cr.set_source_rgba(0.5, 0.5, 0.5, 0.4);
+        cr.set_line_width(1.0);
+        let mut time = (newest.time / interval).floor() * interval;
+        loop {
+            let x = (width as f64) - 0.5 - level.pixels_before(newest.time, time);
+            if x < 0.0 {
+                break;
             }
+            cr.move_to(x, 0.0);
+            cr.line_to(x, height as f64);
+            time -= interval;
         }
-        max_value
+        let _ = cr.stroke();
     }
 
-    pub fn draw(&self, cr: &cairo::Context, width: i32, height: i32, y_scale: f64) {
-        let x_scale: f64 = 1.0;
+    /// Draw the timeseries as a single heatmap row, rather than as a stacked chart.
+    ///
+    /// Each pixel is drawn as a 1-pixel wide column, shaded according to the average total value
+    /// of its data points relative to max_value.
+    pub fn draw_heatmap_row(
+        &self,
+        cr: &cairo::Context,
+        width: i32,
+        y: f64,
+        row_height: f64,
+        max_value: f64,
+    ) {
+        let Some(level) = self.shown_level() else {
+            return;
+        };
+        let c = &self.colors[0];
+        for (x, bucket) in level.iter_newest_first(width, 1.0) {
+            let intensity = (bucket.avg_total() / max_value).clamp(0.0, 1.0);
+            cr.set_source_rgba(c.r, c.g, c.b, intensity);
+            cr.rectangle(x, y, 1.0, row_height);
+            let _ = cr.fill();
+        }
+    }
+
+    /// Draw the total value of each data point as a line, rather than as a stacked chart.
+    ///
+    /// The line is drawn using the color of the first series.  It is broken wherever there is
+    /// missing data.  When each pixel covers several data points, the line shows their average,
+    /// and the range between the smallest and largest of them is shaded.
+    pub fn draw_line(&self, cr: &cairo::Context, width: i32, height: i32, y_scale: f64) {
+        let Some(level) = self.shown_level() else {
+            return;
+        };
+        let c = &self.colors[0];
+        let to_y = |value: f64| (height as f64) - value * y_scale;
 
         cr.set_line_width(1.0);
-        let mut idx = self.next_index;
-        let mut x: f64 = (width as f64) - (0.5 * x_scale);
-        loop {
-            if idx == 0 {
-                idx = self.data.len() - 1;
-            } else {
-                idx -= 1;
+        cr.set_source_rgba(c.r, c.g, c.b, 0.35);
+        for (x, bucket) in level.iter_newest_first(width, 0.5) {
+            if bucket.max_total > bucket.min_total {
+                cr.move_to(x, to_y(bucket.min_total));
+                cr.line_to(x, to_y(bucket.max_total));
             }
-            if idx == self.next_index {
-                break;
+        }
+        let _ = cr.stroke();
+
+        cr.set_source_rgb(c.r, c.g, c.b);
+        cr.set_line_width(1.5);
+        let mut prev_x: Option<f64> = None;
+        for (x, bucket) in level.iter_newest_first(width, 0.5) {
+            let y = to_y(bucket.avg_total());
+            match prev_x {
+                // Only connect points in adjacent pixels, so that gaps in the data are visible
+                Some(prev_x) if prev_x - x <= 1.0 => cr.line_to(x, y),
+                _ => cr.move_to(x, y),
             }
+            prev_x = Some(x);
+        }
+        let _ = cr.stroke();
+    }
 
-            let entry = &self.data[idx];
+    /// Change the time axis of the chart.
+    ///
+    /// The most recent data points are preserved, as long as they still fit in the chart.
+    pub fn set_time_axis(&mut self, axis: &TimeAxis) {
+        let same_levels = axis.width == self.axis.width
+            && axis.interval == self.axis.interval
+            && axis.windows == self.axis.windows;
+        self.axis = axis.clone();
+        if !same_levels {
+            let old_levels = std::mem::take(&mut self.levels);
+            self.levels = axis
+                .windows
+                .iter()
+                .map(|window| Level::rebuild_from(axis.pixel_secs(*window), &old_levels, axis))
+                .collect();
+        }
+        self.update_max();
+    }
+
+    /// Set the color used to draw the specified series
+    pub fn set_color(&mut self, series_idx: usize, color: Color) {
+        self.colors[series_idx] = color;
+    }
+
+    pub fn draw(&self, cr: &cairo::Context, width: i32, height: i32, y_scale: f64) {
+        self.draw_gridlines(cr, width, height);
+        let Some(level) = self.shown_level() else {
+            return;
+        };
+        cr.set_line_width(1.0);
+        for (x, bucket) in level.iter_newest_first(width, 0.5) {
             let mut cur_height = height as f64;
             for ts_idx in 0..NUM_SERIES {
-                let value = entry[ts_idx];
                 let c = &self.colors[ts_idx];
                 cr.move_to(x, cur_height);
                 cr.set_source_rgb(c.r, c.g, c.b);
-                let value_f64: f64 = value.into_f64_lossy();
-                let y = value_f64 * y_scale;
+                let y = bucket.avg(ts_idx) * y_scale;
                 cur_height -= y;
                 cr.line_to(x, cur_height);
                 let _ = cr.stroke();
@@ -214,10 +634,123 @@ where
                 }
             }
 
-            x -= x_scale;
-            if x < 0.0 {
-                break;
+            // When each pixel covers several data points, show how far above the average the
+            // largest of them was, so that short peaks are still visible.
+            let peak_height = (height as f64) - bucket.max_total * y_scale;
+            if peak_height < cur_height {
+                let c = &self.colors[NUM_SERIES - 1];
+                cr.set_source_rgba(c.r, c.g, c.b, 0.35);
+                cr.move_to(x, cur_height);
+                cr.line_to(x, peak_height.max(0.0));
+                let _ = cr.stroke();
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(Color::parse("#000000"), Ok(Color::new(0.0, 0.0, 0.0)));
+        assert_eq!(Color::parse("#ff0000"), Ok(Color::new(1.0, 0.0, 0.0)));
+        assert_eq!(Color::parse("#00FF00"), Ok(Color::new(0.0, 1.0, 0.0)));
+        assert_eq!(Color::parse("#000033"), Ok(Color::new(0.0, 0.0, 0.2)));
+
+        assert!(Color::parse("ff0000").is_err());
+        assert!(Color::parse("#ff00").is_err());
+        assert!(Color::parse("#gg0000").is_err());
+    }
+
+    #[test]
+    fn test_time_axis() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let axis = TimeAxis::new(10, Duration::from_secs(1));
+        let mut chart = StackedTimeseriesChart::<f64, 1>::new(&axis);
+        for (secs, value) in [(0, 5.0), (1, 2.0), (4, 3.0), (5, 1.0)] {
+            chart.add_values_at(at(secs), &[value]);
+        }
+        let positions = |chart: &StackedTimeseriesChart<f64, 1>| -> Vec<(f64, f64)> {
+            chart
+                .shown_level()
+                .unwrap()
+                .iter_newest_first(10, 0.0)
+                .map(|(x, bucket)| (x, bucket.avg(0)))
+                .collect()
+        };
+        // Points are placed by time, leaving a gap where samples are missing
+        assert_eq!(
+            positions(&chart),
+            vec![(10.0, 1.0), (9.0, 3.0), (6.0, 2.0), (5.0, 5.0)]
+        );
+        assert_eq!(chart.max_value(), 5.0);
+
+        // Points older than the chart's time span are dropped
+        chart.add_values_at(at(10), &[4.0]);
+        assert_eq!(
+            positions(&chart),
+            vec![(10.0, 4.0), (5.0, 1.0), (4.0, 3.0), (1.0, 2.0)]
+        );
+        assert_eq!(chart.max_value(), 4.0);
+
+        // If the clock goes backwards, points that appear to be in the future are dropped
+        chart.add_values_at(at(3), &[6.0]);
+        assert_eq!(positions(&chart), vec![(10.0, 6.0), (8.0, 2.0)]);
+        assert_eq!(chart.max_value(), 6.0);
+    }
+
+    #[test]
+    fn test_windows() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut axis = TimeAxis {
+            windows: vec![Duration::from_secs(4), Duration::from_secs(8)],
+            ..TimeAxis::new(4, Duration::from_secs(1))
+        };
+        let mut chart = StackedTimeseriesChart::<u64, 1>::new(&axis);
+        for (secs, value) in [1, 5, 1, 1, 2, 2, 9, 1].into_iter().enumerate() {
+            chart.add_values_at(start + Duration::from_secs(secs as u64), &[value]);
+        }
+        let buckets = |chart: &StackedTimeseriesChart<u64, 1>| -> Vec<(f64, f64, f64)> {
+            chart
+                .shown_level()
+                .unwrap()
+                .iter_newest_first(4, 0.0)
+                .map(|(x, bucket)| (x, bucket.avg(0), bucket.max_total))
+                .collect()
+        };
+        assert_eq!(
+            buckets(&chart),
+            vec![
+                (4.0, 1.0, 1.0),
+                (3.0, 9.0, 9.0),
+                (2.0, 2.0, 2.0),
+                (1.0, 2.0, 2.0)
+            ]
+        );
+
+        // The longer window combines two samples into each pixel, but still keeps the peaks
+        assert!(!axis.select_next(false));
+        assert!(axis.select_next(true));
+        assert!(!axis.select_next(true));
+        chart.set_time_axis(&axis);
+        assert_eq!(
+            buckets(&chart),
+            vec![
+                (4.0, 5.0, 9.0),
+                (3.0, 2.0, 2.0),
+                (2.0, 1.0, 1.0),
+                (1.0, 3.0, 5.0)
+            ]
+        );
+        assert_eq!(chart.max_value(), 9);
+
+        // Windows shorter than one interval per pixel are widened
+        axis.windows = vec![Duration::from_secs(1)];
+        axis.selected = 0;
+        chart.set_time_axis(&axis);
+        assert_eq!(buckets(&chart).len(), 4);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::collectors::processes::{ProcessStats, ProcessUsage};
+use crate::history::WidgetHistory;
+use crate::stats::{AllStats, StatsDelta};
+use crate::util::humanify_f64;
+use crate::waymon::Waymon;
+use crate::widgets::timeseries::{Chart, ChartDrawCallback, TimeAxis};
+use crate::widgets::Widget;
+use gtk::cairo;
+use gtk::prelude::*;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// The height of each process's row
+const ROW_HEIGHT: u32 = 15;
+
+/// Which resource to rank processes by
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TopSort {
+    /// Sort by CPU usage since the previous update
+    #[default]
+    Cpu,
+    /// Sort by resident memory
+    Memory,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct TopWidgetConfig {
+    pub label: String,
+
+    // The number of processes to show
+    #[serde(default = "default_count")]
+    pub count: usize,
+    #[serde(default)]
+    pub sort: TopSort,
+}
+
+fn default_count() -> usize {
+    5
+}
+
+pub struct TopWidget {
+    stats: Rc<RefCell<StatsDelta<ProcessStats>>>,
+    container: gtk::Box,
+    da: gtk::DrawingArea,
+    count: usize,
+    sort: TopSort,
+    // The processes currently shown, heaviest first
+    rows: Vec<ProcessUsage>,
+}
+
+impl TopWidgetConfig {
+    pub fn create_widget(
+        &self,
+        all_stats: &mut AllStats,
+        _time_axis: &TimeAxis,
+    ) -> Rc<RefCell<TopWidget>> {
+        let widget_rc = Rc::new(RefCell::new(TopWidget {
+            stats: all_stats.get::<ProcessStats>(()),
+            container: gtk::Box::new(gtk::Orientation::Vertical, /*spacing*/ 0),
+            da: gtk::DrawingArea::new(),
+            count: self.count,
+            sort: self.sort,
+            rows: Vec::new(),
+        }));
+        {
+            let widget = widget_rc.borrow();
+            Waymon::add_widget_label(&widget.container, &self.label);
+            Chart::configure(
+                &widget.da,
+                self.count as u32 * ROW_HEIGHT,
+                widget_rc.clone(),
+            );
+            widget.container.append(&widget.da);
+        }
+        widget_rc
+    }
+}
+
+impl TopWidget {
+    fn value(&self, usage: &ProcessUsage) -> f64 {
+        match self.sort {
+            TopSort::Cpu => usage.cpu_percent,
+            TopSort::Memory => usage.rss_bytes as f64,
+        }
+    }
+
+    fn value_text(&self, usage: &ProcessUsage) -> String {
+        match self.sort {
+            TopSort::Cpu => format!("{:.0}%", usage.cpu_percent),
+            TopSort::Memory => humanify_f64(usage.rss_bytes as f64, 2),
+        }
+    }
+
+    /// The full details for each process, since the bar is too narrow to show them all.
+    fn tooltip(&self) -> String {
+        self.rows
+            .iter()
+            .map(|usage| {
+                let sign = if usage.rss_delta < 0 { "-" } else { "+" };
+                format!(
+                    "{} {}: {:.1}% CPU, {} ({}{})",
+                    usage.pid,
+                    usage.comm,
+                    usage.cpu_percent,
+                    humanify_f64(usage.rss_bytes as f64, 3),
+                    sign,
+                    humanify_f64(usage.rss_delta.unsigned_abs() as f64, 2),
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+impl ChartDrawCallback for TopWidget {
+    fn draw(&self, cr: &cairo::Context, width: i32, _height: i32) {
+        // CPU usage is scaled to at least one full CPU, so that a mostly idle system does not
+        // look busy.  Memory is scaled relative to the largest process.
+        let max_value = self.rows.first().map_or(0.0, |usage| self.value(usage));
+        let max_value = match self.sort {
+            TopSort::Cpu => max_value.max(100.0),
+            TopSort::Memory => max_value,
+        };
+
+        let font_desc = gtk::pango::FontDescription::from_string("Sans 9");
+        for (row, usage) in self.rows.iter().enumerate() {
+            let y = (row as u32 * ROW_HEIGHT) as f64;
+            if max_value > 0.0 {
+                let bar_width = (width as f64) * self.value(usage) / max_value;
+                cr.set_source_rgba(0.0, 0.0, 1.0, 0.3);
+                cr.rectangle(0.0, y, bar_width, (ROW_HEIGHT - 1) as f64);
+                let _ = cr.fill();
+            }
+
+            // Show the value on the right, and truncate the command name to fit beside it
+            let value_layout = self.da.create_pango_layout(Some(&self.value_text(usage)));
+            value_layout.set_font_description(Some(&font_desc));
+            let (value_width, _) = value_layout.pixel_size();
+            let name_layout = self.da.create_pango_layout(Some(&usage.comm));
+            name_layout.set_font_description(Some(&font_desc));
+            name_layout.set_ellipsize(gtk::pango::EllipsizeMode::End);
+            let name_width = std::cmp::max(width - value_width - 6, 0);
+            name_layout.set_width(name_width * gtk::pango::SCALE);
+
+            cr.set_source_rgb(0.4, 0.4, 0.4);
+            cr.move_to(2.0, y);
+            pangocairo::functions::show_layout(cr, &name_layout);
+            cr.move_to((width - value_width - 2) as f64, y);
+            pangocairo::functions::show_layout(cr, &value_layout);
+        }
+    }
+}
+
+impl Widget for TopWidget {
+    fn update(&mut self) {
+        let mut rows = {
+            let s = self.stats.borrow();
+            let (new, old) = s.get_new_and_old();
+            new.usage_since(old, s.time_delta())
+        };
+        // Break ties by pid, so processes with equal usage don't swap places between updates
+        rows.sort_by(|a, b| {
+            self.value(b)
+                .total_cmp(&self.value(a))
+                .then(a.pid.cmp(&b.pid))
+        });
+        rows.truncate(self.count);
+        self.rows = rows;
+
+        self.da.set_tooltip_text(Some(&self.tooltip()));
+        // Mark that the drawing area needs to be redrawn
+        self.da.queue_draw();
+    }
+
+    fn set_time_axis(&mut self, _time_axis: &TimeAxis) {
+        // This widget only shows the latest sample, so there is no time axis to update
+    }
+
+    fn save_history(&self) -> WidgetHistory {
+        WidgetHistory::new()
+    }
+
+    fn restore_history(&mut self, _history: WidgetHistory) {}
+
+    fn is_stale(&self) -> bool {
+        self.stats.borrow().is_stale()
+    }
+
+    fn gtk_widget(&self) -> &gtk::Box {
+        &self.container
+    }
+}
@@ -0,0 +1,212 @@
+use crate::collectors::vmstat::VmStats;
+use crate::history::WidgetHistory;
+use crate::stats::{counter_delta, AllStats, StatsDelta};
+use crate::util::humanify_f64;
+use crate::waymon::Waymon;
+use crate::widgets::timeseries::{
+    Chart, ChartDrawCallback, Color, StackedTimeseriesChart, TimeAxis,
+};
+use crate::widgets::Widget;
+use gtk::cairo;
+use gtk::prelude::*;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// The rows of the widget, from top to bottom, and the keys used to save their history
+const ROW_SWAP: usize = 0;
+const ROW_MAJOR_FAULTS: usize = 1;
+const ROW_RECLAIM: usize = 2;
+const ROW_COMPACTION: usize = 3;
+const ROW_OOM_KILLS: usize = 4;
+const NUM_ROWS: usize = 5;
+const ROW_KEYS: [&str; NUM_ROWS] = ["swap", "major_faults", "reclaim", "compaction", "oom_kills"];
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct VmstatWidgetConfig {
+    pub label: String,
+
+    // The height of each of the swap, major fault, reclaim, compaction stall, and OOM kill charts
+    #[serde(default = "default_vmstat_chart_height")]
+    pub chart_height: u32,
+}
+
+fn default_vmstat_chart_height() -> u32 {
+    30
+}
+
+pub struct VmstatWidget {
+    stats: Rc<RefCell<StatsDelta<VmStats>>>,
+    container: gtk::Box,
+    da: gtk::DrawingArea,
+    chart_height: u32,
+    // Each chart has up to two series.  Rows that only chart one value leave the second at 0.
+    charts: [StackedTimeseriesChart<f64, 2>; NUM_ROWS],
+    // The latest per-second rates for each chart.  The swap and reclaim rates are in bytes, and
+    // the others are event counts.
+    rates: [[f64; 2]; NUM_ROWS],
+    // The number of OOM kills since boot
+    oom_kills: u64,
+}
+
+impl VmstatWidgetConfig {
+    pub fn create_widget(
+        &self,
+        all_stats: &mut AllStats,
+        time_axis: &TimeAxis,
+    ) -> Rc<RefCell<VmstatWidget>> {
+        let charts = std::array::from_fn(|_| {
+            let mut chart = StackedTimeseriesChart::new(time_axis);
+            chart.set_color(0, Color::new(0.0, 0.0, 1.0));
+            chart.set_color(1, Color::new(0.85, 0.0, 0.0));
+            chart
+        });
+        let widget_rc = Rc::new(RefCell::new(VmstatWidget {
+            stats: all_stats.get::<VmStats>(()),
+            container: gtk::Box::new(gtk::Orientation::Vertical, /*spacing*/ 0),
+            da: gtk::DrawingArea::new(),
+            chart_height: self.chart_height,
+            charts,
+            rates: Default::default(),
+            oom_kills: 0,
+        }));
+        {
+            let widget = widget_rc.borrow();
+            Waymon::add_widget_label(&widget.container, &self.label);
+            Chart::configure(
+                &widget.da,
+                NUM_ROWS as u32 * self.chart_height,
+                widget_rc.clone(),
+            );
+            widget.container.append(&widget.da);
+        }
+        widget_rc
+    }
+}
+
+fn y_scale_for(max_value: f64, height: i32) -> f64 {
+    if max_value <= 0.0 {
+        1.0
+    } else {
+        ((height - 2) as f64) / max_value
+    }
+}
+
+/// Format a rate of events per second.
+fn format_count(value: f64) -> String {
+    if value < 10.0 {
+        format!("{:.1}", value)
+    } else if value < 1000.0 {
+        format!("{:.0}", value)
+    } else if value < 1_000_000.0 {
+        format!("{:.1}k", value / 1000.0)
+    } else {
+        format!("{:.1}M", value / 1_000_000.0)
+    }
+}
+
+impl VmstatWidget {
+    fn annotation(&self, row: usize) -> String {
+        let [first, second] = self.rates[row];
+        match row {
+            ROW_SWAP => format!(
+                "swap in {}/s\nout {}/s",
+                humanify_f64(first, 2),
+                humanify_f64(second, 2)
+            ),
+            ROW_MAJOR_FAULTS => format!("major faults {}/s", format_count(first)),
+            ROW_RECLAIM => format!(
+                "reclaim {}/s\ndirect {}/s",
+                humanify_f64(first + second, 2),
+                humanify_f64(second, 2)
+            ),
+            ROW_COMPACTION => format!("compact stalls {}/s", format_count(first)),
+            _ => format!("OOM kills {}", self.oom_kills),
+        }
+    }
+}
+
+impl ChartDrawCallback for VmstatWidget {
+    fn draw(&self, cr: &cairo::Context, width: i32, _height: i32) {
+        // Leave a 1 pixel gap between each chart
+        let chart_height = self.chart_height.saturating_sub(1) as i32;
+        for (row, chart) in self.charts.iter().enumerate() {
+            let _ = cr.save();
+            cr.translate(0.0, (row as u32 * self.chart_height) as f64);
+            cr.rectangle(0.0, 0.0, width as f64, chart_height as f64);
+            cr.clip();
+            let y_scale = y_scale_for(chart.max_value(), chart_height);
+            chart.draw(cr, width, chart_height, y_scale);
+            Chart::draw_annotation(&self.da, cr, width, chart_height, &self.annotation(row));
+            let _ = cr.restore();
+        }
+    }
+}
+
+impl Widget for VmstatWidget {
+    fn update(&mut self) {
+        {
+            let s = self.stats.borrow();
+            let (new, old) = s.get_new_and_old();
+            let delta_secs = s.time_delta().as_secs_f64();
+            self.oom_kills = new.oom_kill;
+            // The old sample has no page size if this is the first update
+            if old.page_size == 0 || delta_secs <= 0.0 {
+                self.rates = Default::default();
+            } else {
+                let rate =
+                    |new: u64, old: u64| -> f64 { counter_delta(new, old) as f64 / delta_secs };
+                let page_size = new.page_size as f64;
+                // Reclaim done by the background kswapd and khugepaged threads is charted
+                // separately from direct reclaim, where allocating tasks had to stall to free
+                // memory themselves.
+                let background_reclaim = |s: &VmStats| s.pgsteal_kswapd + s.pgsteal_khugepaged;
+                self.rates[ROW_SWAP] = [
+                    rate(new.pswpin, old.pswpin) * page_size,
+                    rate(new.pswpout, old.pswpout) * page_size,
+                ];
+                self.rates[ROW_MAJOR_FAULTS] = [rate(new.pgmajfault, old.pgmajfault), 0.0];
+                self.rates[ROW_RECLAIM] = [
+                    rate(background_reclaim(new), background_reclaim(old)) * page_size,
+                    rate(new.pgsteal_direct, old.pgsteal_direct) * page_size,
+                ];
+                self.rates[ROW_COMPACTION] = [rate(new.compact_stall, old.compact_stall), 0.0];
+                self.rates[ROW_OOM_KILLS] = [rate(new.oom_kill, old.oom_kill), 0.0];
+            }
+        }
+        for (chart, rates) in self.charts.iter_mut().zip(&self.rates) {
+            chart.add_values(rates);
+        }
+
+        // Mark that the drawing area needs to be redrawn
+        self.da.queue_draw();
+    }
+
+    fn set_time_axis(&mut self, time_axis: &TimeAxis) {
+        for chart in &mut self.charts {
+            chart.set_time_axis(time_axis);
+        }
+    }
+
+    fn save_history(&self) -> WidgetHistory {
+        ROW_KEYS
+            .iter()
+            .zip(&self.charts)
+            .map(|(key, chart)| (key.to_string(), chart.save_history()))
+            .collect()
+    }
+
+    fn restore_history(&mut self, mut history: WidgetHistory) {
+        for (key, chart) in ROW_KEYS.iter().zip(&mut self.charts) {
+            chart.restore_from(&mut history, key);
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stats.borrow().is_stale()
+    }
+
+    fn gtk_widget(&self) -> &gtk::Box {
+        &self.container
+    }
+}
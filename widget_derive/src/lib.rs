@@ -56,7 +56,7 @@ fn derive_widget_config_enum(name: syn::Ident, data_enum: &syn::DataEnum) -> Tok
         let vname = &v.ident;
         body = quote!(
             #body
-            #name::#vname(cfg) => cfg.create_widget(all_stats, history_length),
+            #name::#vname(cfg) => cfg.create_widget(all_stats, time_axis),
         );
     }
 
@@ -65,7 +65,7 @@ fn derive_widget_config_enum(name: syn::Ident, data_enum: &syn::DataEnum) -> Tok
         fn create_widget(
             &self,
             all_stats: &mut crate::stats::AllStats,
-            history_length: usize,
+            time_axis: &crate::widgets::timeseries::TimeAxis,
         ) -> std::rc::Rc<std::cell::RefCell<dyn crate::widgets::Widget>> {
             match self {
                 #body
@@ -97,9 +97,9 @@ fn derive_widget_config_struct(input: DeriveInput) -> TokenStream {
         fn create_widget(
             &self,
             all_stats: &mut crate::stats::AllStats,
-            history_length: usize,
+            time_axis: &crate::widgets::timeseries::TimeAxis,
         ) -> std::rc::Rc<std::cell::RefCell<dyn crate::widgets::Widget>> {
-            #widget_name::new(self, all_stats, history_length)
+            #widget_name::new(self, all_stats, time_axis)
         }
     }
     ))